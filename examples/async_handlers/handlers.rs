@@ -1,6 +1,7 @@
 use crate::config;
 use crate::messages;
 
+use rust_messenger::rpc;
 use rust_messenger::traits;
 use rust_messenger::traits::extended::Sender;
 use tokio::io::AsyncReadExt;
@@ -175,49 +176,44 @@ impl traits::core::Handle<messages::Request> for AsyncClient {
     }
 }
 
+/// How long a TCP client waits for the bus to answer its request.
+const REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
 pub struct AsyncServer {
-    response_channel: std::sync::Arc<
-        tokio::sync::Mutex<
-            std::collections::HashMap<usize, tokio::sync::oneshot::Sender<messages::Response>>,
-        >,
-    >,
+    /// Requests forwarded to the bus whose responses the connection tasks
+    /// are awaiting.
+    calls: rpc::Client<messages::Response>,
 }
 
 impl AsyncServer {
     pub fn new<W: traits::core::Writer>(config: &config::Config, writer: &W) -> Self {
-        let response_map =
-            std::sync::Arc::new(tokio::sync::Mutex::new(std::collections::HashMap::new()));
+        let calls = rpc::Client::new(REQUEST_TIMEOUT);
 
         config.runtime.spawn(AsyncServer::serve(
             config.addr.clone(),
             writer.clone(),
-            response_map.clone(),
+            calls.clone(),
             config.runtime.clone(),
         ));
 
-        AsyncServer {
-            response_channel: response_map,
-        }
+        AsyncServer { calls }
     }
 }
 
 impl traits::core::Handler for AsyncServer {
     type Id = HandlerId;
     const ID: HandlerId = HandlerId::AsyncServer;
+
+    fn on_loop<W: traits::core::Writer>(&mut self, writer: &W) {
+        self.calls.expire::<Self, _>(writer);
+    }
 }
 
-impl traits::core::Handle<messages::IdWrapper<messages::Response>> for AsyncServer {
-    fn handle<W: traits::core::Writer>(
-        &mut self,
-        message: &messages::IdWrapper<messages::Response>,
-        _writer: &W,
-    ) {
-        println!("received messages::Response at AsyncServer: {message:?}");
-        if let Some(tx) = self.response_channel.blocking_lock().remove(&message.id) {
-            // The connection task may have ended (client gone); nothing to
-            // deliver to in that case.
-            let _ = tx.send(message.val.clone());
-        }
+// Replies routed to the AsyncServer resolve the calls of its connection
+// tasks; a reply to a task that already ended (client gone) is dropped.
+impl rpc::Caller<messages::Response> for AsyncServer {
+    fn client(&self) -> &rpc::Client<messages::Response> {
+        &self.calls
     }
 }
 
@@ -225,11 +221,7 @@ impl AsyncServer {
     async fn serve<W: traits::core::Writer>(
         addr: String,
         writer: W,
-        response_map: std::sync::Arc<
-            tokio::sync::Mutex<
-                std::collections::HashMap<usize, tokio::sync::oneshot::Sender<messages::Response>>,
-            >,
-        >,
+        calls: rpc::Client<messages::Response>,
         runtime: std::sync::Arc<tokio::runtime::Runtime>,
     ) {
         // Setup async TCP server. Report failures instead of panicking: a
@@ -242,8 +234,6 @@ impl AsyncServer {
             }
         };
 
-        let request_id_counter = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
-
         println!("Server started at {addr}");
 
         loop {
@@ -257,24 +247,14 @@ impl AsyncServer {
 
             println!("Accepted new connection at {addr}");
 
-            runtime.spawn(AsyncServer::serve_client(
-                socket,
-                request_id_counter.clone(),
-                writer.clone(),
-                response_map.clone(),
-            ));
+            runtime.spawn(AsyncServer::serve_client(socket, writer.clone(), calls.clone()));
         }
     }
 
     async fn serve_client<W: traits::core::Writer>(
         mut socket: tokio::net::TcpStream,
-        request_counter: std::sync::Arc<std::sync::atomic::AtomicUsize>,
         writer: W,
-        response_map: std::sync::Arc<
-            tokio::sync::Mutex<
-                std::collections::HashMap<usize, tokio::sync::oneshot::Sender<messages::Response>>,
-            >,
-        >,
+        calls: rpc::Client<messages::Response>,
     ) {
         loop {
             let frame = match read_frame(&mut socket).await {
@@ -287,24 +267,21 @@ impl AsyncServer {
             };
             let incoming_request = messages::Request::deserialize_from(&frame);
 
-            let request_id = request_counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-
-            let (tx, rx) = tokio::sync::oneshot::channel();
-            // insert sender for sync handler
-            response_map.lock().await.insert(request_id, tx);
-
             println!("received messages::Request at AsyncServer: {incoming_request:?}");
 
-            // send request to message bus
-            let request = messages::IdWrapper::<messages::Request> {
-                id: request_id,
-                val: incoming_request,
+            // Send the request to the bus and wait for the sync handler's
+            // response; the call fails if it times out or the messenger
+            // stops before responding.
+            let response = match calls
+                .request::<AsyncServer, _, _>(&incoming_request, &writer)
+                .await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    eprintln!("AsyncServer: request failed: {e}");
+                    return;
+                }
             };
-            Self::send(&request, &writer);
-
-            // wait for response from sync handler; the sender disappears if
-            // the messenger stops before responding.
-            let Ok(response) = rx.await else { return };
 
             // Serialize response
             let resp_buff =
@@ -340,22 +317,19 @@ impl traits::core::Handler for SyncRequestHandler {
     const ID: Self::Id = HandlerId::SyncResponseHandler;
 }
 
-impl traits::core::Handle<messages::IdWrapper<messages::Request>> for SyncRequestHandler {
+impl traits::core::Handle<rpc::Call<messages::Request>> for SyncRequestHandler {
     fn handle<W: traits::core::Writer>(
         &mut self,
-        message: &messages::IdWrapper<messages::Request>,
+        call: &rpc::Call<messages::Request>,
         writer: &W,
     ) {
-        println!("received messages::Request at SyncRequestHandler: {message:?}");
-
-        let response = messages::IdWrapper::<messages::Response> {
-            id: message.id,
-            val: messages::Response {
-                // Widen before adding: val is a u8 from the network, and
-                // val + 1 would overflow for val == 255.
-                response_val: message.val.val as u16 + 1,
-            },
+        println!("received messages::Request at SyncRequestHandler: {call:?}");
+
+        let response = messages::Response {
+            // Widen before adding: val is a u8 from the network, and
+            // val + 1 would overflow for val == 255.
+            response_val: call.message.val as u16 + 1,
         };
-        Self::send(&response, writer);
+        rpc::reply::<Self, _, _, _>(call, &response, writer);
     }
 }
@@ -9,8 +9,8 @@ mod messages;
 // Client opens TCP connection to server and sends request
 // AsyncClient -> Request(0) -> AsyncServer
 //
-// AsyncServer forwards the request to SyncRequestHandler which responds with a new Response object
-// AsyncServer -> IdWrapper<Request(0)> -> SyncRequestHandler -> IdWrapper<Response(1)> -> AsyncServer
+// AsyncServer calls SyncRequestHandler over the bus, which replies with a new Response object
+// AsyncServer -> rpc::Call<Request(2)> -> SyncRequestHandler -> rpc::Reply<Response(3)> -> AsyncServer
 //
 // Server sends the response back over the TCP connection to the client
 // AsyncServer -> Response(1) -> AsyncClient
@@ -35,8 +35,8 @@ rust_messenger::Messenger! {
             sync_request_handler: handlers::SyncRequestHandler,
         ]
         routes: [
-            handlers::AsyncServer, messages::IdWrapper<messages::Request>: [ sync_request_handler ],
-            handlers::SyncRequestHandler, messages::IdWrapper<messages::Response>: [ async_server ],
+            handlers::AsyncServer, rust_messenger::rpc::Call<messages::Request>: [ sync_request_handler ],
+            handlers::SyncRequestHandler, rust_messenger::rpc::Reply<messages::Response>: [ async_server ],
         ]
 }
 
@@ -60,18 +60,13 @@ pub fn main() {
     // Instead of guessing a sleep duration, watch the bus until the Response
     // makes it all the way back from the TCP round trip (AsyncClient is the
     // last hop before SyncApp).
-    use rust_messenger::traits::core::Reader;
     let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
-    let mut position = 0;
-    'wait: while std::time::Instant::now() < deadline {
-        while let Some((header, _)) = bus.read(position) {
-            position += header.slot_len();
-            if header.source == u16::from(handlers::HandlerId::AsyncClient)
-                && header.message_id == u16::from(messages::MessageId::Response)
-            {
-                break 'wait;
-            }
-        }
+    let mut responses = rust_messenger::cursor::Cursor::new().subscribe(
+        &bus,
+        handlers::HandlerId::AsyncClient,
+        messages::Response::deserialize_from,
+    );
+    while std::time::Instant::now() < deadline && responses.next().is_none() {
         std::thread::sleep(std::time::Duration::from_millis(1));
     }
 
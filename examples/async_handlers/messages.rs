@@ -10,18 +10,13 @@ pub struct Response {
     pub response_val: u16,
 }
 
-#[derive(Debug, serde::Serialize, serde::Deserialize, Clone)]
-pub struct IdWrapper<M> {
-    pub id: usize,
-    pub val: M,
-}
-
 rust_messenger::messenger_id_enum!(
     MessageId {
         Request = 0,
         Response = 1,
-        RequestWithId = 2,
-        ResponseWithId = 3,
+        RequestCall = 2,
+        ResponseReply = 3,
+        ResponseTimeout = 4,
     }
 );
 
@@ -52,10 +47,25 @@ macro_rules! impl_message_traits {
                     .unwrap();
             }
         }
+
+        // Lets `rpc::Call` and `rpc::Reply` decode the message they wrap.
+        impl traits::extended::DeserializeFrom for $type {
+            fn deserialize_from(buffer: &[u8]) -> Self {
+                <$type>::deserialize_from(buffer)
+            }
+        }
     };
 }
 
 impl_message_traits!(Request, MessageId::Request);
 impl_message_traits!(Response, MessageId::Response);
-impl_message_traits!(IdWrapper<Request>, MessageId::RequestWithId);
-impl_message_traits!(IdWrapper<Response>, MessageId::ResponseWithId);
+
+// Envelopes travel under their own ids, apart from the bare messages.
+impl rust_messenger::rpc::CallMessage for Request {
+    const CALL_ID: MessageId = MessageId::RequestCall;
+}
+
+impl rust_messenger::rpc::ReplyMessage for Response {
+    const REPLY_ID: MessageId = MessageId::ResponseReply;
+    const TIMEOUT_ID: MessageId = MessageId::ResponseTimeout;
+}
@@ -0,0 +1,388 @@
+//! A portable file format for recordings, to archive them and to move them
+//! between machines.
+//!
+//! An `ExtendingBus` file, and so a flight recorder dump, stores its headers
+//! in the byte order and layout of the build that wrote it. An archive
+//! instead starts with a magic number and a format version, and stores every
+//! number in little-endian:
+//!
+//! | bytes  | field                                                  |
+//! |--------|--------------------------------------------------------|
+//! | 8      | magic, `rmsgarch`                                      |
+//! | 2      | format version, [`VERSION`]                            |
+//! | 2      | flags: 1 = big-endian payloads, 2 = timestamps         |
+//! | 8      | number of messages                                     |
+//! | 8      | schema id of the bus file                              |
+//! | 4      | schema length, then the schema bytes                   |
+//!
+//! Each message follows as its source (2 bytes), message id (2), payload
+//! size (4), with the timestamps flag its timestamp (8), and the payload.
+//!
+//! Payloads are copied verbatim: zero-copy messages are raw struct bytes, in
+//! the byte order of the machine that recorded them. The archive records
+//! that byte order, and an application-defined schema, e.g. a name and
+//! version of its message definitions, so an importer can tell whether it
+//! can read the payloads:
+//!
+//! ```ignore
+//! archive::export("/var/lib/app/bus.dat", "incident.rmsg", b"orders-v3")?;
+//!
+//! let metadata = archive::metadata("incident.rmsg")?;
+//! assert_eq!(metadata.payload_order, archive::ByteOrder::native());
+//! assert_eq!(metadata.schema, b"orders-v3");
+//! archive::import("incident.rmsg", "incident.bus", 1)?;
+//! ```
+
+use crate::bus_file;
+use crate::inspect;
+
+const MAGIC: [u8; 8] = *b"rmsgarch";
+
+/// The format version written by [`export`]. [`import`] reads it and every
+/// earlier version.
+pub const VERSION: u16 = 1;
+
+/// The longest schema an archive may carry, so that a damaged schema length
+/// cannot make a reader allocate gigabytes.
+pub const MAX_SCHEMA_LEN: usize = 1 << 20;
+
+const BIG_ENDIAN_PAYLOADS: u16 = 1;
+const TIMESTAMPS: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    /// The byte order of this machine.
+    pub fn native() -> ByteOrder {
+        if cfg!(target_endian = "big") {
+            ByteOrder::Big
+        } else {
+            ByteOrder::Little
+        }
+    }
+}
+
+/// What an archive says about its messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub version: u16,
+    /// Byte order of the machine that exported the payloads.
+    pub payload_order: ByteOrder,
+    /// Whether the messages carry the timestamps of their original writes.
+    pub timestamps: bool,
+    pub messages: u64,
+    /// The [schema id](crate::bus_file::FileHeader::schema_id) of the
+    /// exported bus file, given to the imported one.
+    pub schema_id: u64,
+    /// As passed to [`export`]; empty when none was given.
+    pub schema: Vec<u8>,
+}
+
+/// Exports the messages of the `ExtendingBus` file at `bus_file` into a new
+/// archive at `archive`, and returns how many it exported. Tombstones and
+/// holes are left out. `schema` describes the payloads to importers, in at
+/// most [`MAX_SCHEMA_LEN`] bytes; pass `&[]` for none.
+pub fn export(
+    bus_file: impl AsRef<std::path::Path>,
+    archive: impl AsRef<std::path::Path>,
+    schema: &[u8],
+) -> std::io::Result<u64> {
+    use std::io::{Seek, Write};
+
+    if schema.len() > MAX_SCHEMA_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("the schema exceeds {MAX_SCHEMA_LEN} bytes"),
+        ));
+    }
+    let schema_len = schema.len() as u32;
+    let bus = inspect::BusFile::open(bus_file)?;
+    let mut flags = 0;
+    if ByteOrder::native() == ByteOrder::Big {
+        flags |= BIG_ENDIAN_PAYLOADS;
+    }
+    if cfg!(feature = "timestamps") {
+        flags |= TIMESTAMPS;
+    }
+
+    let mut out = std::io::BufWriter::new(std::fs::File::create(archive)?);
+    out.write_all(&MAGIC)?;
+    out.write_all(&VERSION.to_le_bytes())?;
+    out.write_all(&flags.to_le_bytes())?;
+    // The number of messages, filled in once known.
+    let count_offset = out.stream_position()?;
+    out.write_all(&0u64.to_le_bytes())?;
+    out.write_all(&bus.header().schema_id.to_le_bytes())?;
+    out.write_all(&schema_len.to_le_bytes())?;
+    out.write_all(schema)?;
+
+    let mut count: u64 = 0;
+    for entry in bus.entries() {
+        let inspect::Entry::Slot(slot) = entry else {
+            continue;
+        };
+        out.write_all(&slot.source.to_le_bytes())?;
+        out.write_all(&slot.message_id.to_le_bytes())?;
+        out.write_all(&(slot.payload.len() as u32).to_le_bytes())?;
+        #[cfg(feature = "timestamps")]
+        out.write_all(&slot.timestamp.to_le_bytes())?;
+        out.write_all(slot.payload)?;
+        count += 1;
+    }
+
+    out.seek(std::io::SeekFrom::Start(count_offset))?;
+    out.write_all(&count.to_le_bytes())?;
+    out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    Ok(count)
+}
+
+/// Reads the metadata of the archive at `path`, without its messages.
+pub fn metadata(path: impl AsRef<std::path::Path>) -> std::io::Result<Metadata> {
+    read_metadata(&mut std::io::BufReader::new(std::fs::File::open(path)?))
+}
+
+/// Imports the archive at `archive` into a new `ExtendingBus` file at
+/// `bus_file`, replacing any file there, and returns the archive's metadata.
+/// The file opens as an `ExtendingBus` configured with `min_page_len` as its
+/// `get_min_page_len`; pass 1 for the system page size.
+///
+/// Timestamps are kept if both the archive and this build have them, and
+/// are 0 otherwise. Payloads are not converted: check
+/// [`payload_order`](Metadata::payload_order) and
+/// [`schema`](Metadata::schema) first if that matters.
+pub fn import(
+    archive: impl AsRef<std::path::Path>,
+    bus_file: impl AsRef<std::path::Path>,
+    min_page_len: usize,
+) -> std::io::Result<Metadata> {
+    use std::io::Read;
+
+    let page_size = bus_file::page_size_for(min_page_len)?;
+    let mut input = std::io::BufReader::new(std::fs::File::open(archive)?);
+    let metadata = read_metadata(&mut input)?;
+    bus_file::write_atomically(bus_file.as_ref(), page_size, metadata.schema_id, |file| {
+        let mut payload = Vec::new();
+        for _ in 0..metadata.messages {
+            let source = u16::from_le_bytes(read_array(&mut input)?);
+            let message_id = u16::from_le_bytes(read_array(&mut input)?);
+            let size = u32::from_le_bytes(read_array(&mut input)?);
+            let timestamp = if metadata.timestamps {
+                u64::from_le_bytes(read_array(&mut input)?)
+            } else {
+                0
+            };
+            payload.clear();
+            (&mut input).take(size as u64).read_to_end(&mut payload)?;
+            if payload.len() != size as usize {
+                return Err(truncated());
+            }
+            file.write(source, message_id, timestamp, &payload)?;
+        }
+        if input.read(&mut [0])? != 0 {
+            return Err(invalid("the archive has data after its last message"));
+        }
+        Ok(())
+    })?;
+    Ok(metadata)
+}
+
+fn read_metadata(input: &mut impl std::io::Read) -> std::io::Result<Metadata> {
+    if read_array::<8>(input)? != MAGIC {
+        return Err(invalid("not a message archive"));
+    }
+    let version = u16::from_le_bytes(read_array(input)?);
+    if version == 0 || version > VERSION {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("archive format version {version} is not supported, only up to {VERSION}"),
+        ));
+    }
+    let flags = u16::from_le_bytes(read_array(input)?);
+    let messages = u64::from_le_bytes(read_array(input)?);
+    let schema_id = u64::from_le_bytes(read_array(input)?);
+    let schema_len = u32::from_le_bytes(read_array(input)?) as usize;
+    if schema_len > MAX_SCHEMA_LEN {
+        return Err(invalid("the schema length exceeds the maximum"));
+    }
+    let mut schema = vec![0; schema_len];
+    input.read_exact(&mut schema).map_err(eof_is_truncation)?;
+    Ok(Metadata {
+        version,
+        payload_order: if flags & BIG_ENDIAN_PAYLOADS != 0 {
+            ByteOrder::Big
+        } else {
+            ByteOrder::Little
+        },
+        timestamps: flags & TIMESTAMPS != 0,
+        messages,
+        schema_id,
+        schema,
+    })
+}
+
+fn read_array<const N: usize>(input: &mut impl std::io::Read) -> std::io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    input.read_exact(&mut bytes).map_err(eof_is_truncation)?;
+    Ok(bytes)
+}
+
+fn eof_is_truncation(e: std::io::Error) -> std::io::Error {
+    if e.kind() == std::io::ErrorKind::UnexpectedEof {
+        truncated()
+    } else {
+        e
+    }
+}
+
+fn truncated() -> std::io::Error {
+    invalid("the archive is truncated")
+}
+
+fn invalid(reason: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flight_recorder::FlightRecorder;
+    use crate::message_bus::atomic_circular_bus;
+    use crate::traits::core::RawWriter;
+
+    struct Config;
+
+    impl atomic_circular_bus::Config for Config {
+        fn get_buffer_size(&self) -> usize {
+            16384
+        }
+    }
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "rust-messenger-archive-{name}-{}",
+            std::process::id()
+        ))
+    }
+
+    /// A bus file holding a message per payload, from source 3, message
+    /// ids counting up from 10.
+    fn recording(name: &str, payloads: &[&[u8]]) -> std::path::PathBuf {
+        let bus = atomic_circular_bus::CircularBus::new(&Config);
+        for (id, payload) in payloads.iter().enumerate() {
+            bus.write_raw(3, 10 + id as u16, payload.len(), |buffer| {
+                buffer[..payload.len()].copy_from_slice(payload)
+            });
+        }
+        let path = temp_path(&format!("{name}.bus"));
+        FlightRecorder::new(bus, &path).schema_id(42).dump().unwrap();
+        path
+    }
+
+    fn messages(bus: &inspect::BusFile) -> Vec<(u16, u16, Vec<u8>)> {
+        bus.entries()
+            .map(|entry| match entry {
+                inspect::Entry::Slot(slot) => (slot.source, slot.message_id, slot.payload.to_vec()),
+                hole => panic!("unexpected {hole:?}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn export_and_import_round_trip() {
+        let payloads: [&[u8]; 3] = [b"first", b"", b"a longer third payload"];
+        let original = recording("round-trip", &payloads);
+        let archive = temp_path("round-trip.rmsg");
+        let imported = temp_path("round-trip-imported.bus");
+
+        assert_eq!(export(&original, &archive, b"orders-v3").unwrap(), 3);
+        let metadata = import(&archive, &imported, 1).unwrap();
+        assert_eq!(metadata, self::metadata(&archive).unwrap());
+        assert_eq!(
+            metadata,
+            Metadata {
+                version: VERSION,
+                payload_order: ByteOrder::native(),
+                timestamps: cfg!(feature = "timestamps"),
+                messages: 3,
+                schema_id: 42,
+                schema: b"orders-v3".to_vec(),
+            }
+        );
+
+        let original_bytes = std::fs::read(&original).unwrap();
+        let imported_bytes = std::fs::read(&imported).unwrap();
+        // Equal but for the creation time in the header page.
+        let page_size = crate::mmap::anonymous_mmap::page_size();
+        assert_eq!(imported_bytes[page_size..], original_bytes[page_size..]);
+        let imported_file = inspect::BusFile::from_bytes(imported_bytes).unwrap();
+        assert_eq!(imported_file.header().schema_id, 42);
+        assert_eq!(
+            messages(&imported_file),
+            [
+                (3, 10, b"first".to_vec()),
+                (3, 11, Vec::new()),
+                (3, 12, b"a longer third payload".to_vec()),
+            ]
+        );
+        for path in [original, archive, imported] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn archive_numbers_are_little_endian() {
+        let original = recording("layout", &[&[1, 2, 3]]);
+        let archive = temp_path("layout.rmsg");
+        export(&original, &archive, b"s").unwrap();
+
+        let bytes = std::fs::read(&archive).unwrap();
+        assert_eq!(&bytes[..8], b"rmsgarch");
+        assert_eq!(&bytes[8..10], &[1, 0]);
+        assert_eq!(&bytes[12..20], &[1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&bytes[20..28], &[42, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&bytes[28..33], &[1, 0, 0, 0, b's']);
+        // Source 3, message id 10, size 3.
+        assert_eq!(&bytes[33..41], &[3, 0, 10, 0, 3, 0, 0, 0]);
+        assert_eq!(&bytes[bytes.len() - 3..], &[1, 2, 3]);
+        std::fs::remove_file(original).unwrap();
+        std::fs::remove_file(archive).unwrap();
+    }
+
+    #[test]
+    fn damaged_archives_are_rejected() {
+        let original = recording("damaged", &[b"payload"]);
+        let archive = temp_path("damaged.rmsg");
+        let imported = temp_path("damaged-imported.bus");
+        export(&original, &archive, &[]).unwrap();
+        let bytes = std::fs::read(&archive).unwrap();
+
+        let rejects = |bytes: &[u8], kind: std::io::ErrorKind, reason: &str| {
+            std::fs::write(&archive, bytes).unwrap();
+            let e = import(&archive, &imported, 1).unwrap_err();
+            assert_eq!(e.kind(), kind);
+            assert!(e.to_string().contains(reason), "{e}");
+            assert!(!imported.exists());
+        };
+        rejects(&bytes[..bytes.len() - 1], std::io::ErrorKind::InvalidData, "truncated");
+        rejects(&[&bytes[..], &[0]].concat(), std::io::ErrorKind::InvalidData, "after its last");
+        rejects(b"not an archive at all", std::io::ErrorKind::InvalidData, "not a message archive");
+        let mut newer = bytes.clone();
+        newer[8] = VERSION as u8 + 1;
+        rejects(&newer, std::io::ErrorKind::Unsupported, "version");
+        let mut huge_schema = bytes.clone();
+        huge_schema[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        rejects(&huge_schema, std::io::ErrorKind::InvalidData, "schema length");
+        let e = export(&original, &archive, &vec![0; MAX_SCHEMA_LEN + 1]).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput);
+
+        let mut temporary = imported.into_os_string();
+        temporary.push(".tmp");
+        assert!(!std::path::Path::new(&temporary).exists());
+        std::fs::remove_file(original).unwrap();
+        std::fs::remove_file(archive).unwrap();
+    }
+}
@@ -0,0 +1,7 @@
+//! Lists the slots of an `ExtendingBus` file; see `rust_messenger::inspect`.
+//! Without decoders for the application's message types, payloads are shown
+//! as sizes and, with `--hex`, hex dumps.
+
+fn main() -> std::process::ExitCode {
+    rust_messenger::inspect::main(&rust_messenger::inspect::Decoders::new())
+}
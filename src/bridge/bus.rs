@@ -0,0 +1,116 @@
+use super::{BridgeHandle, Counters, Filter};
+use crate::cursor;
+use crate::messenger;
+use crate::traits;
+
+/// Messages examined per [`pump`](BusBridge::pump) at most, so that a busy
+/// source bus cannot keep a handler's `on_loop` from returning.
+const BATCH: usize = 1024;
+
+/// Copies the messages selected by a [`Filter`] from one bus onto another
+/// in the same process, e.g. hot-path traffic from a `CircularBus` into an
+/// `ExtendingBus` audit log, or into a second ring for another group of
+/// workers.
+///
+/// Slots are copied byte for byte under their original source and message
+/// id, so the destination routes them like the source did. Either run the
+/// bridge on a thread of its own with [`spawn`](BusBridge::spawn), or own it
+/// in a handler and [`pump`](BusBridge::pump) it from `on_loop`.
+///
+/// The source bus must not block in `try_read`, so it must not be a
+/// `CondvarBus`. Bridging a bus onto itself, or two buses both ways with
+/// overlapping filters, copies the same messages forever.
+pub struct BusBridge<From, To> {
+    from: From,
+    to: To,
+    filter: Filter,
+    cursor: cursor::Cursor,
+    counters: std::sync::Arc<Counters>,
+}
+
+impl<From: traits::core::MessageBus + traits::core::Positions, To: traits::core::RawWriter> BusBridge<From, To> {
+    /// Copies the messages written to `from` from now on.
+    pub fn new(from: From, to: To, filter: Filter) -> BusBridge<From, To> {
+        let cursor = cursor::Cursor::tail(&from);
+        BusBridge {
+            from,
+            to,
+            filter,
+            cursor,
+            counters: Default::default(),
+        }
+    }
+
+    /// Moves the bridge to `start` on the source bus, e.g.
+    /// [`Oldest`](messenger::StartAt::Oldest) to also copy what it still
+    /// holds.
+    pub fn start(mut self, start: messenger::StartAt) -> BusBridge<From, To> {
+        self.cursor.seek(start.position(&self.from, "bus-bridge", 1));
+        self
+    }
+
+    /// Copies the selected messages written to the source since the last
+    /// call, up to a batch, and returns how many it copied. A bridge lapped
+    /// by the source's writers skips to the oldest intact message and counts
+    /// it in [`lapped`](BusBridge::lapped).
+    pub fn pump(&mut self) -> usize {
+        let mut copied = 0;
+        for _ in 0..BATCH {
+            match self.cursor.next(&self.from) {
+                Ok(Some((header, payload))) => {
+                    if self.filter.matches(header) {
+                        self.to
+                            .write_raw(header.source, header.message_id, payload.len(), |buffer| {
+                                buffer[..payload.len()].copy_from_slice(payload)
+                            });
+                        copied += 1;
+                    }
+                }
+                Ok(None) => break,
+                Err(_) => {
+                    self.counters
+                        .lapped
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    self.cursor
+                        .seek(traits::core::Positions::oldest(&self.from));
+                }
+            }
+        }
+        self.counters
+            .messages
+            .fetch_add(copied as u64, std::sync::atomic::Ordering::Relaxed);
+        copied
+    }
+
+    /// Messages copied so far.
+    pub fn copied(&self) -> u64 {
+        self.counters
+            .messages
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Times the bridge fell behind the source and skipped to its oldest
+    /// intact message, losing the ones in between.
+    pub fn lapped(&self) -> u64 {
+        self.counters
+            .lapped
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Pumps on a thread named `bus-bridge` until the returned handle stops.
+    pub fn spawn(mut self) -> BridgeHandle {
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let (st, counters) = (stop.clone(), self.counters.clone());
+        let thread = std::thread::Builder::new()
+            .name("bus-bridge".to_string())
+            .spawn(move || {
+                while !st.load(std::sync::atomic::Ordering::Relaxed) {
+                    if self.pump() == 0 {
+                        std::thread::yield_now();
+                    }
+                }
+            })
+            .expect("spawning the bridge thread failed");
+        BridgeHandle::new(stop, counters, vec![thread])
+    }
+}
@@ -0,0 +1,246 @@
+//! The transport-independent halves of a bridge: streaming bus messages into
+//! a byte stream, and writing the frames read from one onto a bus.
+
+use super::{BridgeHandle, Counters, Filter, FRAME_HEADER_SIZE};
+use crate::cursor;
+use crate::traits;
+
+/// How long blocked socket calls wait before checking the stop flag.
+pub(super) const POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Runs `run` on a thread named `name` until the returned handle stops it.
+pub(super) fn spawn(
+    name: &str,
+    run: impl FnOnce(&std::sync::atomic::AtomicBool, &std::sync::Arc<Counters>) + Send + 'static,
+) -> BridgeHandle {
+    let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let counters = std::sync::Arc::new(Counters::default());
+    let (st, ct) = (stop.clone(), counters.clone());
+    let thread = std::thread::Builder::new()
+        .name(name.to_string())
+        .spawn(move || run(&st, &ct))
+        .expect("spawning the bridge thread failed");
+    BridgeHandle::new(stop, counters, vec![thread])
+}
+
+/// The sending side: which messages, to whom, and how patiently.
+pub(super) struct Outbound<'a> {
+    pub peer: &'a str,
+    pub filter: &'a Filter,
+    pub min_backoff: std::time::Duration,
+    pub max_backoff: std::time::Duration,
+}
+
+impl Outbound<'_> {
+    /// Connects with `connect`, backing off while it fails, and streams the
+    /// selected messages until the bridge stops, reconnecting after every
+    /// failed write.
+    pub fn run<MB: traits::core::MessageBus + traits::core::Positions, S: std::io::Write>(
+        &self,
+        mut connect: impl FnMut() -> std::io::Result<S>,
+        message_bus: MB,
+        mut cursor: cursor::Cursor,
+        stop: &std::sync::atomic::AtomicBool,
+        counters: &Counters,
+    ) {
+        let mut backoff = self.min_backoff;
+        while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+            let stream = match connect() {
+                Ok(stream) => stream,
+                Err(_) => {
+                    sleep_unless_stopped(backoff, stop);
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    continue;
+                }
+            };
+            backoff = self.min_backoff;
+            counters
+                .connections
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            if let Err(e) = self.forward(&message_bus, &mut cursor, stream, stop, counters) {
+                counters.fail(Some(self.peer), e);
+            }
+        }
+    }
+
+    /// Streams messages until the connection fails or the bridge stops.
+    fn forward<MB: traits::core::MessageBus + traits::core::Positions, S: std::io::Write>(
+        &self,
+        message_bus: &MB,
+        cursor: &mut cursor::Cursor,
+        stream: S,
+        stop: &std::sync::atomic::AtomicBool,
+        counters: &Counters,
+    ) -> std::io::Result<()> {
+        use std::io::Write;
+
+        let mut stream = std::io::BufWriter::new(stream);
+        while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+            match cursor.next(message_bus) {
+                Ok(Some((header, payload))) => {
+                    if self.filter.matches(header) {
+                        stream.write_all(&super::encode_frame_header(
+                            header.source,
+                            header.message_id,
+                            header.size,
+                        ))?;
+                        stream.write_all(payload)?;
+                        counters
+                            .messages
+                            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    }
+                }
+                Ok(None) => {
+                    // Caught up: send what is buffered, then wait.
+                    stream.flush()?;
+                    std::thread::yield_now();
+                }
+                Err(_) => {
+                    counters
+                        .lapped
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    cursor.seek(traits::core::Positions::oldest(message_bus));
+                }
+            }
+        }
+        stream.flush()
+    }
+}
+
+/// The receiving side: how to write what arrives onto the bus.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Inbound {
+    pub source: Option<u16>,
+    pub max_message_size: u32,
+}
+
+impl Inbound {
+    /// Polls `accept`, which returns `WouldBlock` when nobody is waiting,
+    /// and receives on one thread per connection until the bridge stops.
+    /// Accepted streams must time out their reads, so that connection
+    /// threads notice the stop flag.
+    pub fn accept<S: std::io::Read + Send + 'static, W: traits::core::RawWriter>(
+        self,
+        mut accept: impl FnMut() -> std::io::Result<(S, String)>,
+        writer: W,
+        stop: &std::sync::atomic::AtomicBool,
+        counters: &std::sync::Arc<Counters>,
+    ) {
+        // Connection threads outlive this borrow of the flag, so they get a
+        // flag of their own, raised on the way out.
+        let connections_stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let mut connections = Vec::new();
+        while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+            match accept() {
+                Ok((stream, peer)) => {
+                    counters
+                        .connections
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    let (writer, stop, counters) =
+                        (writer.clone(), connections_stop.clone(), counters.clone());
+                    connections.push(std::thread::spawn(move || {
+                        if let Err(e) = self.receive(stream, &writer, &stop, &counters) {
+                            counters.fail(Some(&peer), e);
+                        }
+                    }));
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(POLL_INTERVAL / 10);
+                }
+                Err(e) => counters.fail(None, e),
+            }
+            connections.retain(|connection| !connection.is_finished());
+        }
+        connections_stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        for connection in connections {
+            let _ = connection.join();
+        }
+    }
+
+    /// Writes every frame onto the bus until the peer disconnects or the
+    /// bridge stops.
+    fn receive<S: std::io::Read, W: traits::core::RawWriter>(
+        &self,
+        stream: S,
+        writer: &W,
+        stop: &std::sync::atomic::AtomicBool,
+        counters: &Counters,
+    ) -> std::io::Result<()> {
+        let mut stream = std::io::BufReader::new(stream);
+        let mut payload = Vec::new();
+        loop {
+            let mut frame = [0; FRAME_HEADER_SIZE];
+            if !read_exact(&mut stream, &mut frame, true, stop)? {
+                return Ok(());
+            }
+            let (source, message_id, size) = super::decode_frame_header(&frame);
+            if size > self.max_message_size {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!(
+                        "message of {size} bytes exceeds the {} byte limit",
+                        self.max_message_size
+                    ),
+                ));
+            }
+            // Read the whole payload before reserving a slot: a slot held
+            // while waiting on the network would stall every reader behind it.
+            payload.resize(size as usize, 0);
+            if !read_exact(&mut stream, &mut payload, false, stop)? {
+                return Ok(());
+            }
+            let source = self.source.unwrap_or(source);
+            writer.write_raw(source, message_id, payload.len(), |buffer| {
+                buffer[..payload.len()].copy_from_slice(&payload)
+            });
+            counters
+                .messages
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }
+    }
+}
+
+/// Fills `buffer`, retrying read timeouts until the bridge stops. Returns
+/// `false` on a clean end of stream before the first byte of a frame (if
+/// `at_boundary`) or when stopped.
+fn read_exact<R: std::io::Read>(
+    stream: &mut R,
+    buffer: &mut [u8],
+    at_boundary: bool,
+    stop: &std::sync::atomic::AtomicBool,
+) -> std::io::Result<bool> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        if stop.load(std::sync::atomic::Ordering::Relaxed) {
+            return Ok(false);
+        }
+        match stream.read(&mut buffer[filled..]) {
+            Ok(0) if filled == 0 && at_boundary => return Ok(false),
+            Ok(0) => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => filled += read,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::WouldBlock
+                        | std::io::ErrorKind::TimedOut
+                        | std::io::ErrorKind::Interrupted
+                ) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+pub(super) fn sleep_unless_stopped(
+    duration: std::time::Duration,
+    stop: &std::sync::atomic::AtomicBool,
+) {
+    let deadline = std::time::Instant::now() + duration;
+    while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+        let now = std::time::Instant::now();
+        if now >= deadline {
+            return;
+        }
+        std::thread::sleep((deadline - now).min(POLL_INTERVAL));
+    }
+}
@@ -0,0 +1,213 @@
+//! Bridges carry bus messages, header ids included, to another bus without
+//! knowing the message types: in the same process with a
+//! [`BusBridge`](bus::BusBridge), in another process over [`tcp`] or, on one
+//! host, over `unix` sockets.
+
+pub mod bus;
+mod link;
+pub mod tcp;
+#[cfg(target_os = "linux")]
+pub mod unix;
+
+use crate::messenger;
+
+/// The `(source, message_id)` pairs a bridge forwards.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+    /// `None` forwards everything.
+    routes: Option<std::collections::HashSet<(u16, u16)>>,
+}
+
+impl Filter {
+    /// Forwards nothing until routes are added.
+    pub fn new() -> Filter {
+        Filter {
+            routes: Some(Default::default()),
+        }
+    }
+
+    /// Forwards every message.
+    pub fn all() -> Filter {
+        Filter { routes: None }
+    }
+
+    /// Also forwards the messages `message_id` sent by `source`.
+    pub fn route(mut self, source: impl Into<u16>, message_id: impl Into<u16>) -> Filter {
+        self.routes
+            .get_or_insert_with(Default::default)
+            .insert((source.into(), message_id.into()));
+        self
+    }
+
+    pub fn matches(&self, header: &messenger::Header) -> bool {
+        self.allows(header.source, header.message_id)
+    }
+
+    /// Whether the filter forwards the messages `message_id` of `source`.
+    pub fn allows(&self, source: u16, message_id: u16) -> bool {
+        match &self.routes {
+            None => true,
+            Some(routes) => routes.contains(&(source, message_id)),
+        }
+    }
+}
+
+/// Wire header of a forwarded message, followed by `size` payload bytes:
+/// source, message id and size, little endian.
+const FRAME_HEADER_SIZE: usize = 8;
+
+fn encode_frame_header(source: u16, message_id: u16, size: u32) -> [u8; FRAME_HEADER_SIZE] {
+    let mut frame = [0; FRAME_HEADER_SIZE];
+    frame[0..2].copy_from_slice(&source.to_le_bytes());
+    frame[2..4].copy_from_slice(&message_id.to_le_bytes());
+    frame[4..8].copy_from_slice(&size.to_le_bytes());
+    frame
+}
+
+fn decode_frame_header(frame: &[u8; FRAME_HEADER_SIZE]) -> (u16, u16, u32) {
+    (
+        u16::from_le_bytes([frame[0], frame[1]]),
+        u16::from_le_bytes([frame[2], frame[3]]),
+        u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]),
+    )
+}
+
+/// Errors a [`BridgeHandle`] keeps until they are taken; older ones are
+/// dropped, but still counted.
+const KEPT_ERRORS: usize = 64;
+
+/// A connection of a running bridge that failed, collected by its
+/// [`BridgeHandle`].
+#[derive(Debug)]
+pub struct BridgeError {
+    /// The address of the other side, if the connection got that far.
+    pub peer: Option<String>,
+    pub error: std::io::Error,
+}
+
+impl std::fmt::Display for BridgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.peer {
+            Some(peer) => write!(f, "bridge connection with {peer} failed: {}", self.error),
+            None => write!(f, "bridge failed to accept a connection: {}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for BridgeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Counters of a running bridge, shared with its threads.
+#[derive(Debug, Default)]
+struct Counters {
+    messages: std::sync::atomic::AtomicU64,
+    connections: std::sync::atomic::AtomicU64,
+    lapped: std::sync::atomic::AtomicU64,
+    failed: std::sync::atomic::AtomicU64,
+    errors: std::sync::Mutex<std::collections::VecDeque<BridgeError>>,
+}
+
+impl Counters {
+    fn fail(&self, peer: Option<&str>, error: std::io::Error) {
+        self.failed.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let mut errors = self.errors.lock().unwrap_or_else(|e| e.into_inner());
+        if errors.len() == KEPT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(BridgeError {
+            peer: peer.map(str::to_string),
+            error,
+        });
+    }
+}
+
+/// The threads of a running bridge. Dropping it stops and joins them.
+#[must_use = "dropping the handle stops the bridge"]
+pub struct BridgeHandle {
+    stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    counters: std::sync::Arc<Counters>,
+    threads: Vec<std::thread::JoinHandle<()>>,
+}
+
+impl BridgeHandle {
+    fn new(
+        stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+        counters: std::sync::Arc<Counters>,
+        threads: Vec<std::thread::JoinHandle<()>>,
+    ) -> BridgeHandle {
+        BridgeHandle {
+            stop,
+            counters,
+            threads,
+        }
+    }
+
+    /// Messages forwarded (or received) so far.
+    pub fn messages(&self) -> u64 {
+        self.counters
+            .messages
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Connections established so far, counting reconnects.
+    pub fn connections(&self) -> u64 {
+        self.counters
+            .connections
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Times the bridge fell behind a `CircularBus` and skipped to the
+    /// oldest intact message, losing the ones in between.
+    pub fn lapped(&self) -> u64 {
+        self.counters
+            .lapped
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Connections that failed so far, including failed accepts.
+    pub fn failed(&self) -> u64 {
+        self.counters
+            .failed
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Takes the errors of the failed connections, oldest first; only the
+    /// last 64 are kept.
+    pub fn take_errors(&self) -> Vec<BridgeError> {
+        let mut errors = self.counters.errors.lock().unwrap_or_else(|e| e.into_inner());
+        errors.drain(..).collect()
+    }
+
+    /// Stops the bridge threads and waits for them.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            // A bridge thread panicking has already reported itself.
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for BridgeHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_header_roundtrip() {
+        let frame = encode_frame_header(7, 300, 70_000);
+        assert_eq!(decode_frame_header(&frame), (7, 300, 70_000));
+    }
+}
@@ -0,0 +1,136 @@
+use super::{link, BridgeHandle, Filter};
+use crate::cursor;
+use crate::messenger;
+use crate::traits;
+
+/// Forwards the messages selected by a [`Filter`] from a local bus to a
+/// [`TcpReceiver`], connecting to it and reconnecting with exponential
+/// backoff whenever the connection fails.
+///
+/// Messages are read with a [`Cursor`](cursor::Cursor) from where the bus
+/// stands when the forwarder is spawned (see [`start`](TcpForwarder::start)).
+/// While disconnected, messages wait in the bus until the receiver is back;
+/// the ones already buffered for a connection that breaks are lost. If both
+/// processes bridge the same routes to each other, messages echo forever.
+///
+/// The bus must not block in `try_read`, so it must not be a `CondvarBus`.
+#[derive(Debug, Clone)]
+pub struct TcpForwarder {
+    addr: String,
+    filter: Filter,
+    start: messenger::StartAt,
+    min_backoff: std::time::Duration,
+    max_backoff: std::time::Duration,
+}
+
+impl TcpForwarder {
+    /// Forwards the messages selected by `filter` to the receiver at `addr`.
+    pub fn new(addr: impl Into<String>, filter: Filter) -> TcpForwarder {
+        TcpForwarder {
+            addr: addr.into(),
+            filter,
+            start: messenger::StartAt::Tail,
+            min_backoff: std::time::Duration::from_millis(10),
+            max_backoff: std::time::Duration::from_secs(1),
+        }
+    }
+
+    /// Where the forwarder starts reading the bus; defaults to
+    /// [`Tail`](messenger::StartAt::Tail), the messages written from now on.
+    pub fn start(mut self, start: messenger::StartAt) -> TcpForwarder {
+        self.start = start;
+        self
+    }
+
+    /// Wait after the first failed connection attempt, doubled after every
+    /// further failure up to `max`. Defaults to 10 ms and 1 s.
+    pub fn backoff(mut self, min: std::time::Duration, max: std::time::Duration) -> TcpForwarder {
+        assert!(!min.is_zero() && min <= max, "backoff needs 0 < min <= max");
+        self.min_backoff = min;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Starts forwarding from `message_bus` on a thread named
+    /// `bridge-tcp-out`.
+    pub fn spawn<MB: traits::core::MessageBus + traits::core::Positions>(self, message_bus: MB) -> BridgeHandle {
+        let position = self.start.position(&message_bus, "bridge-tcp-out", 1);
+        link::spawn("bridge-tcp-out", move |stop, counters| {
+            let outbound = link::Outbound {
+                peer: &self.addr,
+                filter: &self.filter,
+                min_backoff: self.min_backoff,
+                max_backoff: self.max_backoff,
+            };
+            let connect = || {
+                let stream = std::net::TcpStream::connect(&self.addr)?;
+                // Frames are small; do not hold them back waiting for more.
+                stream.set_nodelay(true)?;
+                Ok(stream)
+            };
+            outbound.run(connect, message_bus, cursor::Cursor::at(position), stop, counters);
+        })
+    }
+}
+
+/// Accepts connections from [`TcpForwarder`]s and writes the messages they
+/// send onto a local bus, keeping their message ids and, unless
+/// [`source`](TcpReceiver::source) says otherwise, their source ids.
+#[derive(Debug)]
+pub struct TcpReceiver {
+    listener: std::net::TcpListener,
+    source: Option<u16>,
+    max_message_size: u32,
+}
+
+impl TcpReceiver {
+    /// Listens on `addr`; port 0 picks a free port, see
+    /// [`local_addr`](TcpReceiver::local_addr).
+    pub fn bind(addr: impl std::net::ToSocketAddrs) -> std::io::Result<TcpReceiver> {
+        Ok(TcpReceiver {
+            listener: std::net::TcpListener::bind(addr)?,
+            source: None,
+            max_message_size: 1 << 20,
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Stamps every received message with `source` instead of its original
+    /// source, e.g. to route everything from the remote process as
+    /// `messenger::Source<N>`.
+    pub fn source(mut self, source: impl Into<u16>) -> TcpReceiver {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Largest payload accepted, 1 MiB by default; a connection announcing
+    /// a larger one is dropped as corrupt.
+    pub fn max_message_size(mut self, bytes: u32) -> TcpReceiver {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Starts accepting on a thread named `bridge-tcp-in`, with one thread
+    /// per connection.
+    pub fn spawn<W: traits::core::RawWriter>(self, writer: W) -> BridgeHandle {
+        let inbound = link::Inbound {
+            source: self.source,
+            max_message_size: self.max_message_size,
+        };
+        link::spawn("bridge-tcp-in", move |stop, counters| {
+            self.listener
+                .set_nonblocking(true)
+                .expect("making the bridge listener non-blocking failed");
+            let accept = || {
+                let (stream, peer) = self.listener.accept()?;
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(link::POLL_INTERVAL))?;
+                Ok((stream, peer.to_string()))
+            };
+            inbound.accept(accept, writer, stop, counters);
+        })
+    }
+}
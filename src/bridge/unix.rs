@@ -0,0 +1,280 @@
+//! Bridges between processes on one host, over Unix domain sockets.
+//!
+//! [`UnixForwarder`] and [`UnixReceiver`] copy messages between two buses
+//! like their `tcp` counterparts. Processes that can share memory need not
+//! copy at all: [`share`] hands the memfd of a
+//! [`CircularBus::shared`](atomic_circular_bus::CircularBus::shared) bus to
+//! every process that connects, and [`attach`] maps it there, so both work
+//! on the same ring. The socket then only carries the handshake.
+
+use std::os::fd::{AsRawFd, FromRawFd};
+
+use super::{link, BridgeHandle, Filter};
+use crate::cursor;
+use crate::message_bus::atomic_circular_bus;
+use crate::messenger;
+use crate::traits;
+
+/// Sent along with the bus file descriptor, so that [`attach`] recognizes
+/// the server it connected to.
+const HANDSHAKE: [u8; 8] = *b"rmbus\0\0\x01";
+
+/// Forwards the messages selected by a [`Filter`] to a [`UnixReceiver`]
+/// bound at a socket path; see `tcp::TcpForwarder`, which it mirrors.
+#[derive(Debug, Clone)]
+pub struct UnixForwarder {
+    path: std::path::PathBuf,
+    filter: Filter,
+    start: messenger::StartAt,
+    min_backoff: std::time::Duration,
+    max_backoff: std::time::Duration,
+}
+
+impl UnixForwarder {
+    /// Forwards the messages selected by `filter` to the receiver at `path`.
+    pub fn new(path: impl Into<std::path::PathBuf>, filter: Filter) -> UnixForwarder {
+        UnixForwarder {
+            path: path.into(),
+            filter,
+            start: messenger::StartAt::Tail,
+            min_backoff: std::time::Duration::from_millis(10),
+            max_backoff: std::time::Duration::from_secs(1),
+        }
+    }
+
+    /// Where the forwarder starts reading the bus; defaults to
+    /// [`Tail`](messenger::StartAt::Tail).
+    pub fn start(mut self, start: messenger::StartAt) -> UnixForwarder {
+        self.start = start;
+        self
+    }
+
+    /// Wait after the first failed connection attempt, doubled after every
+    /// further failure up to `max`. Defaults to 10 ms and 1 s.
+    pub fn backoff(mut self, min: std::time::Duration, max: std::time::Duration) -> UnixForwarder {
+        assert!(!min.is_zero() && min <= max, "backoff needs 0 < min <= max");
+        self.min_backoff = min;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Starts forwarding from `message_bus` on a thread named
+    /// `bridge-unix-out`.
+    pub fn spawn<MB: traits::core::MessageBus + traits::core::Positions>(self, message_bus: MB) -> BridgeHandle {
+        let position = self.start.position(&message_bus, "bridge-unix-out", 1);
+        link::spawn("bridge-unix-out", move |stop, counters| {
+            let peer = self.path.display().to_string();
+            let outbound = link::Outbound {
+                peer: &peer,
+                filter: &self.filter,
+                min_backoff: self.min_backoff,
+                max_backoff: self.max_backoff,
+            };
+            let connect = || std::os::unix::net::UnixStream::connect(&self.path);
+            outbound.run(connect, message_bus, cursor::Cursor::at(position), stop, counters);
+        })
+    }
+}
+
+/// Accepts connections from [`UnixForwarder`]s and writes the messages they
+/// send onto a local bus; see `tcp::TcpReceiver`, which it mirrors.
+#[derive(Debug)]
+pub struct UnixReceiver {
+    listener: Listener,
+    source: Option<u16>,
+    max_message_size: u32,
+}
+
+impl UnixReceiver {
+    /// Listens at `path`, which must not exist yet. The socket file is
+    /// removed when the receiver stops.
+    pub fn bind(path: impl AsRef<std::path::Path>) -> std::io::Result<UnixReceiver> {
+        Ok(UnixReceiver {
+            listener: Listener::bind(path.as_ref())?,
+            source: None,
+            max_message_size: 1 << 20,
+        })
+    }
+
+    /// Stamps every received message with `source` instead of its original
+    /// source.
+    pub fn source(mut self, source: impl Into<u16>) -> UnixReceiver {
+        self.source = Some(source.into());
+        self
+    }
+
+    /// Largest payload accepted, 1 MiB by default; a connection announcing
+    /// a larger one is dropped as corrupt.
+    pub fn max_message_size(mut self, bytes: u32) -> UnixReceiver {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Starts accepting on a thread named `bridge-unix-in`, with one thread
+    /// per connection.
+    pub fn spawn<W: traits::core::RawWriter>(self, writer: W) -> BridgeHandle {
+        let inbound = link::Inbound {
+            source: self.source,
+            max_message_size: self.max_message_size,
+        };
+        link::spawn("bridge-unix-in", move |stop, counters| {
+            let accept = || {
+                let (stream, _) = self.listener.socket.accept()?;
+                stream.set_nonblocking(false)?;
+                stream.set_read_timeout(Some(link::POLL_INTERVAL))?;
+                Ok((stream, self.listener.path.display().to_string()))
+            };
+            inbound.accept(accept, writer, stop, counters);
+        })
+    }
+}
+
+/// Hands the memfd of `bus`, which must be
+/// [`shared`](atomic_circular_bus::CircularBus::shared), to every process
+/// that connects to `path` with [`attach`]. Serves on a thread named
+/// `bridge-unix-share` until the returned handle stops; its
+/// [`connections`](BridgeHandle::connections) counts the attach requests,
+/// and [`take_errors`](BridgeHandle::take_errors) returns those that failed.
+pub fn share(
+    path: impl AsRef<std::path::Path>,
+    bus: &atomic_circular_bus::CircularBus,
+) -> std::io::Result<BridgeHandle> {
+    let fd = bus
+        .shared_fd()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "only a bus created with CircularBus::shared can be shared",
+            )
+        })?
+        .try_clone_to_owned()?;
+    let listener = Listener::bind(path.as_ref())?;
+    Ok(link::spawn("bridge-unix-share", move |stop, counters| {
+        while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+            match listener.socket.accept() {
+                Ok((stream, _)) => {
+                    counters
+                        .connections
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    if let Err(e) = send_fd(&stream, &fd) {
+                        counters.fail(Some(&listener.path.display().to_string()), e);
+                    }
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::sleep(link::POLL_INTERVAL / 10);
+                }
+                Err(e) => counters.fail(None, e),
+            }
+        }
+    }))
+}
+
+/// Connects to a [`share`]d bus at `path` and maps it into this process.
+pub fn attach(
+    path: impl AsRef<std::path::Path>,
+) -> std::io::Result<atomic_circular_bus::CircularBus> {
+    let stream = std::os::unix::net::UnixStream::connect(path)?;
+    atomic_circular_bus::CircularBus::attach(receive_fd(&stream)?)
+}
+
+/// A non-blocking listening socket whose file is removed on drop.
+#[derive(Debug)]
+struct Listener {
+    socket: std::os::unix::net::UnixListener,
+    path: std::path::PathBuf,
+}
+
+impl Listener {
+    fn bind(path: &std::path::Path) -> std::io::Result<Listener> {
+        let socket = std::os::unix::net::UnixListener::bind(path)?;
+        socket.set_nonblocking(true)?;
+        Ok(Listener {
+            socket,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn send_fd(
+    stream: &std::os::unix::net::UnixStream,
+    fd: &std::os::fd::OwnedFd,
+) -> std::io::Result<()> {
+    let mut iov = libc::iovec {
+        iov_base: HANDSHAKE.as_ptr() as *mut libc::c_void,
+        iov_len: HANDSHAKE.len(),
+    };
+    // u64s keep the buffer aligned for the cmsghdr written into it.
+    let mut control = [0u64; 8];
+    let fd_len = std::mem::size_of::<std::os::fd::RawFd>() as u32;
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    message.msg_controllen = unsafe { libc::CMSG_SPACE(fd_len) } as _;
+    unsafe {
+        let header = libc::CMSG_FIRSTHDR(&message);
+        (*header).cmsg_level = libc::SOL_SOCKET;
+        (*header).cmsg_type = libc::SCM_RIGHTS;
+        (*header).cmsg_len = libc::CMSG_LEN(fd_len) as _;
+        std::ptr::write_unaligned(libc::CMSG_DATA(header) as *mut std::os::fd::RawFd, fd.as_raw_fd());
+    }
+    let sent = unsafe { libc::sendmsg(stream.as_raw_fd(), &message, libc::MSG_NOSIGNAL) };
+    if sent < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if sent as usize != HANDSHAKE.len() {
+        return Err(std::io::ErrorKind::WriteZero.into());
+    }
+    Ok(())
+}
+
+fn receive_fd(stream: &std::os::unix::net::UnixStream) -> std::io::Result<std::os::fd::OwnedFd> {
+    let mut payload = [0u8; HANDSHAKE.len()];
+    let mut iov = libc::iovec {
+        iov_base: payload.as_mut_ptr() as *mut libc::c_void,
+        iov_len: payload.len(),
+    };
+    let mut control = [0u64; 8];
+    let mut message: libc::msghdr = unsafe { std::mem::zeroed() };
+    message.msg_iov = &mut iov;
+    message.msg_iovlen = 1;
+    message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    message.msg_controllen = std::mem::size_of_val(&control) as _;
+    let received =
+        unsafe { libc::recvmsg(stream.as_raw_fd(), &mut message, libc::MSG_CMSG_CLOEXEC) };
+    if received < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // Take ownership of any descriptor first, so it is closed on error.
+    let mut fd = None;
+    unsafe {
+        let mut header = libc::CMSG_FIRSTHDR(&message);
+        while !header.is_null() {
+            if (*header).cmsg_level == libc::SOL_SOCKET && (*header).cmsg_type == libc::SCM_RIGHTS {
+                let raw = std::ptr::read_unaligned(libc::CMSG_DATA(header) as *const std::os::fd::RawFd);
+                fd = Some(std::os::fd::OwnedFd::from_raw_fd(raw));
+            }
+            header = libc::CMSG_NXTHDR(&message, header);
+        }
+    }
+    let invalid = |reason: &str| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("bus handshake failed: {reason}"),
+        )
+    };
+    if received as usize != payload.len() || payload != HANDSHAKE {
+        return Err(invalid("unexpected reply"));
+    }
+    if message.msg_flags & libc::MSG_CTRUNC != 0 {
+        return Err(invalid("control data truncated"));
+    }
+    fd.ok_or_else(|| invalid("no file descriptor received"))
+}
@@ -0,0 +1,372 @@
+//! The file format of `ExtendingBus`, which flight recorder dumps and
+//! imported archives share.
+//!
+//! The first page of a bus file is a [`FileHeader`]; the slots follow it,
+//! bus position 0 at the start of the second page. Every slot is a
+//! `messenger::Header` followed by the payload, both as the writing build
+//! lays them out in memory, so a file only opens in builds with the same
+//! [`Layout`]. To move recordings between machines, see `archive`.
+
+use crate::archive;
+use crate::messenger;
+use crate::mmap::anonymous_mmap;
+
+const MAGIC: [u8; 8] = *b"rmsgbus\0";
+
+/// The format version of the files this build writes and reads.
+pub const VERSION: u16 = 1;
+
+/// Bytes of the header page in use; the rest of the page is zero.
+const ENCODED_LEN: usize = 40;
+
+const BIG_ENDIAN: u32 = 1;
+const TIMESTAMPS: u32 = 2;
+
+/// How a build lays out message headers in a bus file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// `messenger::ALIGNED_HEADER_SIZE`.
+    pub header_size: u16,
+    pub byte_order: archive::ByteOrder,
+    /// Whether headers carry a timestamp: the `timestamps` feature.
+    pub timestamps: bool,
+}
+
+impl Layout {
+    /// The layout of this build.
+    pub fn native() -> Layout {
+        Layout {
+            header_size: messenger::ALIGNED_HEADER_SIZE as u16,
+            byte_order: archive::ByteOrder::native(),
+            timestamps: cfg!(feature = "timestamps"),
+        }
+    }
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let byte_order = match self.byte_order {
+            archive::ByteOrder::Little => "little",
+            archive::ByteOrder::Big => "big",
+        };
+        let timestamps = if self.timestamps { "with" } else { "without" };
+        write!(
+            f,
+            "{}-byte {byte_order}-endian headers {timestamps} timestamps",
+            self.header_size
+        )
+    }
+}
+
+/// The first page of a bus file. Its fields are stored in little-endian.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileHeader {
+    pub version: u16,
+    pub layout: Layout,
+    /// The length the file grows by, and of the header page itself.
+    pub page_size: usize,
+    /// When the file was created.
+    pub created: std::time::SystemTime,
+    /// Identifies the messages the file holds, as `Config::get_schema_id`
+    /// of the bus that created it chose.
+    pub schema_id: u64,
+}
+
+/// Why a file does not open as a bus file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileHeaderError {
+    /// The file does not start with a bus file header: it is not a bus
+    /// file, or one written before bus files had a header.
+    NotABusFile,
+    UnsupportedVersion { version: u16 },
+    /// The file was written by a build with another message header layout.
+    Layout { file: Layout, build: Layout },
+    /// The file grows by pages of another length than configured.
+    PageSize { file: usize, config: usize },
+    /// The file holds the messages of another schema than configured.
+    SchemaId { file: u64, config: u64 },
+}
+
+impl std::fmt::Display for FileHeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::NotABusFile => write!(f, "not a bus file (no bus file header)"),
+            Self::UnsupportedVersion { version } => write!(
+                f,
+                "bus file format version {version} is not supported, only {VERSION}"
+            ),
+            Self::Layout { file, build } => write!(
+                f,
+                "the file has {file}, but this build writes {build}"
+            ),
+            Self::PageSize { file, config } => write!(
+                f,
+                "the file has pages of {file} bytes, but the config asks for {config}"
+            ),
+            Self::SchemaId { file, config } => write!(
+                f,
+                "the file has schema id {file}, but the config asks for {config}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FileHeaderError {}
+
+impl From<FileHeaderError> for std::io::Error {
+    fn from(err: FileHeaderError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+    }
+}
+
+impl FileHeader {
+    /// The header of a file created now by this build.
+    pub(crate) fn new(page_size: usize, schema_id: u64) -> FileHeader {
+        FileHeader {
+            version: VERSION,
+            layout: Layout::native(),
+            page_size,
+            created: std::time::SystemTime::now(),
+            schema_id,
+        }
+    }
+
+    /// `created` as stored in the file: nanoseconds since the Unix epoch.
+    pub(crate) fn created_nanos(&self) -> u64 {
+        self.created
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since_epoch| since_epoch.as_nanos() as u64)
+    }
+
+    /// Reads the header at the start of `bytes`, and checks that this build
+    /// can read the file.
+    pub fn parse(bytes: &[u8]) -> Result<FileHeader, FileHeaderError> {
+        let bytes = bytes
+            .get(..ENCODED_LEN)
+            .filter(|bytes| bytes[..8] == MAGIC)
+            .ok_or(FileHeaderError::NotABusFile)?;
+        let field = |offset: usize, len: usize| &bytes[offset..offset + len];
+        let u16_at = |offset| u16::from_le_bytes(field(offset, 2).try_into().unwrap());
+        let u32_at = |offset| u32::from_le_bytes(field(offset, 4).try_into().unwrap());
+        let u64_at = |offset| u64::from_le_bytes(field(offset, 8).try_into().unwrap());
+
+        let version = u16_at(8);
+        if version != VERSION {
+            return Err(FileHeaderError::UnsupportedVersion { version });
+        }
+        let flags = u32_at(12);
+        let layout = Layout {
+            header_size: u16_at(10),
+            byte_order: if flags & BIG_ENDIAN != 0 {
+                archive::ByteOrder::Big
+            } else {
+                archive::ByteOrder::Little
+            },
+            timestamps: flags & TIMESTAMPS != 0,
+        };
+        if layout != Layout::native() {
+            return Err(FileHeaderError::Layout {
+                file: layout,
+                build: Layout::native(),
+            });
+        }
+        Ok(FileHeader {
+            version,
+            layout,
+            page_size: u64_at(16) as usize,
+            created: std::time::UNIX_EPOCH + std::time::Duration::from_nanos(u64_at(24)),
+            schema_id: u64_at(32),
+        })
+    }
+
+    /// Checks that a bus configured with `page_size` and `schema_id` may
+    /// open the file.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn check(&self, page_size: usize, schema_id: u64) -> Result<(), FileHeaderError> {
+        if self.page_size != page_size {
+            return Err(FileHeaderError::PageSize {
+                file: self.page_size,
+                config: page_size,
+            });
+        }
+        if self.schema_id != schema_id {
+            return Err(FileHeaderError::SchemaId {
+                file: self.schema_id,
+                config: schema_id,
+            });
+        }
+        Ok(())
+    }
+
+    pub(crate) fn encode(&self) -> [u8; ENCODED_LEN] {
+        let mut flags = 0;
+        if self.layout.byte_order == archive::ByteOrder::Big {
+            flags |= BIG_ENDIAN;
+        }
+        if self.layout.timestamps {
+            flags |= TIMESTAMPS;
+        }
+        let created = self.created_nanos();
+
+        let mut bytes = [0; ENCODED_LEN];
+        bytes[..8].copy_from_slice(&MAGIC);
+        bytes[8..10].copy_from_slice(&self.version.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.layout.header_size.to_le_bytes());
+        bytes[12..16].copy_from_slice(&flags.to_le_bytes());
+        bytes[16..24].copy_from_slice(&(self.page_size as u64).to_le_bytes());
+        bytes[24..32].copy_from_slice(&created.to_le_bytes());
+        bytes[32..40].copy_from_slice(&self.schema_id.to_le_bytes());
+        bytes
+    }
+
+    /// The header of the bus file at `path`, or `None` if there is no such
+    /// file or it is empty: a bus would create it.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn read(path: &std::path::Path) -> std::io::Result<Option<FileHeader>> {
+        use std::io::Read;
+
+        let mut file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut bytes = Vec::with_capacity(ENCODED_LEN);
+        (&mut file).take(ENCODED_LEN as u64).read_to_end(&mut bytes)?;
+        if bytes.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(FileHeader::parse(&bytes)?))
+    }
+}
+
+/// The page length of an `ExtendingBus` configured with `min_page_len`:
+/// the next power of two of at least the system page size, as
+/// `ExtendingBus::try_new` computes it.
+pub(crate) fn page_size_for(min_page_len: usize) -> std::io::Result<usize> {
+    min_page_len
+        .max(anonymous_mmap::page_size())
+        .checked_next_power_of_two()
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("page length {min_page_len} overflows"),
+            )
+        })
+}
+
+/// Writes a bus file outside a running bus: flight recorder dumps and
+/// imported archives.
+pub(crate) struct SlotWriter<W> {
+    out: W,
+    page_size: usize,
+    written: usize,
+}
+
+impl<W: std::io::Write> SlotWriter<W> {
+    /// Starts the file with its header page, for pages of `page_size`
+    /// bytes, see [`page_size_for`].
+    pub(crate) fn new(mut out: W, page_size: usize, schema_id: u64) -> std::io::Result<SlotWriter<W>> {
+        let mut page = vec![0; page_size];
+        page[..ENCODED_LEN].copy_from_slice(&FileHeader::new(page_size, schema_id).encode());
+        out.write_all(&page)?;
+        Ok(SlotWriter {
+            out,
+            page_size,
+            written: 0,
+        })
+    }
+
+    /// Appends a committed slot. `timestamp` is only stored with the
+    /// `timestamps` feature.
+    pub(crate) fn write(
+        &mut self,
+        source: u16,
+        message_id: u16,
+        timestamp: u64,
+        payload: &[u8],
+    ) -> std::io::Result<()> {
+        let size = u32::try_from(payload.len()).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("message of size {} exceeds the maximum message size", payload.len()),
+            )
+        })?;
+        let mut prefix = [0; messenger::ALIGNED_HEADER_SIZE];
+        let mut put = |offset: usize, bytes: &[u8]| {
+            prefix[offset..offset + bytes.len()].copy_from_slice(bytes)
+        };
+        put(
+            std::mem::offset_of!(messenger::Header, source),
+            &source.to_ne_bytes(),
+        );
+        put(
+            std::mem::offset_of!(messenger::Header, message_id),
+            &message_id.to_ne_bytes(),
+        );
+        put(std::mem::offset_of!(messenger::Header, size), &size.to_ne_bytes());
+        #[cfg(feature = "timestamps")]
+        put(
+            std::mem::offset_of!(messenger::Header, timestamp),
+            &timestamp.to_ne_bytes(),
+        );
+        #[cfg(not(feature = "timestamps"))]
+        let _ = timestamp;
+        put(
+            std::mem::offset_of!(messenger::Header, commit_stamp),
+            &messenger::Header::commit_stamp_for(self.written).to_ne_bytes(),
+        );
+
+        let padding = messenger::align_to_usize(payload.len()) - payload.len();
+        self.out.write_all(&prefix)?;
+        self.out.write_all(payload)?;
+        self.out
+            .write_all(&[0; std::mem::size_of::<usize>()][..padding])?;
+        self.written += messenger::ALIGNED_HEADER_SIZE + payload.len() + padding;
+        Ok(())
+    }
+
+    /// Pads the slots to whole pages, as `ExtendingBus` maps them, and
+    /// returns the output.
+    pub(crate) fn finish(mut self) -> std::io::Result<W> {
+        let padded = self.written.next_multiple_of(self.page_size);
+        self.out.write_all(&vec![0; padded - self.written])?;
+        Ok(self.out)
+    }
+}
+
+/// The timestamp of `header`, or 0 without the `timestamps` feature.
+pub(crate) fn timestamp_of(header: &messenger::Header) -> u64 {
+    #[cfg(feature = "timestamps")]
+    return header.timestamp;
+    #[cfg(not(feature = "timestamps"))]
+    {
+        let _ = header;
+        0
+    }
+}
+
+/// Writes a bus file with pages of `page_size` bytes next to `path` and
+/// renames it over `path` once `fill` succeeded, so a reader never sees a
+/// half-written file. On failure `path` is left as it was.
+pub(crate) fn write_atomically<T>(
+    path: &std::path::Path,
+    page_size: usize,
+    schema_id: u64,
+    fill: impl FnOnce(&mut SlotWriter<std::io::BufWriter<std::fs::File>>) -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    let mut temporary = path.to_path_buf().into_os_string();
+    temporary.push(".tmp");
+    let written = (|| {
+        let out = std::io::BufWriter::new(std::fs::File::create(&temporary)?);
+        let mut writer = SlotWriter::new(out, page_size, schema_id)?;
+        let filled = fill(&mut writer)?;
+        let file = writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        std::fs::rename(&temporary, path)?;
+        Ok(filled)
+    })();
+    if written.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    written
+}
@@ -0,0 +1,79 @@
+/// Source of the current time for handlers, so time-driven logic in `on_loop`
+/// and timers can run against a [`VirtualClock`] under simulation.
+///
+/// Handlers get a clock like any other dependency, typically an
+/// `Arc<dyn Clock>` in the Messenger config.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> std::time::Instant;
+}
+
+/// The real monotonic clock, `Instant::now()`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> std::time::Instant {
+        std::time::Instant::now()
+    }
+}
+
+/// A clock that only moves when advanced. Clones share the same time.
+#[derive(Debug, Clone)]
+pub struct VirtualClock {
+    start: std::time::Instant,
+    elapsed_nanos: std::sync::Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl VirtualClock {
+    /// A clock standing at the current real time.
+    pub fn new() -> VirtualClock {
+        VirtualClock {
+            start: std::time::Instant::now(),
+            elapsed_nanos: Default::default(),
+        }
+    }
+
+    pub fn advance(&self, duration: std::time::Duration) {
+        self.elapsed_nanos.fetch_add(
+            duration.as_nanos() as u64,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+    }
+
+    /// Time advanced since the clock was created.
+    pub fn elapsed(&self) -> std::time::Duration {
+        std::time::Duration::from_nanos(
+            self.elapsed_nanos
+                .load(std::sync::atomic::Ordering::Relaxed),
+        )
+    }
+}
+
+impl Default for VirtualClock {
+    fn default() -> VirtualClock {
+        VirtualClock::new()
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> std::time::Instant {
+        self.start + self.elapsed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn virtual_clock_moves_only_when_advanced() {
+        let clock = VirtualClock::new();
+        let shared = clock.clone();
+        let before = clock.now();
+        assert_eq!(clock.now(), before);
+
+        shared.advance(std::time::Duration::from_millis(250));
+        assert_eq!(clock.now() - before, std::time::Duration::from_millis(250));
+        assert_eq!(clock.elapsed(), std::time::Duration::from_millis(250));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::messenger;
+use crate::traits;
+
+/// A read position on a bus, for consuming it outside the generated workers.
+///
+/// `next` returns the message at the cursor and moves past it by its padded
+/// slot length. A cursor lapped by the writers gets
+/// [`Lapped`](messenger::Lapped) instead of a panic and stays where it is,
+/// so the caller can decide how to recover, e.g. by [`seek`](Cursor::seek)ing
+/// to the tail.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Cursor {
+    position: usize,
+}
+
+impl Cursor {
+    /// A cursor at position 0, the first message ever written.
+    pub fn new() -> Cursor {
+        Cursor::default()
+    }
+
+    /// A cursor at `position`, which must be the start of a message (or the
+    /// write head) for `next` to return anything.
+    pub fn at(position: usize) -> Cursor {
+        Cursor { position }
+    }
+
+    /// A cursor at the write head: it sees only messages written from now on.
+    pub fn tail<P: traits::core::Positions>(message_bus: &P) -> Cursor {
+        Cursor::at(message_bus.write_head())
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn seek(&mut self, position: usize) {
+        self.position = position;
+    }
+
+    /// The message at the cursor, advancing past it; `Ok(None)` if it has
+    /// not been written yet.
+    pub fn next<'a, R: traits::core::Reader>(
+        &mut self,
+        message_bus: &'a R,
+    ) -> Result<Option<(&'a messenger::Header, &'a [u8])>, messenger::Lapped> {
+        let message = message_bus.try_read(self.position)?;
+        if let Some((header, _)) = message {
+            self.position += header.slot_len();
+        }
+        Ok(message)
+    }
+
+    /// Iterates over the messages of type `M` sent by `source`, decoded with
+    /// `decode`, usually `M::deserialize_from`.
+    pub fn subscribe<'a, R: traits::core::Reader, M: traits::core::Message>(
+        self,
+        message_bus: &'a R,
+        source: impl Into<u16>,
+        decode: fn(&'a [u8]) -> M,
+    ) -> Subscription<'a, R, M, M> {
+        Subscription::new(message_bus, self, source.into(), decode)
+    }
+
+    /// Like [`subscribe`](Cursor::subscribe), for zero-copy messages whose
+    /// `deserialize_from` returns a reference into the bus.
+    pub fn subscribe_zero_copy<'a, R: traits::core::Reader, M: traits::core::Message>(
+        self,
+        message_bus: &'a R,
+        source: impl Into<u16>,
+        decode: fn(&'a [u8]) -> &'a M,
+    ) -> Subscription<'a, R, M, &'a M> {
+        Subscription::new(message_bus, self, source.into(), decode)
+    }
+}
+
+/// Iterator over the messages `M` of one source on a bus, decoded to `T`;
+/// built with [`Cursor::subscribe`] or [`Cursor::subscribe_zero_copy`].
+///
+/// `next` returns `None` once it has caught up with the writers; calling it
+/// again later picks up newer messages. A lapped subscription yields
+/// `Err(Lapped)` until its cursor is moved with
+/// [`cursor_mut`](Subscription::cursor_mut).
+pub struct Subscription<'a, R, M, T> {
+    message_bus: &'a R,
+    cursor: Cursor,
+    source: u16,
+    decode: fn(&'a [u8]) -> T,
+    _message: std::marker::PhantomData<fn() -> M>,
+}
+
+impl<'a, R: traits::core::Reader, M: traits::core::Message, T> Subscription<'a, R, M, T> {
+    fn new(
+        message_bus: &'a R,
+        cursor: Cursor,
+        source: u16,
+        decode: fn(&'a [u8]) -> T,
+    ) -> Subscription<'a, R, M, T> {
+        Subscription {
+            message_bus,
+            cursor,
+            source,
+            decode,
+            _message: std::marker::PhantomData,
+        }
+    }
+
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    pub fn cursor_mut(&mut self) -> &mut Cursor {
+        &mut self.cursor
+    }
+}
+
+impl<'a, R: traits::core::Reader, M: traits::core::Message, T> Iterator
+    for Subscription<'a, R, M, T>
+{
+    type Item = Result<T, messenger::Lapped>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.cursor.next(self.message_bus) {
+                Err(lapped) => return Some(Err(lapped)),
+                Ok(None) => return None,
+                Ok(Some((header, buffer))) => {
+                    if header.source == self.source
+                        && header.message_id == Into::<u16>::into(M::ID)
+                    {
+                        return Some(Ok((self.decode)(buffer)));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_bus::atomic_circular_bus::{CircularBus, Config};
+
+    struct Cfg;
+    impl Config for Cfg {
+        fn get_buffer_size(&self) -> usize {
+            4096
+        }
+    }
+
+    struct A;
+    impl traits::core::Handler for A {
+        type Id = u16;
+        const ID: u16 = 1;
+    }
+    struct B;
+    impl traits::core::Handler for B {
+        type Id = u16;
+        const ID: u16 = 2;
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[repr(C)]
+    struct Num(u64);
+    impl traits::core::Message for Num {
+        type Id = u16;
+        const ID: u16 = 7;
+    }
+    impl traits::zero_copy::ZeroCopyMessage for Num {}
+    impl traits::extended::ExtendedMessage for Num {
+        fn get_size(&self) -> usize {
+            8
+        }
+        fn write_into(&self, buffer: &mut [u8]) {
+            buffer[..8].copy_from_slice(&self.0.to_ne_bytes());
+        }
+    }
+    impl Num {
+        fn deserialize_from(buffer: &[u8]) -> Num {
+            Num(u64::from_ne_bytes(buffer[..8].try_into().unwrap()))
+        }
+        fn view(buffer: &[u8]) -> &Num {
+            unsafe { &*(buffer.as_ptr() as *const Num) }
+        }
+    }
+
+    struct Other;
+    impl traits::core::Message for Other {
+        type Id = u16;
+        const ID: u16 = 8;
+    }
+    impl traits::extended::ExtendedMessage for Other {
+        fn get_size(&self) -> usize {
+            0
+        }
+        fn write_into(&self, _buffer: &mut [u8]) {}
+    }
+
+    fn send<H: traits::core::Handler, M: traits::extended::ExtendedMessage>(bus: &CircularBus, message: &M) {
+        <H as traits::extended::Sender>::send(message, bus);
+    }
+
+    #[test]
+    fn subscription_filters_by_source_and_message() {
+        let bus = CircularBus::new(&Cfg);
+        send::<A, _>(&bus, &Num(1));
+        send::<B, _>(&bus, &Num(2));
+        send::<A, _>(&bus, &Other);
+        send::<A, _>(&bus, &Num(3));
+
+        let mut from_a = Cursor::new().subscribe(&bus, 1u16, Num::deserialize_from);
+        assert_eq!(from_a.next(), Some(Ok(Num(1))));
+        assert_eq!(from_a.next(), Some(Ok(Num(3))));
+        assert_eq!(from_a.next(), None);
+
+        send::<A, _>(&bus, &Num(4));
+        assert_eq!(from_a.next(), Some(Ok(Num(4))), "picks up later messages");
+
+        let from_b: Vec<_> = Cursor::new()
+            .subscribe_zero_copy(&bus, 2u16, Num::view)
+            .map(|num| *num.unwrap())
+            .collect();
+        assert_eq!(from_b, [Num(2)]);
+    }
+
+    #[test]
+    fn tail_cursor_sees_only_new_messages() {
+        let bus = CircularBus::new(&Cfg);
+        send::<A, _>(&bus, &Num(1));
+
+        let mut cursor = Cursor::tail(&bus);
+        assert!(matches!(cursor.next(&bus), Ok(None)));
+        send::<A, _>(&bus, &Num(2));
+        let (_, buffer) = cursor.next(&bus).unwrap().unwrap();
+        assert_eq!(Num::deserialize_from(buffer), Num(2));
+        assert_eq!(cursor.position(), traits::core::Positions::write_head(&bus));
+    }
+
+    #[test]
+    fn lapped_cursor_reports_instead_of_panicking() {
+        let bus = CircularBus::new(&Cfg);
+        let mut cursor = Cursor::new();
+        // 2048 bytes of ring, 24 byte slots: message 256 overwrites slot 0.
+        for i in 0..300 {
+            send::<A, _>(&bus, &Num(i));
+        }
+
+        let Err(lapped) = cursor.next(&bus) else {
+            panic!("expected a lap");
+        };
+        assert_eq!(lapped.position, 0);
+        assert_eq!(cursor.position(), 0, "a lapped cursor stays put");
+
+        cursor.seek(lapped.write_head);
+        assert!(matches!(cursor.next(&bus), Ok(None)));
+    }
+}
@@ -0,0 +1,331 @@
+use crate::bus_file;
+use crate::traits;
+
+/// Dumps the messages a bus still holds to a file, so that the traffic
+/// leading up to a crash survives it.
+///
+/// A `CircularBus` keeps the recent traffic, but only in memory. A
+/// [`dump`](FlightRecorder::dump) copies every slot still committed in the
+/// ring, oldest first, into a file in the format of `ExtendingBus`: opening
+/// the file as an `ExtendingBus` (with the
+/// [minimum page length](FlightRecorder::min_page_len) and the
+/// [schema id](FlightRecorder::schema_id) of the dump) replays the recorded
+/// messages from position 0, with their original source and message ids.
+///
+/// Dumps happen on an explicit call, on panics once
+/// [`dump_on_panic`](FlightRecorder::dump_on_panic) is installed, and on a
+/// signal with [`dump_on_signal`](FlightRecorder::dump_on_signal). Each dump
+/// replaces the previous file.
+#[derive(Clone)]
+pub struct FlightRecorder<B> {
+    bus: B,
+    path: std::path::PathBuf,
+    last_bytes: Option<usize>,
+    min_page_len: usize,
+    schema_id: u64,
+}
+
+impl<B: traits::core::MessageBus + traits::core::Positions> FlightRecorder<B> {
+    /// Records `bus` into the file at `path`.
+    pub fn new(bus: B, path: impl Into<std::path::PathBuf>) -> FlightRecorder<B> {
+        FlightRecorder {
+            bus,
+            path: path.into(),
+            last_bytes: None,
+            min_page_len: 1,
+            schema_id: 0,
+        }
+    }
+
+    /// Dumps only the messages within the last `bytes` bus positions
+    /// instead of everything the bus still holds.
+    pub fn last(mut self, bytes: usize) -> FlightRecorder<B> {
+        self.last_bytes = Some(bytes);
+        self
+    }
+
+    /// Lays the dumps out in pages for a bus configured with `min_page_len`
+    /// as its `get_min_page_len`; 1 by default, for the system page size.
+    pub fn min_page_len(mut self, min_page_len: usize) -> FlightRecorder<B> {
+        self.min_page_len = min_page_len;
+        self
+    }
+
+    /// Records `schema_id` in the file header of the dumps, as a bus
+    /// configured with it expects; 0 by default.
+    pub fn schema_id(mut self, schema_id: u64) -> FlightRecorder<B> {
+        self.schema_id = schema_id;
+        self
+    }
+
+    /// Writes the messages still held by the bus to the file and returns
+    /// how many it wrote. Writers may carry on meanwhile; what they write
+    /// after the dump started is not included. Slots the bus cannot read
+    /// are stepped over with [`Positions::skip`](traits::core::Positions::skip);
+    /// the dump ends at one it cannot step over either.
+    ///
+    /// The file is written next to its final path and renamed over it, so a
+    /// reader never sees a half-written dump.
+    pub fn dump(&self) -> std::io::Result<usize> {
+        let page_size = bus_file::page_size_for(self.min_page_len)?;
+        let write_head = self.bus.write_head();
+        let from = write_head.saturating_sub(self.last_bytes.unwrap_or(usize::MAX));
+
+        bus_file::write_atomically(&self.path, page_size, self.schema_id, |file| {
+            let mut count = 0;
+            let mut position = self.bus.oldest();
+            while position < write_head {
+                match self.bus.try_read(position) {
+                    Ok(Some((header, payload))) => {
+                        if position >= from {
+                            file.write(
+                                header.source,
+                                header.message_id,
+                                bus_file::timestamp_of(header),
+                                payload,
+                            )?;
+                            count += 1;
+                        }
+                        position += header.slot_len();
+                    }
+                    // An in-flight slot, or one whose writer panicked: step
+                    // over it once its length is known.
+                    Ok(None) => match self.bus.skip(position) {
+                        Some(next) => position = next,
+                        None if self.bus.oldest() > position => position = self.bus.oldest(),
+                        // Its writer has not written the header yet, or the
+                        // bus cannot tell the slot's length: what follows
+                        // cannot be found.
+                        None => break,
+                    },
+                    // Overwritten while dumping.
+                    Err(_) => position = position.max(self.bus.oldest()),
+                }
+            }
+            Ok(count)
+        })
+    }
+
+    /// Dumps on a panic, after the previously installed panic hook ran, and
+    /// passes the result of the dump to `on_dump`.
+    ///
+    /// A panic hook cannot tell whether a supervised worker will recover
+    /// from the panic, so panics are dumped at most once per `min_interval`:
+    /// a handler panicking on every message does not rewrite the file for
+    /// each. The first panic of a burst is dumped, and the dump holds the
+    /// traffic leading up to it.
+    pub fn dump_on_panic<F>(&self, min_interval: std::time::Duration, on_dump: F)
+    where
+        F: Fn(std::io::Result<usize>) + Send + Sync + 'static,
+    {
+        let recorder = self.clone();
+        let limit = RateLimit::new(min_interval);
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            previous(info);
+            if limit.allow(std::time::Instant::now()) {
+                on_dump(recorder.dump());
+            }
+        }));
+    }
+
+    /// Dumps whenever the process receives `signal`, e.g. `libc::SIGUSR1`
+    /// for an operator to take a snapshot of a running process.
+    ///
+    /// The dump runs on a thread named `flight-recorder`, not in the signal
+    /// handler, and passes its result to `on_dump` there. The signal no
+    /// longer has its default effect, so installing this for `SIGTERM` or
+    /// `SIGINT` keeps them from terminating the process.
+    #[cfg(unix)]
+    pub fn dump_on_signal<F>(&self, signal: libc::c_int, on_dump: F) -> std::io::Result<()>
+    where
+        F: Fn(std::io::Result<usize>) + Send + 'static,
+    {
+        let recorder = self.clone();
+        signals::register(signal, Box::new(move || on_dump(recorder.dump())))
+    }
+}
+
+/// Lets through at most one event per interval.
+struct RateLimit {
+    interval: std::time::Duration,
+    last: std::sync::Mutex<Option<std::time::Instant>>,
+}
+
+impl RateLimit {
+    fn new(interval: std::time::Duration) -> RateLimit {
+        RateLimit {
+            interval,
+            last: std::sync::Mutex::new(None),
+        }
+    }
+
+    fn allow(&self, now: std::time::Instant) -> bool {
+        let mut last = self.last.lock().unwrap_or_else(|e| e.into_inner());
+        match *last {
+            Some(at) if now.saturating_duration_since(at) < self.interval => false,
+            _ => {
+                *last = Some(now);
+                true
+            }
+        }
+    }
+}
+
+/// Signal handlers may only do async-signal-safe work, so the handler writes
+/// the signal number into a pipe and a watcher thread runs the dumps.
+#[cfg(unix)]
+mod signals {
+    use std::os::fd::FromRawFd;
+
+    type Dump = Box<dyn Fn() + Send>;
+
+    /// Write end of the pipe, or -1 before the first registration.
+    static WAKE: std::sync::atomic::AtomicI32 = std::sync::atomic::AtomicI32::new(-1);
+    static DUMPS: std::sync::Mutex<Vec<(libc::c_int, Dump)>> = std::sync::Mutex::new(Vec::new());
+
+    pub(super) fn register(signal: libc::c_int, dump: Dump) -> std::io::Result<()> {
+        if !(1..=u8::MAX as libc::c_int).contains(&signal) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("invalid signal {signal}"),
+            ));
+        }
+        // Also serializes starting the watcher.
+        let mut dumps = lock();
+        if WAKE.load(std::sync::atomic::Ordering::Acquire) < 0 {
+            let mut fds = [0; 2];
+            if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            for fd in fds {
+                unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+            }
+            let pipe = unsafe { std::fs::File::from_raw_fd(fds[0]) };
+            std::thread::Builder::new()
+                .name("flight-recorder".to_string())
+                .spawn(move || watch(pipe))?;
+            WAKE.store(fds[1], std::sync::atomic::Ordering::Release);
+        }
+        if !dumps.iter().any(|(registered, _)| *registered == signal) {
+            let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+            action.sa_sigaction = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            unsafe { libc::sigemptyset(&mut action.sa_mask) };
+            if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+        }
+        dumps.push((signal, dump));
+        Ok(())
+    }
+
+    extern "C" fn on_signal(signal: libc::c_int) {
+        let byte = signal as u8;
+        // The interrupted code may be about to read errno, which the write
+        // can change.
+        let errno = unsafe { *errno_location() };
+        unsafe {
+            libc::write(
+                WAKE.load(std::sync::atomic::Ordering::Acquire),
+                &byte as *const u8 as *const libc::c_void,
+                1,
+            );
+            *errno_location() = errno;
+        }
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "emscripten", target_os = "redox"))]
+    pub(super) fn errno_location() -> *mut libc::c_int {
+        unsafe { libc::__errno_location() }
+    }
+
+    #[cfg(any(target_os = "android", target_os = "netbsd", target_os = "openbsd"))]
+    pub(super) fn errno_location() -> *mut libc::c_int {
+        unsafe { libc::__errno() }
+    }
+
+    #[cfg(any(target_vendor = "apple", target_os = "freebsd", target_os = "dragonfly"))]
+    pub(super) fn errno_location() -> *mut libc::c_int {
+        unsafe { libc::__error() }
+    }
+
+    #[cfg(any(target_os = "solaris", target_os = "illumos"))]
+    pub(super) fn errno_location() -> *mut libc::c_int {
+        unsafe { libc::___errno() }
+    }
+
+    fn watch(mut pipe: std::fs::File) {
+        use std::io::Read;
+
+        let mut signal = [0];
+        while pipe.read_exact(&mut signal).is_ok() {
+            for (registered, dump) in lock().iter() {
+                if *registered == signal[0] as libc::c_int {
+                    dump();
+                }
+            }
+        }
+    }
+
+    fn lock() -> std::sync::MutexGuard<'static, Vec<(libc::c_int, Dump)>> {
+        DUMPS.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::message_bus::atomic_circular_bus;
+    use crate::messenger;
+    use crate::mmap::anonymous_mmap;
+    use crate::traits::core::RawWriter;
+
+    struct Config;
+
+    impl atomic_circular_bus::Config for Config {
+        fn get_buffer_size(&self) -> usize {
+            16384
+        }
+    }
+
+    #[test]
+    fn signal_triggers_a_dump() {
+        let path = std::env::temp_dir().join(format!(
+            "rust-messenger-signal-{}.bus",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let bus = atomic_circular_bus::CircularBus::new(&Config);
+        bus.write_raw(1, 2, 4, |buffer| buffer[..4].copy_from_slice(&[7; 4]));
+        let (dumped, dumps) = std::sync::mpsc::channel();
+        FlightRecorder::new(bus, &path)
+            .dump_on_signal(libc::SIGUSR1, move |result| dumped.send(result.unwrap()).unwrap())
+            .unwrap();
+
+        // The handler leaves errno as the interrupted code had it.
+        unsafe { *signals::errno_location() = libc::EAGAIN };
+        assert_eq!(unsafe { libc::raise(libc::SIGUSR1) }, 0);
+        assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::EAGAIN));
+        let count = dumps.recv_timeout(std::time::Duration::from_secs(10)).expect("no dump written");
+        assert_eq!(count, 1);
+        let dump = std::fs::read(&path).unwrap();
+        let page_size = anonymous_mmap::page_size();
+        assert_eq!(dump.len() % page_size, 0);
+        assert_eq!(bus_file::FileHeader::parse(&dump).unwrap().page_size, page_size);
+        assert_eq!(&dump[page_size + messenger::ALIGNED_HEADER_SIZE..][..4], &[7; 4]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn panic_dumps_are_rate_limited() {
+        let limit = RateLimit::new(std::time::Duration::from_secs(60));
+        let start = std::time::Instant::now();
+        assert!(limit.allow(start));
+        assert!(!limit.allow(start + std::time::Duration::from_secs(59)));
+        assert!(limit.allow(start + std::time::Duration::from_secs(60)));
+
+        let every = RateLimit::new(std::time::Duration::ZERO);
+        assert!(every.allow(start) && every.allow(start));
+    }
+}
@@ -0,0 +1,763 @@
+//! Looking inside `ExtendingBus` files without opening them as a bus: the
+//! library half of the `messenger-inspect` binary.
+//!
+//! [`BusFile`] maps a bus file and walks its slots, tombstones and holes. [`run`] is the
+//! command line of `messenger-inspect`; an application that wants its own
+//! message types printed as JSON builds its own inspector binary around it
+//! with a [`Decoders`] registry:
+//!
+//! ```ignore
+//! fn main() -> std::process::ExitCode {
+//!     let decoders = inspect::Decoders::new()
+//!         .register(HandlerId::Gateway, MessageId::Order, "Order", |payload| {
+//!             let order = Order::deserialize_from(payload);
+//!             format!(r#"{{"id":{},"qty":{}}}"#, order.id, order.qty)
+//!         });
+//!     inspect::main(&decoders)
+//! }
+//! ```
+
+use crate::bus_file;
+use crate::messenger;
+
+/// A bus file, mapped read-only. Reading never modifies the file, so it is
+/// safe on the file of a running bus; messages committed after `open` are
+/// seen only if they fit in the length the file had then.
+pub struct BusFile {
+    header: bus_file::FileHeader,
+    /// The whole file, header page included.
+    bytes: Bytes,
+}
+
+enum Bytes {
+    #[cfg(unix)]
+    Mapped(crate::mmap::read_only_mmap::ReadOnlyMmap),
+    Owned(Vec<u8>),
+}
+
+impl std::ops::Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            #[cfg(unix)]
+            Bytes::Mapped(map) => map.as_slice(),
+            Bytes::Owned(bytes) => bytes,
+        }
+    }
+}
+
+/// What [`BusFile::entries`] finds at a position.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry<'a> {
+    /// A committed message.
+    Slot(Slot<'a>),
+    /// A message that was never committed.
+    Tombstone(Tombstone),
+    /// Bytes that are neither a message nor a tombstone although messages
+    /// follow them, or non-zero bytes after the last message: a writer
+    /// that crashed while writing its header, or a damaged file.
+    Hole { position: usize, len: usize },
+}
+
+impl Entry<'_> {
+    pub fn position(&self) -> usize {
+        match *self {
+            Entry::Slot(ref slot) => slot.position,
+            Entry::Tombstone(ref tombstone) => tombstone.position,
+            Entry::Hole { position, .. } => position,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slot<'a> {
+    pub position: usize,
+    pub source: u16,
+    pub message_id: u16,
+    /// See `Header::timestamp`.
+    #[cfg(feature = "timestamps")]
+    pub timestamp: u64,
+    pub payload: &'a [u8],
+}
+
+impl Slot<'_> {
+    /// Bytes the slot occupies, header and padding included.
+    pub fn len(&self) -> usize {
+        messenger::ALIGNED_HEADER_SIZE + messenger::align_to_usize(self.payload.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.payload.is_empty()
+    }
+}
+
+/// A message whose header was written but that was never committed: its
+/// writer crashed or panicked mid-message, or is still writing. Readers skip
+/// it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tombstone {
+    pub position: usize,
+    pub source: u16,
+    pub message_id: u16,
+    /// The payload length the header announces.
+    pub size: usize,
+}
+
+impl Tombstone {
+    /// Bytes the slot occupies, header and padding included.
+    pub fn len(&self) -> usize {
+        messenger::ALIGNED_HEADER_SIZE + messenger::align_to_usize(self.size)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+/// The fields of the slot header at some position, as raw as the file holds
+/// them.
+struct RawHeader {
+    source: u16,
+    message_id: u16,
+    size: usize,
+    #[cfg(feature = "timestamps")]
+    timestamp: u64,
+    commit_stamp: u64,
+    /// Whether every header byte is zero: free space.
+    zeroed: bool,
+}
+
+impl BusFile {
+    /// Maps the file read-only, so that even a large recording is read
+    /// page by page as it is walked. Fails with an `InvalidData` error
+    /// wrapping a [`FileHeaderError`](bus_file::FileHeaderError) if this
+    /// build cannot read the file.
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<BusFile> {
+        #[cfg(unix)]
+        {
+            let file = std::fs::File::open(path)?;
+            let map = crate::mmap::read_only_mmap::ReadOnlyMmap::new(&file)?;
+            BusFile::new(Bytes::Mapped(map))
+        }
+        // Bus files are only written on Unix; elsewhere read them whole.
+        #[cfg(not(unix))]
+        BusFile::from_bytes(std::fs::read(path)?)
+    }
+
+    /// A bus file already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> std::io::Result<BusFile> {
+        BusFile::new(Bytes::Owned(bytes))
+    }
+
+    fn new(bytes: Bytes) -> std::io::Result<BusFile> {
+        let header = bus_file::FileHeader::parse(&bytes)?;
+        if bytes.len() < header.page_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the bus file is shorter than its header page",
+            ));
+        }
+        Ok(BusFile { header, bytes })
+    }
+
+    pub fn header(&self) -> &bus_file::FileHeader {
+        &self.header
+    }
+
+    /// The slots, without the header page.
+    fn slots(&self) -> &[u8] {
+        &self.bytes[self.header.page_size..]
+    }
+
+    /// Length of the slots, free space at the end included.
+    pub fn len(&self) -> usize {
+        self.slots().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots().is_empty()
+    }
+
+    fn raw_header(&self, position: usize) -> Option<RawHeader> {
+        let prefix = self
+            .slots()
+            .get(position..position.checked_add(messenger::ALIGNED_HEADER_SIZE)?)?;
+        let field = |offset: usize, len: usize| &prefix[offset..offset + len];
+        Some(RawHeader {
+            source: u16::from_ne_bytes(
+                field(std::mem::offset_of!(messenger::Header, source), 2)
+                    .try_into()
+                    .unwrap(),
+            ),
+            message_id: u16::from_ne_bytes(
+                field(std::mem::offset_of!(messenger::Header, message_id), 2)
+                    .try_into()
+                    .unwrap(),
+            ),
+            size: u32::from_ne_bytes(
+                field(std::mem::offset_of!(messenger::Header, size), 4)
+                    .try_into()
+                    .unwrap(),
+            ) as usize,
+            #[cfg(feature = "timestamps")]
+            timestamp: u64::from_ne_bytes(
+                field(std::mem::offset_of!(messenger::Header, timestamp), 8)
+                    .try_into()
+                    .unwrap(),
+            ),
+            commit_stamp: u64::from_ne_bytes(
+                field(std::mem::offset_of!(messenger::Header, commit_stamp), 8)
+                    .try_into()
+                    .unwrap(),
+            ),
+            zeroed: prefix.iter().all(|&byte| byte == 0),
+        })
+    }
+
+    /// The position after the padded slot of `header` at `position`, if the
+    /// whole slot is in the file.
+    fn slot_end(&self, position: usize, header: &RawHeader) -> Option<usize> {
+        let end = position
+            .checked_add(messenger::ALIGNED_HEADER_SIZE)?
+            .checked_add(messenger::align_to_usize(header.size))?;
+        (end <= self.len()).then_some(end)
+    }
+
+    /// The committed message at `position`, if there is one.
+    pub fn slot(&self, position: usize) -> Option<Slot<'_>> {
+        let header = self.raw_header(position)?;
+        if header.commit_stamp != messenger::Header::commit_stamp_for(position) {
+            return None;
+        }
+        self.slot_end(position, &header)?;
+        let start = position + messenger::ALIGNED_HEADER_SIZE;
+        Some(Slot {
+            position,
+            source: header.source,
+            message_id: header.message_id,
+            #[cfg(feature = "timestamps")]
+            timestamp: header.timestamp,
+            payload: &self.slots()[start..start + header.size],
+        })
+    }
+
+    /// The tombstone at `position`, if there is one: an uncommitted header
+    /// whose slot ends where another message or the free space starts,
+    /// possibly across further tombstones.
+    pub fn tombstone(&self, position: usize) -> Option<Tombstone> {
+        let tombstone = self.uncommitted(position)?;
+        // Trust the length the header announces only if it leads somewhere.
+        let mut end = position + tombstone.len();
+        loop {
+            if end == self.len()
+                || self.slot(end).is_some()
+                || self.raw_header(end).is_some_and(|next| next.zeroed)
+            {
+                return Some(tombstone);
+            }
+            end += self.uncommitted(end)?.len();
+        }
+    }
+
+    /// The header at `position`, if it was written but never committed and
+    /// its slot fits in the file.
+    fn uncommitted(&self, position: usize) -> Option<Tombstone> {
+        let header = self.raw_header(position)?;
+        let uncommitted = header.commit_stamp == 0
+            || header.commit_stamp == messenger::Header::sized_stamp_for(position);
+        if !uncommitted || header.zeroed {
+            return None;
+        }
+        self.slot_end(position, &header)?;
+        Some(Tombstone {
+            position,
+            source: header.source,
+            message_id: header.message_id,
+            size: header.size,
+        })
+    }
+
+    /// Every message, tombstone and hole, in file order. Zeroed space after
+    /// the last message is free, and not reported.
+    pub fn entries(&self) -> impl Iterator<Item = Entry<'_>> {
+        let mut position = 0;
+        std::iter::from_fn(move || {
+            if let Some(slot) = self.slot(position) {
+                position += slot.len();
+                return Some(Entry::Slot(slot));
+            }
+            if let Some(tombstone) = self.tombstone(position) {
+                position += tombstone.len();
+                return Some(Entry::Tombstone(tombstone));
+            }
+            // Slots start usize-aligned; find the next message or tombstone.
+            let len = self.len();
+            let start = position;
+            let step = std::mem::size_of::<usize>();
+            let mut next = start + step;
+            while next < len && self.slot(next).is_none() && self.tombstone(next).is_none() {
+                next += step;
+            }
+            let next = next.min(len);
+            position = next;
+            let rest = self.slots().get(start..next)?;
+            if next == len && rest.iter().all(|&byte| byte == 0) {
+                return None;
+            }
+            Some(Entry::Hole {
+                position: start,
+                len: next - start,
+            })
+        })
+    }
+}
+
+/// Count and size of the messages of one `(source, message_id)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub count: usize,
+    pub bytes: usize,
+    pub min_size: usize,
+    pub max_size: usize,
+    pub first_position: usize,
+    pub last_position: usize,
+}
+
+/// [`Stats`] of every `(source, message_id)` in `entries`.
+pub fn stats<'a>(
+    entries: impl IntoIterator<Item = Entry<'a>>,
+) -> std::collections::BTreeMap<(u16, u16), Stats> {
+    let mut stats = std::collections::BTreeMap::<_, Stats>::new();
+    for entry in entries {
+        let Entry::Slot(slot) = entry else {
+            continue;
+        };
+        let size = slot.payload.len();
+        let stats = stats
+            .entry((slot.source, slot.message_id))
+            .or_insert_with(|| Stats {
+                min_size: size,
+                first_position: slot.position,
+                ..Stats::default()
+            });
+        stats.count += 1;
+        stats.bytes += size;
+        stats.min_size = stats.min_size.min(size);
+        stats.max_size = stats.max_size.max(size);
+        stats.last_position = slot.position;
+    }
+    stats
+}
+
+/// Decoders from payloads to JSON, by `(source, message_id)`, for the
+/// message types an inspector knows. Message ids are per source: two
+/// sources may use one id for different types.
+#[derive(Default)]
+pub struct Decoders {
+    by_id: std::collections::HashMap<(u16, u16), (&'static str, Decode)>,
+}
+
+/// Renders a payload as JSON.
+pub type Decode = fn(&[u8]) -> String;
+
+impl Decoders {
+    pub fn new() -> Decoders {
+        Decoders::default()
+    }
+
+    /// Prints the payloads of `message_id` from `source` as the JSON
+    /// `decode` returns, and names them `name` in the summary.
+    pub fn register(
+        mut self,
+        source: impl Into<u16>,
+        message_id: impl Into<u16>,
+        name: &'static str,
+        decode: Decode,
+    ) -> Decoders {
+        self.by_id
+            .insert((source.into(), message_id.into()), (name, decode));
+        self
+    }
+
+    pub fn name(&self, source: u16, message_id: u16) -> Option<&'static str> {
+        self.by_id
+            .get(&(source, message_id))
+            .map(|&(name, _)| name)
+    }
+
+    /// The payload as JSON, if its source and message id have a decoder.
+    pub fn decode(&self, source: u16, message_id: u16, payload: &[u8]) -> Option<String> {
+        self.by_id
+            .get(&(source, message_id))
+            .map(|&(_, decode)| decode(payload))
+    }
+}
+
+/// `bytes` as lines of 16, offset and hex first, printable ASCII last.
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        dump.push_str(&format!("{:08x} ", line * 16));
+        for column in 0..16 {
+            match chunk.get(column) {
+                Some(byte) => dump.push_str(&format!(" {byte:02x}")),
+                None => dump.push_str("   "),
+            }
+        }
+        dump.push_str("  |");
+        dump.extend(chunk.iter().map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        }));
+        dump.push_str("|\n");
+    }
+    dump
+}
+
+const USAGE: &str = "\
+usage: messenger-inspect [options] <bus-file>
+
+Lists the messages of an ExtendingBus file, one line per slot:
+position, source, message id, payload size and commit state. Tombstones,
+messages never committed, and holes, bytes that are neither, are listed
+too.
+
+options:
+  --summary          count and size per (source, message id) instead
+  --holes            list only tombstones and holes
+  --hex              hex-dump every payload
+  --time             show when each message was written (needs a build
+                     with the timestamps feature)
+  --source <id>      only messages from this source
+  --message-id <id>  only messages with this id
+  --from <position>  only slots at or after this position
+  --to <position>    only slots before this position
+";
+
+/// The options of one `messenger-inspect` run.
+#[derive(Debug, Default)]
+struct Options {
+    path: Option<std::path::PathBuf>,
+    summary: bool,
+    holes: bool,
+    hex: bool,
+    time: bool,
+    source: Option<u16>,
+    message_id: Option<u16>,
+    from: usize,
+    to: Option<usize>,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        fn number<T: std::str::FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+            let value = value.ok_or_else(|| format!("{flag} needs a value"))?;
+            value
+                .parse()
+                .map_err(|_| format!("{flag}: not a number: {value}"))
+        }
+
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--summary" => options.summary = true,
+                "--holes" => options.holes = true,
+                "--hex" => options.hex = true,
+                "--time" => options.time = true,
+                "--source" => options.source = Some(number(&arg, args.next())?),
+                "--message-id" => options.message_id = Some(number(&arg, args.next())?),
+                "--from" => options.from = number(&arg, args.next())?,
+                "--to" => options.to = Some(number(&arg, args.next())?),
+                flag if flag.starts_with("--") => return Err(format!("unknown option {flag}")),
+                _ if options.path.is_none() => options.path = Some(arg.into()),
+                _ => return Err(format!("unexpected argument {arg}")),
+            }
+        }
+        if options.path.is_none() {
+            return Err("no bus file given".to_string());
+        }
+        if options.time && !cfg!(feature = "timestamps") {
+            return Err("--time needs a build with the timestamps feature".to_string());
+        }
+        Ok(options)
+    }
+
+    fn selects(&self, entry: &Entry) -> bool {
+        let position = entry.position();
+        if position < self.from || self.to.is_some_and(|to| position >= to) {
+            return false;
+        }
+        match entry {
+            Entry::Slot(slot) => {
+                !self.holes
+                    && self.source.is_none_or(|source| source == slot.source)
+                    && self.message_id.is_none_or(|id| id == slot.message_id)
+            }
+            Entry::Tombstone(tombstone) => {
+                self.source.is_none_or(|source| source == tombstone.source)
+                    && self.message_id.is_none_or(|id| id == tombstone.message_id)
+            }
+            Entry::Hole { .. } => true,
+        }
+    }
+}
+
+/// Runs `messenger-inspect` with the command line `args` (without the
+/// program name), writing the listing to `out`. Errors are usage errors or
+/// failures to read the file, ready to print.
+pub fn run(
+    args: impl Iterator<Item = String>,
+    decoders: &Decoders,
+    out: &mut impl std::io::Write,
+) -> Result<(), String> {
+    let options = Options::parse(args).map_err(|e| format!("{e}\n\n{USAGE}"))?;
+    let path = options.path.as_deref().expect("parse requires a path");
+    let file = BusFile::open(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let entries = file.entries().filter(|entry| options.selects(entry));
+    let failed = |e: std::io::Error| e.to_string();
+
+    if options.summary {
+        writeln!(out, "{:>6} {:>10} {:>10} {:>12} {:>8} {:>8}  name", "source", "message_id", "count", "bytes", "min", "max")
+            .map_err(failed)?;
+        for ((source, message_id), stats) in stats(entries) {
+            writeln!(
+                out,
+                "{source:>6} {message_id:>10} {:>10} {:>12} {:>8} {:>8}  {}",
+                stats.count,
+                stats.bytes,
+                stats.min_size,
+                stats.max_size,
+                decoders.name(source, message_id).unwrap_or("-"),
+            )
+            .map_err(failed)?;
+        }
+        return Ok(());
+    }
+
+    writeln!(out, "{:>12} {:>6} {:>10} {:>8}  state", "position", "source", "message_id", "size").map_err(failed)?;
+    for entry in entries {
+        match entry {
+            Entry::Slot(slot) => {
+                let decoded = decoders
+                    .decode(slot.source, slot.message_id, slot.payload)
+                    .map(|json| format!("  {json}"))
+                    .unwrap_or_default();
+                writeln!(
+                    out,
+                    "{:>12} {:>6} {:>10} {:>8}  committed{}{decoded}",
+                    slot.position,
+                    slot.source,
+                    slot.message_id,
+                    slot.payload.len(),
+                    written_at(&slot, options.time),
+                )
+                .map_err(failed)?;
+                if options.hex {
+                    write!(out, "{}", hex_dump(slot.payload)).map_err(failed)?;
+                }
+            }
+            Entry::Tombstone(tombstone) => {
+                writeln!(
+                    out,
+                    "{:>12} {:>6} {:>10} {:>8}  tombstone",
+                    tombstone.position, tombstone.source, tombstone.message_id, tombstone.size,
+                )
+                .map_err(failed)?;
+            }
+            Entry::Hole { position, len } => {
+                writeln!(out, "{position:>12} {:>6} {:>10} {len:>8}  hole", "-", "-").map_err(failed)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// ` at <seconds>.<nanoseconds>` since the Unix epoch, if asked for.
+fn written_at(slot: &Slot, show: bool) -> String {
+    #[cfg(feature = "timestamps")]
+    if show {
+        return format!(
+            " at {}.{:09}",
+            slot.timestamp / 1_000_000_000,
+            slot.timestamp % 1_000_000_000
+        );
+    }
+    let _ = (slot, show);
+    String::new()
+}
+
+/// `main` of an inspector binary: [`run`] on the process arguments and
+/// standard output, exiting with status 2 on errors.
+pub fn main(decoders: &Decoders) -> std::process::ExitCode {
+    let stdout = std::io::stdout();
+    let mut out = std::io::BufWriter::new(stdout.lock());
+    match run(std::env::args().skip(1), decoders, &mut out) {
+        Ok(()) => std::process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::flight_recorder::FlightRecorder;
+    use crate::message_bus::atomic_circular_bus;
+    use crate::traits::core::RawWriter;
+
+    struct Config;
+
+    impl atomic_circular_bus::Config for Config {
+        fn get_buffer_size(&self) -> usize {
+            16384
+        }
+    }
+
+    /// Every test message fits a slot of this length.
+    const SLOT: usize = messenger::ALIGNED_HEADER_SIZE + 8;
+
+    /// A bus file holding four messages: the header of the second damaged,
+    /// the third never committed.
+    fn damaged_file(name: &str) -> (std::path::PathBuf, BusFile) {
+        let bus = atomic_circular_bus::CircularBus::new(&Config);
+        bus.write_raw(1, 10, 4, |buffer| buffer[..4].copy_from_slice(&7u32.to_ne_bytes()));
+        bus.write_raw(1, 11, 3, |buffer| buffer[..3].copy_from_slice(b"xyz"));
+        bus.write_raw(1, 12, 3, |buffer| buffer[..3].copy_from_slice(b"abc"));
+        bus.write_raw(2, 10, 4, |buffer| buffer[..4].copy_from_slice(&9u32.to_ne_bytes()));
+        let path = std::env::temp_dir().join(format!(
+            "rust-messenger-inspect-{name}-{}.bus",
+            std::process::id()
+        ));
+        FlightRecorder::new(bus, &path).dump().unwrap();
+
+        let mut bytes = std::fs::read(&path).unwrap();
+        let page_size = crate::mmap::anonymous_mmap::page_size();
+        for slot in [page_size + SLOT, page_size + 2 * SLOT] {
+            let stamp = slot + std::mem::offset_of!(messenger::Header, commit_stamp);
+            bytes[stamp..stamp + 8].fill(0);
+        }
+        let size = page_size + SLOT + std::mem::offset_of!(messenger::Header, size);
+        bytes[size..size + 4].fill(0xff);
+        std::fs::write(&path, &bytes).unwrap();
+        let file = BusFile::open(&path).unwrap();
+        (path, file)
+    }
+
+    fn lines(args: &[&str], path: &std::path::Path, decoders: &Decoders) -> Vec<String> {
+        let mut out = Vec::new();
+        let args = args
+            .iter()
+            .map(|arg| arg.to_string())
+            .chain([path.display().to_string()]);
+        run(args, decoders, &mut out).unwrap();
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn entries_report_slots_tombstones_and_holes() {
+        let (path, file) = damaged_file("entries");
+        let entries: Vec<_> = file.entries().collect();
+        assert_eq!(entries.len(), 4);
+        assert!(matches!(&entries[0], Entry::Slot(slot) if slot.position == 0 && slot.source == 1));
+        assert_eq!(entries[1], Entry::Hole { position: SLOT, len: SLOT });
+        assert_eq!(
+            entries[2],
+            Entry::Tombstone(Tombstone {
+                position: 2 * SLOT,
+                source: 1,
+                message_id: 12,
+                size: 3,
+            })
+        );
+        assert!(matches!(&entries[3], Entry::Slot(slot) if slot.position == 3 * SLOT && slot.message_id == 10));
+
+        let stats = stats(entries);
+        assert_eq!(stats.keys().copied().collect::<Vec<_>>(), [(1, 10), (2, 10)]);
+        assert_eq!(stats[&(2, 10)].first_position, 3 * SLOT);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn tombstones_may_follow_each_other_to_the_end_of_the_file() {
+        let bus = atomic_circular_bus::CircularBus::new(&Config);
+        bus.write_raw(1, 10, 4, |buffer| buffer[..4].copy_from_slice(&7u32.to_ne_bytes()));
+        bus.write_raw(1, 11, 3, |buffer| buffer[..3].copy_from_slice(b"abc"));
+        bus.write_raw(1, 12, 3, |buffer| buffer[..3].copy_from_slice(b"xyz"));
+        let path = std::env::temp_dir().join(format!(
+            "rust-messenger-inspect-last-tombstone-{}.bus",
+            std::process::id()
+        ));
+        FlightRecorder::new(bus, &path).dump().unwrap();
+        let mut bytes = std::fs::read(&path).unwrap();
+        for slot in [SLOT, 2 * SLOT] {
+            let stamp = crate::mmap::anonymous_mmap::page_size()
+                + slot
+                + std::mem::offset_of!(messenger::Header, commit_stamp);
+            bytes[stamp..stamp + 8].fill(0);
+        }
+        std::fs::remove_file(path).unwrap();
+
+        let file = BusFile::from_bytes(bytes).unwrap();
+        let entries: Vec<_> = file.entries().collect();
+        assert_eq!(entries.len(), 3);
+        assert!(matches!(&entries[1], Entry::Tombstone(tombstone) if tombstone.position == SLOT));
+        assert!(matches!(&entries[2], Entry::Tombstone(tombstone) if tombstone.message_id == 12));
+    }
+
+    #[test]
+    fn cli_lists_filters_and_decodes() {
+        let (path, _) = damaged_file("cli");
+        let decoders = Decoders::new().register(2u16, 10u16, "Count", |payload| {
+            format!(r#"{{"count":{}}}"#, u32::from_ne_bytes(payload[..4].try_into().unwrap()))
+        });
+
+        assert_eq!(
+            lines(&[], &path, &decoders),
+            [
+                "position source message_id size state".to_string(),
+                "0 1 10 4 committed".to_string(),
+                format!("{SLOT} - - {SLOT} hole"),
+                format!("{} 1 12 3 tombstone", 2 * SLOT),
+                format!(r#"{} 2 10 4 committed {{"count":9}}"#, 3 * SLOT),
+            ]
+        );
+        assert_eq!(
+            lines(&["--summary"], &path, &decoders),
+            [
+                "source message_id count bytes min max name",
+                "1 10 1 4 4 4 -",
+                "2 10 1 4 4 4 Count",
+            ]
+        );
+        assert_eq!(
+            lines(&["--holes", "--source", "1"], &path, &Decoders::new())[1..],
+            [
+                format!("{SLOT} - - {SLOT} hole"),
+                format!("{} 1 12 3 tombstone", 2 * SLOT),
+            ]
+        );
+
+        let mut out = Vec::new();
+        let error = run(["--from".to_string()].into_iter(), &decoders, &mut out).unwrap_err();
+        assert!(error.starts_with("--from needs a value"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hex_dump_shows_offsets_bytes_and_text() {
+        assert_eq!(
+            hex_dump(b"hello, bus\x00\x01"),
+            "00000000  68 65 6c 6c 6f 2c 20 62 75 73 00 01              |hello, bus..|\n"
+        );
+    }
+}
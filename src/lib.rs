@@ -1,5 +1,17 @@
+pub mod archive;
+pub mod bridge;
+pub mod bus_file;
+pub mod clock;
+pub mod cursor;
+pub mod flight_recorder;
+pub mod inspect;
 pub mod macros;
 pub mod message_bus;
 pub mod messenger;
 mod mmap;
+pub mod replay;
+pub mod rpc;
+pub mod simulation;
+pub mod testing;
+pub mod timer;
 pub mod traits;
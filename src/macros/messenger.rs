@@ -26,85 +26,45 @@
 /// }
 /// ```
 ///
-/// generates:
+/// generates (abridged; `...` marks elided code):
 ///
 /// ```ignore
-/// use rust_messenger::messenger;
-/// use rust_messenger::traits;
-/// use rust_messenger::traits::core::Handle;
-/// use rust_messenger::traits::core::Handler;
-/// use rust_messenger::traits::core::Message;
-/// use rust_messenger::traits::core::Router;
-///
-/// pub struct Messenger <M: traits::core::MessageBus> {
+/// pub struct Messenger<M: traits::core::MessageBus> {
 ///     message_bus: M,
 ///     stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+///     // ...
 /// }
 ///
-/// impl rust_messenger::Messenger {
-///     pub fn new(message_bus: M) -> rust_messenger::Messenger {
-///         rust_messenger::Messenger {
-///             message_bus: message_bus,
-///             stop: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
-///         }
-///     }
+/// impl<M: traits::core::MessageBus> Messenger<M> {
+///     pub fn new(message_bus: M) -> Messenger<M> { ... }
 ///
 ///     pub fn run(&self, config: &config::Config) -> messenger::JoinHandles {
-///         let mut handles = Vec::<std::thread::JoinHandle<()>>::new();
-///
+///         // One thread per worker, each reading the bus on its own.
 ///         let mb = self.message_bus.clone();
 ///         let cf = config.clone();
 ///         let st = self.stop.clone();
-///         handles.push(std::thread::spawn(|| WorkerA::run_task(mb, cf, st)));
-///         let mb = self.message_bus.clone();
-///         let cf = config.clone();
-///         let st = self.stop.clone();
-///         handles.push(std::thread::spawn(|| WorkerB::run_task(mb, cf, st)));
-///
-///         messenger::JoinHandlers::new(handles)
+///         handles.push(thread.spawn("WorkerA", replica, move |replica| WorkerA::run_task(mb, cf, st, ...)));
+///         // ... the same for WorkerB
+///         messenger::JoinHandles::new(handles, ...)
 ///     }
 ///
 ///     pub fn stop(&self) {
 ///         self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
-///         println!("Stopping Messenger, Goodbye!");
+///         self.message_bus.on_stop();
 ///     }
 /// }
 ///
-/// struct WorkerA {
-///     position: usize,
-///     handler_a: handlers::HandlerA,
-///     handler_b: handlers::HandlerB,
-///     stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
-/// }
-/// struct WorkerB {
+/// pub struct WorkerA {
 ///     position: usize,
-///     handler_c: handlers::HandlerC,
-///     stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+///     pub handler_a: handlers::HandlerA,
+///     pub handler_b: handlers::HandlerB,
+///     // ...
 /// }
 ///
 /// impl WorkerA {
-///     fn run_task<MB: traits::core::MessageBus>(mut message_bus: MB, config: config::Config, stop: std::sync::Arc<std::sync::atomic::AtomicBool>) {
-///         let mut worker = WorkerA {
-///             position: 0,
-///             handler_a: handlers::HandlerA::new(&config, &message_bus),
-///             handler_b: handlers::HandlerB::new(&config, &message_bus),
-///             stop,
-///         };
-///         worker.run(&mut message_bus)
-///     }
-///
-///     fn run<MB: traits::core::MessageBus>(&mut self, message_bus: &mut MB) {
-///         self.handler_a.on_start(message_bus);
-///         self.handler_b.on_start(message_bus);
+///     pub fn run_on_current_thread<MB: traits::core::MessageBus>(&mut self, message_bus: &MB) {
 ///         loop {
-///             if let Some((header, buffer)) = message_bus.read(self.position) {
-///                 self.position += header.slot_len();
-///                 self.route(&header, &buffer, message_bus);
-///             }
-///
-///             self.handler_a.on_loop(message_bus);
-///             self.handler_b.on_loop(message_bus);
-///
+///             self.poll_once(message_bus);
 ///             if self.stop.load(std::sync::atomic::Ordering::Relaxed) {
 ///                 self.handler_a.on_stop();
 ///                 self.handler_b.on_stop();
@@ -112,91 +72,178 @@
 ///             }
 ///         }
 ///     }
-/// }
 ///
-/// impl WorkerB {
-///     pub fn run_task<MB: traits::core::MessageBus>(mut message_bus: MB, config: config::Config, stop: std::sync::Arc<std::sync::atomic::AtomicBool>) {
-///         let mut worker = WorkerB {
-///             position: 0,
-///             handler_c: handlers::HandlerC::new(&config, &message_bus),
-///             stop,
+///     pub fn poll_once<MB: traits::core::MessageBus>(&mut self, message_bus: &MB) -> bool {
+///         // The first call runs every handler's `on_start`.
+///         let read = if let Some((header, buffer)) = message_bus.read(self.position) {
+///             self.route(&header, &buffer, message_bus);
+///             self.position += header.slot_len();
+///             true
+///         } else {
+///             false
 ///         };
-///         worker.run(&mut message_bus)
-///     }
-///     pub fn run<MB: traits::core::MessageBus>(&mut self, message_bus: &mut MB) {
-///         self.handler_c.on_start(message_bus);
-///         loop {
-///             if let Some((header, buffer)) = message_bus.read(self.position) {
-///                 self.position += header.slot_len();
-///                 self.route(&header, &buffer, message_bus);
-///             }
-///
-///             self.handler_c.on_loop(message_bus);
-///
-///             if self.stop.load(std::sync::atomic::Ordering::Relaxed) {
-///                 self.handler_c.on_stop();
-///                 break;
-///             }
-///         }
+///         self.handler_a.on_loop(message_bus);
+///         self.handler_b.on_loop(message_bus);
+///         read
 ///     }
 /// }
 ///
 /// impl traits::core::Router for WorkerA {
-///     #[inline]
-///     fn route<'a, W: traits::core::Writer>(&mut self, header: &rust_messenger::Header, buffer: &'a [u8], writer: &W) {
+///     fn route<'a, MB: traits::core::MessageBus>(&mut self, header: &messenger::Header, buffer: &'a [u8], message_bus: &MB) {
 ///         match (header.source, header.message_id) {
 ///             (source, message_id)
-///                 if source == handlers::HandlerB::ID.into()
-///                     && message_id == messages::MessageA::ID.into() => {
-///                 let message = <messages::MessageA>::deserialize_from(&buffer);
-///                 self.handler_a.handle(&message, writer);
+///                 if source == Into::<u16>::into(<handlers::HandlerA>::ID)
+///                     && message_id == Into::<u16>::into(<messages::MessageB>::ID) => {
+///                 let message = <messages::MessageB>::deserialize_from(buffer);
+///                 self.handler_b.handle_with_context(&message, &context, message_bus);
 ///             }
-///
 ///             (source, message_id)
-///                 if source == handlers::HandlerA::ID.into()
-///                     && message_id == messages::MessageB::ID.into() => {
-///                 let message = <messages::MessageB>::deserialize_from(&buffer);
-///                 self.handler_b.handle(&message, writer);
+///                 if source == Into::<u16>::into(<handlers::HandlerB>::ID)
+///                     && message_id == Into::<u16>::into(<messages::MessageA>::ID) => {
+///                 let message = <messages::MessageA>::deserialize_from(buffer);
+///                 self.handler_a.handle_with_context(&message, &context, message_bus);
 ///             }
 ///             _ => {}
 ///         }
 ///     }
 /// }
 ///
-/// impl traits::core::Router for WorkerB {
-///     #[inline]
-///     fn route<'a, W: traits::core::Writer>(&mut self, header: &rust_messenger::Header, buffer: &'a [u8], writer: &W) {
-///         match (header.source, header.message_id) {
-///             (source, message_id)
-///                 if source == handlers::HandlerB::ID.into()
-///                     && message_id == messages::MessageA::ID.into() => {
-///                 let message = <messages::MessageA>::deserialize_from(&buffer);
-///                 self.handler_c.handle(&message, writer);
-///             }
-///             _ => {}
-///         }
-///     }
-/// }
+/// // ... and the same for WorkerB, with its `handler_c`.
 /// ```
 ///
 /// to run the Messenger you can do:
 /// ``` ignore
 /// pub fn main() {
-///     let config = Config::new()
-///     let message_bus = rust_messenger::message_bus::CircularBus::new(&config);
-///     let mut messenger = rust_messenger::new(message_bus);
-///     messenger.run(&config);
+///     let config = Config::new();
+///     let message_bus = rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&config);
+///     let messenger = Messenger::new(message_bus);
+///     let handles = messenger.run(&config);
+///     // ... until shutdown:
+///     messenger.stop();
+///     handles.join();
 /// }
 /// ```
 ///
+/// Several instances of one handler type can run side by side with distinct
+/// source ids. Declare them as `name @ id: Type`, where `id` is a `u16`
+/// literal or a braced const expression such as
+/// `{ HandlerId::ConnA.to_u16() }`. Everything an instance sends is stamped
+/// with its instance id instead of `Type::ID`, and routes name an instance
+/// as a source by its name (the macro declares `name` as an alias of
+/// `messenger::Source<id>`):
+/// ``` ignore
+/// rust_messenger::Messenger! {
+///     config::Config,
+///     Connectors:
+///         handlers: [
+///             conn_a @ 10: handlers::Connector,
+///             conn_b @ 11: handlers::Connector,
+///         ]
+///         routes: [
+///             handlers::Strategy, messages::Order: [ conn_a, conn_b ],
+///         ]
+///     Strategies:
+///         handlers: [
+///             strategy: handlers::Strategy,
+///         ]
+///         routes: [
+///             conn_a, messages::Quote: [ strategy ],
+///         ]
+/// }
+/// ```
+///
+/// A worker declared with `replicas: N` runs as N threads. Every replica
+/// reads every message and handles broadcast routes, while a route marked
+/// `balanced` hands each of its messages to exactly one replica, so a
+/// stateless handler scales horizontally like a work queue:
+/// ``` ignore
+/// rust_messenger::Messenger! {
+///     config::Config,
+///     Pool:
+///         replicas: 4
+///         handlers: [
+///             cruncher: handlers::Cruncher,
+///         ]
+///         routes: [
+///             handlers::Api, messages::Job: balanced [ cruncher ],
+///             handlers::Api, messages::Reload: [ cruncher ],
+///         ]
+/// }
+/// ```
+///
+/// A route marked `sharded` partitions its messages across the replicas
+/// instead: the replica `hash(key) % N` handles every message whose
+/// [`PartitionKey`](crate::traits::core::PartitionKey) is `key`, so each
+/// replica sees all messages of its keys, in bus order:
+/// ``` ignore
+///     Books:
+///         replicas: 8
+///         handlers: [
+///             book: handlers::OrderBook,
+///         ]
+///         routes: [
+///             handlers::Gateway, messages::Order: sharded [ book ],
+///         ]
+/// ```
+///
+/// Workers read from the first message ever written unless declared with
+/// `start:`. On a `CircularBus` that has already wrapped, `start: Tail`
+/// skips everything written before the Messenger runs and `start: Oldest`
+/// begins at the oldest message not yet overwritten. `start: Committed`
+/// resumes after the offset the worker's handlers last committed with
+/// [`MessageContext::commit`](crate::messenger::MessageContext::commit), on buses
+/// that persist offsets such as `ExtendingBus` (see
+/// [`StartAt`](crate::messenger::StartAt)). `start:` goes after `replicas:`:
+/// ``` ignore
+///     Dashboard:
+///         start: Tail
+///         handlers: [
+///             view: handlers::View,
+///         ]
+///         routes: [
+///             handlers::Gateway, messages::Order: [ view ],
+///         ]
+/// ```
+///
+/// Every `on_start`, `handle` and `on_loop` call runs under `catch_unwind`.
+/// A panic is recorded as a [`PanicEvent`](crate::messenger::PanicEvent),
+/// returned by `Messenger::panic_events`, the handler's
+/// [`on_panic`](crate::traits::core::Handler::on_panic) hook is called, and
+/// its [`ON_PANIC`](crate::traits::core::Handler::ON_PANIC) policy decides
+/// between rebuilding the handler, skipping the message, or stopping the
+/// Messenger (the default). Rebuilds are limited by
+/// [`RESTART_LIMIT`](crate::traits::core::Handler::RESTART_LIMIT):
+/// ``` ignore
+/// impl traits::core::Handler for handlers::Parser {
+///     type Id = HandlerId;
+///     const ID: HandlerId = HandlerId::Parser;
+///     const ON_PANIC: messenger::Supervision = messenger::Supervision::Restart;
+/// }
+/// ```
+///
+/// Routes call [`HandleWithContext`](crate::traits::core::HandleWithContext),
+/// which every `Handle` implements; a handler that needs the source, size or
+/// bus position of a message, or commits offsets, implements it instead of
+/// `Handle` and receives a [`MessageContext`](crate::messenger::MessageContext).
+///
+/// Messages that were on the bus when it was opened (an `ExtendingBus`
+/// reopened after a restart) are replayed through the same handlers. Each
+/// handler's [`on_replay_complete`](crate::traits::core::Handler::on_replay_complete)
+/// is called once its worker has caught up, and handlers whose
+/// [`REPLAY_WRITES`](crate::traits::core::Handler::REPLAY_WRITES) is
+/// `Suppress` write nothing until then, so replayed requests are not
+/// answered twice.
+///
 #[macro_export]
 macro_rules! Messenger {
     (
         $config:ty,
         $(
             $worker:ident:
-                handlers: [ $( $handler_ident:ident: $handler_ty:ty $(,)? ),+ ]
-                routes: [ $( $source:ty, $message:ty: [ $( $receiver:ident $(,)? ),+ ] ),+ $(,)? ]
+                $( replicas: $replicas:literal )?
+                $( start: $start:ident )?
+                handlers: [ $( $handler_ident:ident $(@ $instance_id:tt)?: $handler_ty:ty $(,)? ),+ ]
+                routes: [ $( $source:ty, $message:ty: $( $delivery:ident )? [ $( $receiver:ident $(,)? ),+ ] ),+ $(,)? ]
         )+
     ) => {
         use rust_messenger::messenger;
@@ -206,30 +253,80 @@ macro_rules! Messenger {
         use rust_messenger::traits::core::Message;
         use rust_messenger::traits::core::Router;
 
+        $($(
+            rust_messenger::__messenger_handler!(alias $handler_ident $(, $instance_id)?);
+        )+)+
+
         pub struct Messenger <M: traits::core::MessageBus> {
             message_bus: M,
             stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+            supervisor: messenger::Supervisor,
+            threads: std::collections::HashMap<String, messenger::ThreadConfig>,
         }
 
         impl<M: traits::core::MessageBus> Messenger<M>  {
             pub fn new(message_bus: M) -> Messenger<M> {
+                let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
                 Messenger {
                     message_bus: message_bus,
-                    stop: std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false)),
+                    supervisor: messenger::Supervisor::new(stop.clone()),
+                    stop,
+                    threads: std::collections::HashMap::new(),
                 }
             }
 
+            /// Handle for publishing onto the bus from outside the Messenger,
+            /// stamped with the source id `SOURCE`; route its messages from
+            /// `messenger::Source<SOURCE>`.
+            pub fn injector<const SOURCE: u16>(&self) -> messenger::Injector<M, SOURCE> {
+                messenger::Injector::new(self.message_bus.clone())
+            }
+
+            /// Thread settings for the worker (`Pool`) or worker replica
+            /// (`Pool.1`) named `worker`, used by the next `run`.
+            ///
+            /// Panics if no worker or replica has that name.
+            pub fn with_thread_config(mut self, worker: &str, config: messenger::ThreadConfig) -> Messenger<M> {
+                let known = [$( (stringify!($worker), rust_messenger::__messenger_or!($( $replicas )?; 1)) ),+];
+                assert!(
+                    known.iter().any(|&(name, replicas)| messenger::Replica::names(worker, name, replicas)),
+                    "no worker or replica named {worker}",
+                );
+                self.threads.insert(worker.to_string(), config);
+                self
+            }
+
             pub fn run(&self, config: &$config) -> messenger::JoinHandles {
-                let mut handles = Vec::<std::thread::JoinHandle<()>>::new();
+                let mut handles = Vec::<(String, std::thread::JoinHandle<()>)>::new();
+                // Fix every start position before any worker runs (and
+                // writes), once per replica group so replicas sharing
+                // balanced claims start at the same position.
+                let mut starts = [$( $worker::START.position(&self.message_bus, stringify!($worker), rust_messenger::__messenger_or!($( $replicas )?; 1)) ),+].into_iter();
 
                 $(
-                    let mb = self.message_bus.clone();
-                    let cf = config.clone();
-                    let st = self.stop.clone();
-                    handles.push(std::thread::spawn(|| $worker::run_task(mb, cf, st)));
+                    let start = starts.next().unwrap();
+                    let replicas = rust_messenger::__messenger_or!($( $replicas )?; 1);
+                    for replica in messenger::Replica::group(replicas) {
+                        let thread = self
+                            .threads
+                            .get(&replica.worker_name(stringify!($worker)))
+                            .or_else(|| self.threads.get(stringify!($worker)))
+                            .cloned()
+                            .unwrap_or_default();
+                        let mb = self.message_bus.clone();
+                        let cf = config.clone();
+                        let st = self.stop.clone();
+                        let sv = self.supervisor.clone();
+                        handles.push(thread.spawn(stringify!($worker), replica, move |replica| $worker::run_task(mb, cf, st, sv, replica, start)));
+                    }
                 )+
 
-                messenger::JoinHandles::new(handles)
+                let mb = self.message_bus.clone();
+                let st = self.stop.clone();
+                messenger::JoinHandles::new(handles, move || {
+                    st.store(true, std::sync::atomic::Ordering::Relaxed);
+                    mb.on_stop();
+                })
             }
 
             pub fn stop(&self) {
@@ -237,42 +334,95 @@ macro_rules! Messenger {
                 self.message_bus.on_stop();
                 println!("Stopping Messenger, Goodbye!");
             }
+
+            /// Builds every worker (and replica) for a deterministic,
+            /// single-threaded [`Simulation`](rust_messenger::simulation::Simulation)
+            /// instead of threads. Handler panics, including escalation,
+            /// behave as under `run`.
+            pub fn simulation(&self, config: &$config, seed: u64) -> rust_messenger::simulation::Simulation<M> {
+                let mut simulation = rust_messenger::simulation::Simulation::new(self.message_bus.clone(), seed);
+                let mut starts = [$( $worker::START.position(&self.message_bus, stringify!($worker), rust_messenger::__messenger_or!($( $replicas )?; 1)) ),+].into_iter();
+                $(
+                    let start = starts.next().unwrap();
+                    let replicas = rust_messenger::__messenger_or!($( $replicas )?; 1);
+                    for replica in messenger::Replica::group(replicas) {
+                        let name = replica.worker_name(stringify!($worker));
+                        let worker = $worker::build(&self.message_bus, config.clone(), self.stop.clone(), self.supervisor.clone(), replica, start);
+                        simulation.add_worker(name, worker);
+                    }
+                )+
+                simulation
+            }
+
+            /// Removes and returns the handler panics caught so far.
+            pub fn panic_events(&self) -> Vec<messenger::PanicEvent> {
+                self.supervisor.take_events()
+            }
         }
 
         $(
-            struct $worker {
+            /// A worker of the Messenger. `Messenger::run` drives one per
+            /// thread; built with `new`, it can also be driven by hand with
+            /// `poll_once` or `run_on_current_thread`.
+            pub struct $worker {
                 position: usize,
-                $($handler_ident: $handler_ty,)+
+                started: bool,
+                history_end: usize,
+                replaying: bool,
+                replica: messenger::Replica,
+                restarts: messenger::Restarts,
+                $(pub $handler_ident: rust_messenger::__messenger_handler!(ty $handler_ty $(, $instance_id)?),)+
+                config: $config,
                 stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+                supervisor: messenger::Supervisor,
             }
 
             impl $worker {
-                fn run_task<MB: traits::core::MessageBus>(mut message_bus: MB, config: $config, stop: std::sync::Arc<std::sync::atomic::AtomicBool>) {
-                    let mut worker = $worker {
-                        position: 0,
-                        $($handler_ident: <$handler_ty>::new(&config, &message_bus),)+
+                /// Where the worker starts reading, from `start:` in `Messenger!`.
+                pub const START: messenger::StartAt = rust_messenger::__messenger_or!($( messenger::StartAt::$start )?; messenger::StartAt::Beginning);
+
+                /// Builds a standalone worker reading `message_bus` from its
+                /// [`START`](Self::START) position, with its own stop flag
+                /// and panic log.
+                pub fn new<MB: traits::core::MessageBus>(message_bus: &MB, config: &$config) -> $worker {
+                    let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+                    let supervisor = messenger::Supervisor::new(stop.clone());
+                    let replica = messenger::Replica::group(1).pop().unwrap();
+                    let start = $worker::START.position(message_bus, stringify!($worker), 1);
+                    $worker::build(message_bus, config.clone(), stop, supervisor, replica, start)
+                }
+
+                fn build<MB: traits::core::MessageBus>(message_bus: &MB, config: $config, stop: std::sync::Arc<std::sync::atomic::AtomicBool>, supervisor: messenger::Supervisor, replica: messenger::Replica, start: usize) -> $worker {
+                    $worker {
+                        position: start,
+                        started: false,
+                        history_end: message_bus.history_end(),
+                        replaying: true,
+                        replica,
+                        restarts: messenger::Restarts::default(),
+                        $($handler_ident: rust_messenger::__messenger_handler!(new $handler_ty $(, $instance_id)?; &config, message_bus),)+
+                        config,
                         stop,
-                    };
-                    worker.run(&mut message_bus)
+                        supervisor,
+                    }
                 }
 
-                fn run<MB: traits::core::MessageBus>(&mut self, message_bus: &mut MB) {
-                    $(
-                        self.$handler_ident.on_start(message_bus);
-                    )+
-                    loop {
-                        if let Some((header, buffer)) = message_bus.read(self.position) {
-                            // Advance by the padded slot length, not the
-                            // payload length, to land on the next slot.
-                            self.position += header.slot_len();
-                            self.route(&header, &buffer, message_bus);
-                        }
+                fn run_task<MB: traits::core::MessageBus>(message_bus: MB, config: $config, stop: std::sync::Arc<std::sync::atomic::AtomicBool>, supervisor: messenger::Supervisor, replica: messenger::Replica, start: usize) {
+                    $worker::build(&message_bus, config, stop, supervisor, replica, start).run_on_current_thread(&message_bus)
+                }
 
-                        $(
-                            self.$handler_ident.on_loop(message_bus);
-                        )+
+                /// Runs the worker loop on the calling thread until the
+                /// worker is stopped, then calls every handler's `on_stop`.
+                pub fn run_on_current_thread<MB: traits::core::MessageBus>(&mut self, message_bus: &MB) {
+                    loop {
+                        self.poll_once(message_bus);
 
                         if self.stop.load(std::sync::atomic::Ordering::Relaxed) {
+                            if self.supervisor.escalated() {
+                                // Wake the workers blocked in `read`, as
+                                // `Messenger::stop` would.
+                                message_bus.on_stop();
+                            }
                             $(
                                 self.$handler_ident.on_stop();
                             )+
@@ -280,11 +430,173 @@ macro_rules! Messenger {
                         }
                     }
                 }
+
+                /// One iteration of the worker loop: routes the next message,
+                /// if there is one, then runs every handler's `on_loop`. The
+                /// first call runs the handlers' `on_start` beforehand.
+                ///
+                /// Returns whether a message was read. With a blocking bus
+                /// such as `CondvarBus` this waits for the next message.
+                pub fn poll_once<MB: traits::core::MessageBus>(&mut self, message_bus: &MB) -> bool {
+                    if !self.started {
+                        self.started = true;
+                        $(
+                            self.supervise(stringify!($handler_ident), None, message_bus, |worker| {
+                                worker.$handler_ident.on_start(message_bus)
+                            });
+                        )+
+                    }
+                    self.finish_replay(message_bus);
+
+                    let read = if let Some((header, buffer)) = message_bus.read(self.position) {
+                        // Route before advancing: `self.position` is the
+                        // position of the message being routed (claims of
+                        // balanced routes are keyed by it). Advance by the
+                        // padded slot length, not the payload length, to
+                        // land on the next slot.
+                        self.route(&header, &buffer, message_bus);
+                        self.position += header.slot_len();
+                        true
+                    } else {
+                        false
+                    };
+                    self.finish_replay(message_bus);
+
+                    $(
+                        self.supervise(stringify!($handler_ident), None, message_bus, |worker| {
+                            if worker.replaying && messenger::replay_writes_of(&worker.$handler_ident) == messenger::ReplayWrites::Suppress {
+                                worker.$handler_ident.on_loop(messenger::SuppressedWriter::from_ref(message_bus))
+                            } else {
+                                worker.$handler_ident.on_loop(message_bus)
+                            }
+                        });
+                    )+
+
+                    read
+                }
+
+                /// Whether a message is waiting at the worker's position.
+                /// Blocks like `poll_once` on a blocking bus.
+                pub fn has_pending<R: traits::core::Reader>(&self, message_bus: &R) -> bool {
+                    message_bus.read(self.position).is_some()
+                }
+
+                /// Bus position of the next message the worker reads.
+                pub fn position(&self) -> usize {
+                    self.position
+                }
+
+                /// Whether the worker is still reading messages that were on
+                /// the bus when it was opened (see `MessageBus::history_end`).
+                pub fn is_replaying(&self) -> bool {
+                    self.replaying
+                }
+
+                /// Ends the replay once the worker reaches the end of the
+                /// history, calling every handler's `on_replay_complete`.
+                #[inline]
+                fn finish_replay<MB: traits::core::MessageBus>(&mut self, message_bus: &MB) {
+                    if self.replaying && self.position >= self.history_end {
+                        self.replaying = false;
+                        $(
+                            self.supervise(stringify!($handler_ident), None, message_bus, |worker| {
+                                worker.$handler_ident.on_replay_complete(message_bus)
+                            });
+                        )+
+                    }
+                }
+
+                /// Makes `run_on_current_thread` return after its current
+                /// iteration. Also raised by an escalated handler panic.
+                pub fn stop(&self) {
+                    self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+                }
+
+                pub fn is_stopped(&self) -> bool {
+                    self.stop.load(std::sync::atomic::Ordering::Relaxed)
+                }
+
+                /// Removes and returns the handler panics caught so far. For
+                /// workers run by a Messenger, use `Messenger::panic_events`.
+                pub fn panic_events(&self) -> Vec<messenger::PanicEvent> {
+                    self.supervisor.take_events()
+                }
+
+                /// Runs `dispatch` on the handler named `handler`, applying
+                /// its [`Supervision`](messenger::Supervision) policy if it
+                /// panics. A restart past the handler's `RESTART_LIMIT`, or
+                /// one whose rebuilt handler panics too, escalates.
+                fn supervise<W: traits::core::Writer, F: FnOnce(&mut Self)>(
+                    &mut self,
+                    handler: &'static str,
+                    message: Option<messenger::PanickedMessage>,
+                    writer: &W,
+                    dispatch: F,
+                ) {
+                    let Err(payload) = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| dispatch(self))) else {
+                        return;
+                    };
+                    let mut event = messenger::PanicEvent {
+                        worker: stringify!($worker),
+                        replica: self.replica.index(),
+                        handler,
+                        message,
+                        reason: messenger::panic_reason(&*payload),
+                        action: messenger::Supervision::Escalate,
+                    };
+                    let mut rebuild_failed = None;
+                    $(
+                        if handler == stringify!($handler_ident) {
+                            event.action = messenger::policy_of(&self.$handler_ident);
+                            if event.action == messenger::Supervision::Restart
+                                && !self.restarts.try_restart(handler, messenger::restart_limit_of(&self.$handler_ident))
+                            {
+                                event.action = messenger::Supervision::Escalate;
+                            }
+                            self.$handler_ident.on_panic(&event);
+                            if event.action == messenger::Supervision::Restart {
+                                let config = &self.config;
+                                let rebuilt = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                                    let mut rebuilt = rust_messenger::__messenger_handler!(new $handler_ty $(, $instance_id)?; config, writer);
+                                    rebuilt.on_start(writer);
+                                    rebuilt
+                                }));
+                                match rebuilt {
+                                    Ok(rebuilt) => self.$handler_ident = rebuilt,
+                                    Err(payload) => rebuild_failed = Some(messenger::PanicEvent {
+                                        message: None,
+                                        reason: messenger::panic_reason(&*payload),
+                                        action: messenger::Supervision::Escalate,
+                                        ..event.clone()
+                                    }),
+                                }
+                            }
+                        }
+                    )+
+                    self.supervisor.report(event);
+                    if let Some(event) = rebuild_failed {
+                        self.supervisor.report(event);
+                    }
+                }
+            }
+
+            impl<MB: traits::core::MessageBus> rust_messenger::simulation::Step<MB> for $worker {
+                fn poll_once(&mut self, message_bus: &MB) -> bool {
+                    $worker::poll_once(self, message_bus)
+                }
+
+                fn has_pending(&self, message_bus: &MB) -> bool {
+                    $worker::has_pending(self, message_bus)
+                }
+
+                fn is_stopped(&self) -> bool {
+                    $worker::is_stopped(self)
+                }
             }
 
             impl traits::core::Router for $worker {
                 #[inline]
-                fn route<'a, W: traits::core::Writer>(&mut self, header: &messenger::Header, buffer: &'a [u8], writer: &W) {
+                fn route<'a, MB: traits::core::MessageBus>(&mut self, header: &messenger::Header, buffer: &'a [u8], message_bus: &MB) {
                     // Compare raw u16 ids: headers come from the bus, and an
                     // unknown id must fall through, not panic in a conversion.
                     // The conversions spell out `Into::<u16>` because bare
@@ -296,10 +608,35 @@ macro_rules! Messenger {
                             (source, message_id)
                                 if source == Into::<u16>::into(<$source>::ID)
                                     && message_id == Into::<u16>::into(<$message>::ID) => {
-                                let message = <$message>::deserialize_from(buffer);
-                                $(
-                                    self.$receiver.handle(&message, writer);
-                                )+
+                                if rust_messenger::__messenger_delivery!(claim $( $delivery )?; self.replica, self.position, header) {
+                                    let message = <$message>::deserialize_from(buffer);
+                                    if rust_messenger::__messenger_delivery!(owns $( $delivery )?; self.replica, message) {
+                                        let (replica, replicas) = (self.replica.index(), self.replica.count());
+                                        let commit = |next: usize| {
+                                            message_bus.commit_offset(&messenger::Replica::name(stringify!($worker), replica, replicas), next)
+                                        };
+                                        let context = messenger::MessageContext::new(header, self.position, stringify!($worker), replica, self.replaying)
+                                            .with_commit(&commit);
+                                        $(
+                                            let panicked = messenger::PanickedMessage {
+                                                source: header.source,
+                                                message_id: header.message_id,
+                                                position: self.position,
+                                            };
+                                            self.supervise(stringify!($receiver), Some(panicked), message_bus, |worker| {
+                                                use rust_messenger::traits::core::HandleWithContext as _;
+                                                // Naming the type deref-coerces a zero-copy `&&M`
+                                                // to `&M`, fixing the message type for the call.
+                                                let message: &$message = &message;
+                                                if worker.replaying && messenger::replay_writes_of(&worker.$receiver) == messenger::ReplayWrites::Suppress {
+                                                    worker.$receiver.handle_with_context(message, &context, messenger::SuppressedWriter::from_ref(message_bus))
+                                                } else {
+                                                    worker.$receiver.handle_with_context(message, &context, message_bus)
+                                                }
+                                            });
+                                        )+
+                                    }
+                                }
                             }
                         )+
                         _ => {}
@@ -309,3 +646,69 @@ macro_rules! Messenger {
         )+
     };
 }
+
+/// Internal helper of `Messenger!`: expands a handler declaration into its
+/// field type (`ty`), its constructor call (`new`) or its route alias
+/// (`alias`). Handlers declared with an instance id (`name @ 10: Type`) are
+/// wrapped in [`messenger::Instance`](crate::messenger::Instance),
+/// constructed with an [`InstanceWriter`](crate::messenger::InstanceWriter),
+/// and routes name them through the alias `name` of
+/// [`messenger::Source<10>`](crate::messenger::Source).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __messenger_handler {
+    (alias $handler_ident:ident) => {};
+    (alias $handler_ident:ident, $instance_id:tt) => {
+        /// Source of the handler instance of this name, for routes.
+        #[allow(non_camel_case_types)]
+        pub type $handler_ident = rust_messenger::messenger::Source<$instance_id>;
+    };
+    (ty $handler_ty:ty) => { $handler_ty };
+    (ty $handler_ty:ty, $instance_id:tt) => {
+        rust_messenger::messenger::Instance<$handler_ty, $instance_id>
+    };
+    (new $handler_ty:ty; $config:expr, $writer:expr) => {
+        <$handler_ty>::new($config, $writer)
+    };
+    (new $handler_ty:ty, $instance_id:tt; $config:expr, $writer:expr) => {
+        rust_messenger::messenger::Instance::<$handler_ty, $instance_id>::new(
+            <$handler_ty>::new(
+                $config,
+                rust_messenger::messenger::InstanceWriter::<_, $handler_ty, $instance_id>::from_ref($writer),
+            ),
+        )
+    };
+}
+
+/// Internal helper of `Messenger!`: the first argument if present, otherwise
+/// the default after the `;`.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __messenger_or {
+    (; $default:expr) => { $default };
+    ($value:expr; $default:expr) => { $value };
+}
+
+/// Internal helper of `Messenger!`: whether this worker replica takes the
+/// message of a route with the given delivery mode, checked in two phases:
+/// `claim` before the message is deserialized, `owns` after. Broadcast
+/// routes (no mode) are delivered to every replica, `balanced` routes to the
+/// one replica that claims the message, and `sharded` routes to the replica
+/// owning the message's [`PartitionKey`](crate::traits::core::PartitionKey).
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __messenger_delivery {
+    (claim; $replica:expr, $position:expr, $header:expr) => { true };
+    (claim balanced; $replica:expr, $position:expr, $header:expr) => {
+        $replica.try_claim($position, $header.slot_len())
+    };
+    (claim sharded; $replica:expr, $position:expr, $header:expr) => { true };
+    (owns; $replica:expr, $message:expr) => { true };
+    (owns balanced; $replica:expr, $message:expr) => { true };
+    (owns sharded; $replica:expr, $message:expr) => {
+        $replica.owns(&{
+            use rust_messenger::traits::core::PartitionKey as _;
+            $message.partition_key()
+        })
+    };
+}
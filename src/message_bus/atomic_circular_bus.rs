@@ -1,5 +1,7 @@
 use crate::messenger;
 use crate::mmap::anonymous_mmap;
+#[cfg(target_os = "linux")]
+use crate::mmap::linux::shared_mmap;
 use crate::traits;
 
 /// A circular bus implementation that uses a shared memory buffer to store messages.
@@ -19,6 +21,11 @@ use crate::traits;
 /// cannot skip an unpublished slot). If handlers may panic, run with
 /// `panic = "abort"` or keep callbacks trivially infallible.
 ///
+/// The bus also tracks its tail, the start of the oldest slot not yet
+/// overwritten: before a writer overwrites old slots, it moves the tail
+/// past them, reading their lengths from their headers (written before the
+/// callback runs, so a panicked slot has one too).
+///
 /// # Fallen-behind readers panic
 ///
 /// Writers never wait for readers. A reader that falls more than half the
@@ -60,24 +67,228 @@ pub trait Config {
 }
 
 struct SharedBuffer {
-    mmap: anonymous_mmap::AnonymousMmap,
-    write_head: std::sync::atomic::AtomicUsize,
+    memory: Memory,
+    /// Start of the ring in `memory`: `wrap_size` bytes of slot starts, plus
+    /// as many again for slots that start near the end.
+    ring: *mut u8,
+    /// Points into `memory`; a shared bus keeps it in the shared control
+    /// page, so every attached process reserves from the same head.
+    write_head: *const std::sync::atomic::AtomicUsize,
+    /// Start of the oldest slot not yet overwritten, kept next to
+    /// `write_head`. Only ever moved past slots whose header is written.
+    tail: *const std::sync::atomic::AtomicUsize,
     wrap_size: usize,
     /// `wrap_size - 1`; valid because the buffer size is a power of two.
     /// Lets the hot path wrap positions with `&` instead of a division.
     wrap_mask: usize,
 }
 
+// SAFETY: `ring`, `write_head` and `tail` point into `memory`, which the buffer owns
+// and which lives as long as it; all access through them is atomic-based as
+// for the anonymous mapping itself.
+unsafe impl Send for SharedBuffer {}
+unsafe impl Sync for SharedBuffer {}
+
+/// Owns what `ring`, `write_head` and `tail` point into.
+enum Memory {
+    Anonymous {
+        _mmap: anonymous_mmap::AnonymousMmap,
+        /// Boxed for a stable address.
+        _write_head: Box<std::sync::atomic::AtomicUsize>,
+        _tail: Box<std::sync::atomic::AtomicUsize>,
+    },
+    #[cfg(target_os = "linux")]
+    Shared(shared_mmap::SharedMmap),
+}
+
+/// The first page of a shared bus's memfd; the ring follows it.
+#[cfg(target_os = "linux")]
+#[repr(C)]
+struct Control {
+    magic: u64,
+    buffer_size: u64,
+    /// On a cache line of its own, away from the constant fields above.
+    write_head: CacheAligned,
+    tail: CacheAligned,
+}
+
+#[cfg(target_os = "linux")]
+#[repr(C, align(64))]
+struct CacheAligned(std::sync::atomic::AtomicUsize);
+
+#[cfg(target_os = "linux")]
+const CONTROL_MAGIC: u64 = u64::from_le_bytes(*b"rmbus\0\0\x01");
+
+impl SharedBuffer {
+    fn write_head(&self) -> &std::sync::atomic::AtomicUsize {
+        unsafe { &*self.write_head }
+    }
+
+    fn tail(&self) -> &std::sync::atomic::AtomicUsize {
+        unsafe { &*self.tail }
+    }
+
+    fn header(&self, position: usize) -> *mut messenger::Header {
+        unsafe { self.ring.add(position & self.wrap_mask) as *mut messenger::Header }
+    }
+
+    /// The length of the slot at `position` once its header is written,
+    /// committed or not; `None` before that, or once a newer slot took its
+    /// place.
+    fn sized_slot_len(&self, position: usize) -> Option<usize> {
+        let header_ptr = self.header(position);
+        let stamp = unsafe { &(*header_ptr).commit_stamp }.load(std::sync::atomic::Ordering::Acquire);
+        if stamp != messenger::Header::commit_stamp_for(position)
+            && stamp != messenger::Header::sized_stamp_for(position)
+        {
+            return None;
+        }
+        // Atomic like the writer's store: a writer that overwrites the slot
+        // concurrently must not make this read a data race.
+        let size = unsafe { std::sync::atomic::AtomicU32::from_ptr(std::ptr::addr_of_mut!((*header_ptr).size)) }
+            .load(std::sync::atomic::Ordering::Relaxed);
+        Some(messenger::ALIGNED_HEADER_SIZE + messenger::align_to_usize(size as usize))
+    }
+
+    /// Moves the tail past every slot that starts more than `wrap_size`
+    /// before `end`, the slots a reservation ending at `end` overwrites,
+    /// and returns it.
+    ///
+    /// Writers call this before they touch their slot, so the slot at the
+    /// tail is intact as long as the tail has not moved: its length is read
+    /// before the compare-exchange that moves past it, which a failed
+    /// exchange discards. A slot reserved but not yet sized by its writer
+    /// is waited for; that writer is between its reservation and its header.
+    fn advance_tail(&self, end: usize) -> usize {
+        let limit = end.saturating_sub(self.wrap_size);
+        let mut tail = self.tail().load(std::sync::atomic::Ordering::Acquire);
+        while tail < limit {
+            let Some(slot_len) = self.sized_slot_len(tail) else {
+                std::hint::spin_loop();
+                tail = self.tail().load(std::sync::atomic::Ordering::Acquire);
+                continue;
+            };
+            match self.tail().compare_exchange_weak(
+                tail,
+                tail + slot_len,
+                std::sync::atomic::Ordering::AcqRel,
+                std::sync::atomic::Ordering::Acquire,
+            ) {
+                Ok(_) => tail += slot_len,
+                Err(current) => tail = current,
+            }
+        }
+        tail
+    }
+}
+
 impl CircularBus {
     pub fn new<C: Config>(config: &C) -> CircularBus {
         let mmap = anonymous_mmap::AnonymousMmap::new(config.get_buffer_size())
             .expect("invalid bus buffer size");
-        let write_head = std::sync::atomic::AtomicUsize::new(0);
-        let wrap_size = config.get_buffer_size() >> 1;
+        let write_head = Box::new(std::sync::atomic::AtomicUsize::new(0));
+        let tail = Box::new(std::sync::atomic::AtomicUsize::new(0));
+        let ring = mmap.get_ptr() as *mut u8;
+        let write_head_ptr: *const std::sync::atomic::AtomicUsize = &*write_head;
+        let tail_ptr: *const std::sync::atomic::AtomicUsize = &*tail;
+        CircularBus::from_memory(
+            Memory::Anonymous {
+                _mmap: mmap,
+                _write_head: write_head,
+                _tail: tail,
+            },
+            ring,
+            write_head_ptr,
+            tail_ptr,
+            config.get_buffer_size(),
+        )
+    }
+
+    /// A bus in shared memory that other processes can
+    /// [`attach`](CircularBus::attach) to, given its
+    /// [`shared_fd`](CircularBus::shared_fd), e.g. through
+    /// `bridge::unix::share`. Writers and readers of every attached process
+    /// then use the one ring, without copying.
+    #[cfg(target_os = "linux")]
+    pub fn shared<C: Config>(config: &C) -> std::io::Result<CircularBus> {
+        let buffer_size = config.get_buffer_size();
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGE_SIZE) as usize };
+        if !buffer_size.is_power_of_two() || buffer_size < page_size {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("buffer size must be a power of 2 of at least {page_size}, got {buffer_size}"),
+            ));
+        }
+        let mmap = shared_mmap::SharedMmap::create("rust-messenger-bus", page_size + buffer_size)?;
+        // The memfd is new and zeroed, and no other process has it yet.
+        let control = mmap.as_ptr() as *mut Control;
+        unsafe {
+            (*control).magic = CONTROL_MAGIC;
+            (*control).buffer_size = buffer_size as u64;
+        }
+        Ok(CircularBus::from_shared(mmap, page_size))
+    }
+
+    /// Maps the shared bus behind `fd`, received from the process that
+    /// created it with [`shared`](CircularBus::shared).
+    #[cfg(target_os = "linux")]
+    pub fn attach(fd: std::os::fd::OwnedFd) -> std::io::Result<CircularBus> {
+        let mmap = shared_mmap::SharedMmap::from_fd(fd)?;
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGE_SIZE) as usize };
+        let invalid = |reason: &str| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("not a shared bus: {reason}"),
+            )
+        };
+        if mmap.len() < page_size {
+            return Err(invalid("too small"));
+        }
+        let control = unsafe { &*(mmap.as_ptr() as *const Control) };
+        if control.magic != CONTROL_MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        let buffer_size = control.buffer_size as usize;
+        if !buffer_size.is_power_of_two() || mmap.len() != page_size + buffer_size {
+            return Err(invalid("size mismatch"));
+        }
+        Ok(CircularBus::from_shared(mmap, page_size))
+    }
+
+    /// The memfd of a [`shared`](CircularBus::shared) or attached bus, to
+    /// pass to another process; `None` for a private bus.
+    #[cfg(target_os = "linux")]
+    pub fn shared_fd(&self) -> Option<std::os::fd::BorrowedFd<'_>> {
+        match &self.buffer.memory {
+            Memory::Shared(mmap) => Some(mmap.fd()),
+            Memory::Anonymous { .. } => None,
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn from_shared(mmap: shared_mmap::SharedMmap, page_size: usize) -> CircularBus {
+        let control = mmap.as_ptr() as *const Control;
+        let buffer_size = unsafe { (*control).buffer_size } as usize;
+        let write_head = unsafe { &(*control).write_head.0 } as *const _;
+        let tail = unsafe { &(*control).tail.0 } as *const _;
+        let ring = unsafe { mmap.as_ptr().add(page_size) };
+        CircularBus::from_memory(Memory::Shared(mmap), ring, write_head, tail, buffer_size)
+    }
+
+    fn from_memory(
+        memory: Memory,
+        ring: *mut u8,
+        write_head: *const std::sync::atomic::AtomicUsize,
+        tail: *const std::sync::atomic::AtomicUsize,
+        buffer_size: usize,
+    ) -> CircularBus {
+        let wrap_size = buffer_size >> 1;
         Self {
             buffer: std::sync::Arc::new(SharedBuffer {
-                mmap,
+                memory,
+                ring,
                 write_head,
+                tail,
                 wrap_size,
                 wrap_mask: wrap_size - 1,
             }),
@@ -91,6 +302,19 @@ impl traits::core::Writer for CircularBus {
         &self,
         size: usize,
         callback: F,
+    ) {
+        traits::core::RawWriter::write_raw(self, H::ID.into(), M::ID.into(), size, callback);
+    }
+}
+
+impl traits::core::RawWriter for CircularBus {
+    #[inline]
+    fn write_raw<F: FnOnce(&mut [u8])>(
+        &self,
+        source: u16,
+        message_id: u16,
+        size: usize,
+        callback: F,
     ) {
         let aligned_size = messenger::align_to_usize(size);
         let len = messenger::ALIGNED_HEADER_SIZE + aligned_size;
@@ -102,14 +326,14 @@ impl traits::core::Writer for CircularBus {
 
         let position = self
             .buffer
-            .write_head
+            .write_head()
             .fetch_add(len, std::sync::atomic::Ordering::Relaxed);
-        let wrapped_pos = position & self.buffer.wrap_mask;
-
-        let ptr = self.buffer.mmap.get_ptr() as *mut u8;
-        let ptr = unsafe { ptr.add(wrapped_pos) };
+        // Move the tail past the slots this one overwrites before touching
+        // them.
+        self.buffer.advance_tail(position + len);
 
-        let hdr_ptr = ptr as *mut messenger::Header;
+        let hdr_ptr = self.buffer.header(position);
+        let ptr = hdr_ptr as *mut u8;
         // Field projection through the raw pointer: borrows only the atomic
         // stamp, so the sibling field writes below do not alias it. Sound on
         // arbitrary slot bytes because every Header bit pattern is valid.
@@ -120,20 +344,31 @@ impl traits::core::Writer for CircularBus {
         stamp.store(0, std::sync::atomic::Ordering::Release);
 
         unsafe {
-            // Zero the header padding and the alignment tail beyond `size`;
-            // the callback is responsible for the payload bytes themselves.
-            std::ptr::write_bytes(ptr, 0, std::mem::offset_of!(messenger::Header, commit_stamp));
+            // The header has no padding, and each of its fields is written
+            // below. Zero the alignment tail beyond `size`; the callback is
+            // responsible for the payload bytes themselves.
             std::ptr::write_bytes(
                 ptr.add(messenger::ALIGNED_HEADER_SIZE + size),
                 0,
                 aligned_size - size,
             );
-            std::ptr::addr_of_mut!((*hdr_ptr).source).write(H::ID.into());
-            std::ptr::addr_of_mut!((*hdr_ptr).message_id).write(M::ID.into());
+            std::ptr::addr_of_mut!((*hdr_ptr).source).write(source);
+            std::ptr::addr_of_mut!((*hdr_ptr).message_id).write(message_id);
             // The exact payload length; the padded length the slot occupies is
             // derived from it via Header::aligned_size when walking slots.
-            std::ptr::addr_of_mut!((*hdr_ptr).size).write(size as u32);
+            // Atomic, as `advance_tail` may read it while a later writer
+            // overwrites the slot.
+            std::sync::atomic::AtomicU32::from_ptr(std::ptr::addr_of_mut!((*hdr_ptr).size))
+                .store(size as u32, std::sync::atomic::Ordering::Relaxed);
+            #[cfg(feature = "timestamps")]
+            std::ptr::addr_of_mut!((*hdr_ptr).timestamp).write(messenger::timestamp_now());
         }
+        // The header is complete: the tail may move past the slot from now
+        // on, even if the callback panics.
+        stamp.store(
+            messenger::Header::sized_stamp_for(position),
+            std::sync::atomic::Ordering::Release,
+        );
 
         // The callback still gets the full padded buffer to write into; the
         // header records that only `size` of it is the real payload.
@@ -152,38 +387,51 @@ impl traits::core::Writer for CircularBus {
 }
 
 impl CircularBus {
-    /// Cold path of [`read`](traits::core::Reader::read): the slot is not
-    /// committed for `position`. Decides between "no message yet" (`None`)
-    /// and "reader fell behind" (panic). Only here is the write_head cache
-    /// line touched, keeping reader polling off the line writers contend on.
+    /// Cold path of [`try_read`](traits::core::Reader::try_read): the slot is
+    /// not committed for `position`. Decides between "no message yet"
+    /// (`None`) and "reader fell behind" (`Lapped`). Only here is the
+    /// write_head cache line touched, keeping reader polling off the line
+    /// writers contend on.
     #[cold]
-    fn read_uncommitted(&self, position: usize) -> Option<(&messenger::Header, &[u8])> {
-        let write_head_position = self
+    fn read_uncommitted(
+        &self,
+        position: usize,
+    ) -> Result<Option<(&messenger::Header, &[u8])>, messenger::Lapped> {
+        let write_head = self
             .buffer
-            .write_head
+            .write_head()
             .load(std::sync::atomic::Ordering::Relaxed);
 
         // Writers never wait for readers: once a reservation extends more
         // than wrap_size past `position`, this reader's slot has been handed
-        // to a newer message and its own message is gone. Fail loudly.
-        assert!(
-            position >= write_head_position
-                || write_head_position - position <= self.buffer.wrap_size,
-            "reader at position {position} fell behind the writers (write head \
-             {write_head_position}) and its messages were overwritten; \
-             increase the bus buffer size or consume faster"
-        );
+        // to a newer message and its own message is gone.
+        if position < write_head && write_head - position > self.buffer.wrap_size {
+            return Err(messenger::Lapped {
+                position,
+                write_head,
+            });
+        }
 
-        None
+        Ok(None)
     }
 }
 
 impl traits::core::Reader for CircularBus {
     #[inline]
     fn read(&self, position: usize) -> Option<(&messenger::Header, &[u8])> {
+        // Fail loudly rather than return torn data or skip messages.
+        self.try_read(position)
+            .unwrap_or_else(|lapped| panic!("{lapped}"))
+    }
+
+    #[inline]
+    fn try_read(
+        &self,
+        position: usize,
+    ) -> Result<Option<(&messenger::Header, &[u8])>, messenger::Lapped> {
         let wrapped_position = position & self.buffer.wrap_mask;
 
-        let ptr = self.buffer.mmap.get_ptr() as *const u8;
+        let ptr = self.buffer.ring as *const u8;
         let ptr = unsafe { ptr.add(wrapped_position) };
 
         let header_ptr = ptr as *const messenger::Header;
@@ -203,16 +451,40 @@ impl traits::core::Reader for CircularBus {
         let header = unsafe { &*header_ptr };
         // Validate the padded slot fits; return the exact (unpadded) payload.
         if header.slot_len() > self.buffer.wrap_size {
-            return None;
+            return Ok(None);
         }
 
         let ptr = unsafe { ptr.add(messenger::ALIGNED_HEADER_SIZE) };
         let buffer = unsafe { std::slice::from_raw_parts(ptr, header.size as usize) };
-        Some((header, buffer))
+        Ok(Some((header, buffer)))
     }
 }
 
-impl traits::core::MessageBus for CircularBus {}
+impl traits::core::Positions for CircularBus {
+    fn write_head(&self) -> usize {
+        self.buffer
+            .write_head()
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// The tail, moved past the slots already overwritten by reservations
+    /// up to the write head.
+    fn oldest(&self) -> usize {
+        self.buffer.advance_tail(self.write_head())
+    }
+
+    fn skip(&self, position: usize) -> Option<usize> {
+        self.buffer
+            .sized_slot_len(position)
+            .map(|slot_len| position + slot_len)
+    }
+}
+
+impl traits::core::MessageBus for CircularBus {
+    fn positions(&self) -> Option<&dyn traits::core::Positions> {
+        Some(self)
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -377,6 +649,120 @@ mod tests {
         assert_eq!(first, 0);
     }
 
+    /// `oldest` must find the first intact message after the ring wrapped,
+    /// from which a reader walks to the write head without being lapped.
+    #[test]
+    fn test_oldest_is_the_first_intact_message() {
+        use crate::traits::core::Positions;
+        use crate::traits::core::Reader;
+        use crate::traits::extended::Sender;
+
+        let bus = CircularBus::new(&Config {});
+        assert_eq!(bus.oldest(), 0);
+        let slot = messenger::ALIGNED_HEADER_SIZE
+            + messenger::align_to_usize(std::mem::size_of::<MsgA>());
+        for i in 0..1000u16 {
+            let message = MsgA {
+                data: [i, 1, 2, 3, 4],
+            };
+            HandlerA::send(&message, &bus);
+            if i == 10 {
+                assert_eq!(bus.oldest(), 0, "nothing overwritten yet");
+            }
+        }
+
+        let oldest = bus.oldest();
+        let write_head = bus.write_head();
+        assert_eq!(oldest % slot, 0, "oldest is a slot start");
+        assert!(write_head - oldest <= 8192);
+        assert!(write_head - oldest > 8192 - slot, "no intact message skipped");
+
+        let mut position = oldest;
+        let mut expected = (oldest / slot) as u16;
+        while let Some((_, buffer)) = bus.read(position) {
+            assert_eq!(u16::from_ne_bytes([buffer[0], buffer[1]]), expected);
+            expected += 1;
+            position += slot;
+        }
+        assert_eq!(position, write_head);
+    }
+
+    /// Payloads made of fake headers, each with the commit stamp of its own
+    /// position, look like slots; `oldest` must still land on a real one.
+    #[test]
+    fn test_oldest_is_not_fooled_by_stamp_like_payloads() {
+        use crate::traits::core::Positions;
+        use crate::traits::core::Reader;
+        use crate::traits::core::RawWriter;
+
+        let bus = CircularBus::new(&Config {});
+        for i in 0..500usize {
+            let position = bus.write_head();
+            // Varying sizes, so slot starts do not fall on a fixed grid.
+            let size = messenger::HEADER_SIZE * (1 + i % 5);
+            bus.write_raw(1, 2, size, |buffer| {
+                for (n, chunk) in buffer.chunks_exact_mut(messenger::HEADER_SIZE).enumerate() {
+                    let at = position + messenger::ALIGNED_HEADER_SIZE + n * messenger::HEADER_SIZE;
+                    let fake = messenger::Header {
+                        source: 1,
+                        message_id: 2,
+                        size: 8,
+                        #[cfg(feature = "timestamps")]
+                        timestamp: 0,
+                        commit_stamp: std::sync::atomic::AtomicU64::new(
+                            messenger::Header::commit_stamp_for(at),
+                        ),
+                    };
+                    let bytes = unsafe {
+                        std::slice::from_raw_parts(&fake as *const messenger::Header as *const u8, messenger::HEADER_SIZE)
+                    };
+                    chunk.copy_from_slice(bytes);
+                }
+            });
+        }
+
+        let mut position = bus.oldest();
+        let write_head = bus.write_head();
+        assert!(write_head - position <= 8192);
+        let mut count = 0;
+        while let Some((header, _)) = bus.read(position) {
+            position += header.slot_len();
+            count += 1;
+        }
+        assert_eq!(position, write_head, "oldest must be a slot start");
+        assert!(count > 50);
+    }
+
+    /// The tail moves past a slot whose writer panicked, so `oldest` keeps
+    /// finding real slot starts once the ring laps it.
+    #[test]
+    fn test_oldest_passes_panicked_slots() {
+        use crate::traits::core::Positions;
+        use crate::traits::core::Reader;
+        use crate::traits::core::Writer;
+
+        let bus = CircularBus::new(&Config {});
+        bus.write::<MsgA, HandlerA, _>(16, |_| {});
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            bus.write::<MsgA, HandlerA, _>(24, |_| panic!("callback failed"));
+        }));
+        assert!(result.is_err());
+        let panicked = messenger::ALIGNED_HEADER_SIZE + messenger::align_to_usize(16);
+        assert_eq!(
+            bus.skip(panicked),
+            Some(panicked + messenger::ALIGNED_HEADER_SIZE + 24),
+        );
+
+        for _ in 0..1000 {
+            bus.write::<MsgA, HandlerA, _>(16, |_| {});
+        }
+        let mut position = bus.oldest();
+        while let Some((header, _)) = bus.read(position) {
+            position += header.slot_len();
+        }
+        assert_eq!(position, bus.write_head());
+    }
+
     /// A message larger than the ring capacity must be rejected instead of
     /// writing past the end of the mapping.
     #[test]
@@ -546,7 +932,7 @@ mod tests {
                             // yield occasionally so oversubscribed CI runners
                             // still make progress.
                             spins += 1;
-                            if spins % 128 == 0 {
+                            if spins.is_multiple_of(128) {
                                 std::thread::yield_now();
                             } else {
                                 std::hint::spin_loop();
@@ -36,12 +36,27 @@ impl<M: MessageBus> CondvarBus<M> {
 }
 
 impl<MB: MessageBus> traits::core::Writer for CondvarBus<MB> {
+    #[inline]
     fn write<M: traits::core::Message, H: traits::core::Handler, F: FnOnce(&mut [u8])>(
         &self,
         size: usize,
         callback: F,
     ) {
-        self.inner.message_bus.write::<M, H, F>(size, callback);
+        traits::core::RawWriter::write_raw(self, H::ID.into(), M::ID.into(), size, callback);
+    }
+}
+
+impl<MB: MessageBus> traits::core::RawWriter for CondvarBus<MB> {
+    fn write_raw<F: FnOnce(&mut [u8])>(
+        &self,
+        source: u16,
+        message_id: u16,
+        size: usize,
+        callback: F,
+    ) {
+        self.inner
+            .message_bus
+            .write_raw(source, message_id, size, callback);
         // Dekker-style pairing with read(): the SeqCst fence orders the
         // message publication before the waiters load, and readers increment
         // waiters (SeqCst) before their final availability re-check. So if we
@@ -59,12 +74,20 @@ impl<MB: MessageBus> traits::core::Writer for CondvarBus<MB> {
 
 impl<MB: MessageBus> traits::core::Reader for CondvarBus<MB> {
     fn read(&self, position: usize) -> Option<(&messenger::Header, &[u8])> {
+        self.try_read(position)
+            .unwrap_or_else(|lapped| panic!("{lapped}"))
+    }
+
+    fn try_read(
+        &self,
+        position: usize,
+    ) -> Result<Option<(&messenger::Header, &[u8])>, messenger::Lapped> {
         loop {
-            if let Some(result) = self.inner.message_bus.read(position) {
-                return Some(result);
+            if let Some(result) = self.inner.message_bus.try_read(position)? {
+                return Ok(Some(result));
             }
             if self.inner.stop.load(std::sync::atomic::Ordering::Relaxed) {
-                return None;
+                return Ok(None);
             }
 
             let guard = self.inner.lock.lock().unwrap();
@@ -73,7 +96,7 @@ impl<MB: MessageBus> traits::core::Reader for CondvarBus<MB> {
             self.inner
                 .waiters
                 .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-            if self.inner.message_bus.read(position).is_none()
+            if matches!(self.inner.message_bus.try_read(position), Ok(None))
                 && !self.inner.stop.load(std::sync::atomic::Ordering::Relaxed)
             {
                 drop(self.inner.cvar.wait(guard).unwrap());
@@ -87,6 +110,20 @@ impl<MB: MessageBus> traits::core::Reader for CondvarBus<MB> {
     }
 }
 
+impl<MB: MessageBus + traits::core::Positions> traits::core::Positions for CondvarBus<MB> {
+    fn write_head(&self) -> usize {
+        self.inner.message_bus.write_head()
+    }
+
+    fn oldest(&self) -> usize {
+        self.inner.message_bus.oldest()
+    }
+
+    fn skip(&self, position: usize) -> Option<usize> {
+        self.inner.message_bus.skip(position)
+    }
+}
+
 impl<MB: MessageBus> traits::core::MessageBus for CondvarBus<MB> {
     fn on_stop(&self) {
         self.inner
@@ -95,6 +132,22 @@ impl<MB: MessageBus> traits::core::MessageBus for CondvarBus<MB> {
         let _guard = self.inner.lock.lock().unwrap();
         self.inner.cvar.notify_all();
     }
+
+    fn positions(&self) -> Option<&dyn traits::core::Positions> {
+        self.inner.message_bus.positions()
+    }
+
+    fn committed_offset(&self, consumer: &str) -> Option<usize> {
+        self.inner.message_bus.committed_offset(consumer)
+    }
+
+    fn commit_offset(&self, consumer: &str, position: usize) -> std::io::Result<()> {
+        self.inner.message_bus.commit_offset(consumer, position)
+    }
+
+    fn history_end(&self) -> usize {
+        self.inner.message_bus.history_end()
+    }
 }
 
 #[cfg(test)]
@@ -1,3 +1,4 @@
+use crate::bus_file;
 use crate::messenger;
 use crate::mmap::linux::extending_mmap;
 use crate::traits;
@@ -19,11 +20,29 @@ use crate::traits;
 /// Reopening an existing file resumes appending after the last committed
 /// message, while readers can replay the prior history from position 0.
 ///
+/// The first page of the file is a [`FileHeader`](bus_file::FileHeader):
+/// reopening checks that the file was written by a build with the same
+/// message header layout, with the same page length and
+/// [schema id](Config::get_schema_id), and refuses files without a header.
+///
+/// Consumer offsets committed with
+/// [`commit_offset`](traits::core::MessageBus::commit_offset) are kept in a
+/// sidecar file next to the bus file (`<file>.offsets`), so workers declared
+/// `start: Committed` resume where they left off instead of replaying the
+/// whole history. The sidecar records the creation time and schema id of the
+/// bus file: reopening fails with an `InvalidData` error if the sidecar was
+/// written for another bus file, such as one deleted and created again at
+/// the same path, or holds an offset that is not a message boundary of the
+/// file. Messages found on reopen end at
+/// [`history_end`](traits::core::MessageBus::history_end): workers reading
+/// them are replaying, which handlers can observe through
+/// `Handler::on_replay_complete` and `Handler::REPLAY_WRITES`.
+///
 /// # Caveats
 ///
 /// * Linux-only (relies on `fallocate`).
-/// * Capacity is fixed at `page_size * max_pages` of reserved address space;
-///   writing past it panics. History is never reclaimed — size the
+/// * Capacity is fixed at `page_size * (max_pages - 1)` of reserved address
+///   space, the header page aside; writing past it panics. History is never reclaimed — size the
 ///   reservation for the lifetime of the bus.
 /// * A write callback that panics leaves an uncommitted hole; readers and
 ///   the reopen scan stop at the hole.
@@ -34,7 +53,116 @@ pub struct ExtendingBus {
 
 struct Inner {
     mmap: extending_mmap::ExtendingMmap,
+    header: bus_file::FileHeader,
     write_head: std::sync::atomic::AtomicUsize,
+    /// The write head found by the reopen scan.
+    history_end: usize,
+    offsets: Offsets,
+}
+
+/// Committed consumer offsets, persisted as one `<position> <consumer>` line
+/// per consumer after a `bus <created> <schema id>` line naming the bus file
+/// they belong to. Every commit rewrites the whole (small) file through a
+/// synced temporary file and a rename, so a crash leaves either the old or
+/// the new offsets, never a torn mix. The directory is not synced: a crash
+/// may roll back to the previous offsets, whose consumers then handle the
+/// messages since again, as they would after any uncommitted crash.
+struct Offsets {
+    path: std::path::PathBuf,
+    /// The first line of the file.
+    bus: String,
+    committed: std::sync::Mutex<std::collections::BTreeMap<String, usize>>,
+}
+
+impl Offsets {
+    /// Reads the offsets of the bus file at `bus_path`, which has `header`.
+    /// Offsets written for another bus file, such as one deleted and created
+    /// again at the same path, fail with `InvalidData`.
+    fn open(bus_path: &std::path::Path, header: &bus_file::FileHeader) -> std::io::Result<Offsets> {
+        let mut path = bus_path.as_os_str().to_owned();
+        path.push(".offsets");
+        let path = std::path::PathBuf::from(path);
+        let bus = format!("bus {} {}", header.created_nanos(), header.schema_id);
+
+        let mut committed = std::collections::BTreeMap::new();
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                let mut lines = contents.lines().peekable();
+                let written_for = lines.next_if(|line| line.starts_with("bus ")).unwrap_or_default();
+                for line in lines {
+                    let parsed = line
+                        .split_once(' ')
+                        .and_then(|(position, consumer)| Some((consumer, position.parse().ok()?)));
+                    let Some((consumer, position)) = parsed else {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            format!("malformed line in {}: {line:?}", path.display()),
+                        ));
+                    };
+                    committed.insert(consumer.to_string(), position);
+                }
+                if written_for != bus && !committed.is_empty() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!(
+                            "{} was written for another bus file; delete it to start its consumers over",
+                            path.display()
+                        ),
+                    ));
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        Ok(Offsets {
+            path,
+            bus,
+            committed: std::sync::Mutex::new(committed),
+        })
+    }
+
+    /// Fails with `InvalidData` on the first offset for which `valid` is
+    /// false.
+    fn check(&self, valid: impl Fn(usize) -> bool) -> std::io::Result<()> {
+        match self.lock().iter().find(|(_, position)| !valid(**position)) {
+            Some((consumer, position)) => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "{}: the offset {position} of {consumer} is not a message boundary of the bus file",
+                    self.path.display()
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn get(&self, consumer: &str) -> Option<usize> {
+        self.lock().get(consumer).copied()
+    }
+
+    fn commit(&self, consumer: &str, position: usize) -> std::io::Result<()> {
+        use std::io::Write;
+
+        // Held across the write, so concurrent commits persist in order.
+        let mut committed = self.lock();
+        committed.insert(consumer.to_string(), position);
+
+        let mut contents = format!("{}\n", self.bus);
+        for (consumer, position) in committed.iter() {
+            contents.push_str(&format!("{position} {consumer}\n"));
+        }
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = std::fs::File::create(&temporary)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_data()?;
+        std::fs::rename(&temporary, &self.path)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, std::collections::BTreeMap<String, usize>> {
+        self.committed.lock().unwrap_or_else(|e| e.into_inner())
+    }
 }
 
 pub trait Config {
@@ -42,38 +170,107 @@ pub trait Config {
     /// Lower bound for the granularity the file grows by; rounded up to a
     /// power of two of at least the system page size.
     fn get_min_page_len(&self) -> usize;
-    /// Maximum number of pages, fixing the total capacity of the bus.
+    /// Maximum number of pages, fixing the total capacity of the bus. The
+    /// first page holds the file header.
     fn get_max_pages(&self) -> usize;
+    /// Identifies the messages the bus carries, e.g. a hash of their
+    /// definitions: a file created with another schema id does not open.
+    fn get_schema_id(&self) -> u64 {
+        0
+    }
+}
+
+impl Inner {
+    /// The first slot, after the header page.
+    fn slots(&self) -> *mut u8 {
+        unsafe { self.mmap.as_ptr().add(self.header.page_size) }
+    }
+
+    /// Bytes of slots currently mapped.
+    fn mapped_len(&self) -> usize {
+        self.mmap.mapped_len() - self.header.page_size
+    }
+
+    /// Bytes of slots the reservation can hold.
+    fn capacity(&self) -> usize {
+        self.mmap.reserved_len() - self.header.page_size
+    }
 }
 
 impl ExtendingBus {
+    /// Opens or creates the bus file, panicking if that fails; see
+    /// [`try_new`](ExtendingBus::try_new).
     pub fn new<C: Config>(config: &C) -> ExtendingBus {
+        ExtendingBus::try_new(config).unwrap_or_else(|e| {
+            panic!(
+                "opening the bus file {} failed: {e}",
+                config.get_file_path().display()
+            )
+        })
+    }
+
+    /// Opens or creates the bus file. A file whose header does not match
+    /// this build and `config` fails with an `InvalidData` error wrapping a
+    /// [`FileHeaderError`](bus_file::FileHeaderError).
+    pub fn try_new<C: Config>(config: &C) -> std::io::Result<ExtendingBus> {
         use traits::core::Reader;
 
+        let path = config.get_file_path();
+        let page_size = extending_mmap::align_page_size(config.get_min_page_len().max(1))
+            .map_err(std::io::Error::other)?;
+        // Checked before mapping, which fails less clearly on a mismatch.
+        let existing = bus_file::FileHeader::read(&path)?;
+        if let Some(header) = &existing {
+            header.check(page_size, config.get_schema_id())?;
+        }
         let mmap = extending_mmap::ExtendingMmap::new(
-            config.get_file_path(),
+            path.clone(),
             config.get_min_page_len(),
             config.get_max_pages(),
         )
-        .expect("opening the bus file failed");
-
-        let bus = ExtendingBus {
+        .map_err(std::io::Error::other)?;
+        let header = existing.unwrap_or_else(|| {
+            let header = bus_file::FileHeader::new(page_size, config.get_schema_id());
+            let encoded = header.encode();
+            // A new file is mapped with one zeroed page.
+            unsafe { std::ptr::copy_nonoverlapping(encoded.as_ptr(), mmap.as_ptr(), encoded.len()) };
+            header
+        });
+        let offsets = Offsets::open(&path, &header)?;
+
+        let mut bus = ExtendingBus {
             inner: std::sync::Arc::new(Inner {
                 mmap,
+                header,
                 write_head: std::sync::atomic::AtomicUsize::new(0),
+                history_end: 0,
+                offsets,
             }),
         };
 
         // Resume appending after the last committed message of an existing
-        // file (a fresh file scans straight to 0).
+        // file (a fresh file scans straight to 0). Committed offsets must be
+        // message boundaries the scan passes.
+        let mut unchecked: std::collections::BTreeSet<usize> =
+            bus.inner.offsets.lock().values().copied().collect();
         let mut end = 0;
+        unchecked.remove(&end);
         while let Some((header, _)) = bus.read(end) {
             end += header.slot_len();
+            unchecked.remove(&end);
         }
+        bus.inner.offsets.check(|position| !unchecked.contains(&position))?;
         bus.inner
             .write_head
             .store(end, std::sync::atomic::Ordering::Relaxed);
-        bus
+        let inner = std::sync::Arc::get_mut(&mut bus.inner).expect("the bus is not shared yet");
+        inner.history_end = end;
+        Ok(bus)
+    }
+
+    /// The header of the bus file.
+    pub fn header(&self) -> &bus_file::FileHeader {
+        &self.inner.header
     }
 }
 
@@ -83,6 +280,19 @@ impl traits::core::Writer for ExtendingBus {
         &self,
         size: usize,
         callback: F,
+    ) {
+        traits::core::RawWriter::write_raw(self, H::ID.into(), M::ID.into(), size, callback);
+    }
+}
+
+impl traits::core::RawWriter for ExtendingBus {
+    #[inline]
+    fn write_raw<F: FnOnce(&mut [u8])>(
+        &self,
+        source: u16,
+        message_id: u16,
+        size: usize,
+        callback: F,
     ) {
         let aligned_size = messenger::align_to_usize(size);
         let len = messenger::ALIGNED_HEADER_SIZE + aligned_size;
@@ -99,16 +309,16 @@ impl traits::core::Writer for ExtendingBus {
             .checked_add(len)
             .expect("bus position overflowed usize");
         assert!(
-            end <= self.inner.mmap.reserved_len(),
+            end <= self.inner.capacity(),
             "bus file capacity exhausted ({} bytes reserved in pages of {})",
-            self.inner.mmap.reserved_len(),
+            self.inner.capacity(),
             self.inner.mmap.page_size(),
         );
 
         // Grow the file until the slot is backed by mapped pages. Only one
         // writer extends at a time; contenders spin on the mapped length.
         // The capacity assert above guarantees this loop terminates.
-        while self.inner.mmap.mapped_len() < end {
+        while self.inner.mapped_len() < end {
             match self.inner.mmap.extend() {
                 Ok(_) => {}
                 Err(extending_mmap::ExtendingMmapError::AlreadyExtending) => {
@@ -118,7 +328,7 @@ impl traits::core::Writer for ExtendingBus {
             }
         }
 
-        let ptr = unsafe { self.inner.mmap.as_ptr().add(position) };
+        let ptr = unsafe { self.inner.slots().add(position) };
 
         let hdr_ptr = ptr as *mut messenger::Header;
         // Field projection through the raw pointer: borrows only the atomic
@@ -139,11 +349,13 @@ impl traits::core::Writer for ExtendingBus {
                 0,
                 aligned_size - size,
             );
-            std::ptr::addr_of_mut!((*hdr_ptr).source).write(H::ID.into());
-            std::ptr::addr_of_mut!((*hdr_ptr).message_id).write(M::ID.into());
+            std::ptr::addr_of_mut!((*hdr_ptr).source).write(source);
+            std::ptr::addr_of_mut!((*hdr_ptr).message_id).write(message_id);
             // The exact payload length; the padded length the slot occupies is
             // derived from it via Header::aligned_size when walking slots.
             std::ptr::addr_of_mut!((*hdr_ptr).size).write(size as u32);
+            #[cfg(feature = "timestamps")]
+            std::ptr::addr_of_mut!((*hdr_ptr).timestamp).write(messenger::timestamp_now());
         }
 
         let msg_ptr = unsafe { ptr.add(messenger::ALIGNED_HEADER_SIZE) };
@@ -166,13 +378,13 @@ impl traits::core::Reader for ExtendingBus {
         // Never touch bytes beyond the mapped length: the remainder of the
         // reservation is PROT_NONE. The Acquire load pairs with the Release
         // in extend(), making freshly mapped pages visible.
-        let mapped = self.inner.mmap.mapped_len();
+        let mapped = self.inner.mapped_len();
         match position.checked_add(messenger::ALIGNED_HEADER_SIZE) {
             Some(slot_end) if slot_end <= mapped => {}
             _ => return None,
         }
 
-        let ptr = unsafe { self.inner.mmap.as_ptr().add(position) };
+        let ptr = unsafe { self.inner.slots().add(position) };
         let header_ptr = ptr as *const messenger::Header;
 
         // A slot is only readable once its writer committed it for exactly
@@ -197,7 +409,38 @@ impl traits::core::Reader for ExtendingBus {
     }
 }
 
-impl traits::core::MessageBus for ExtendingBus {}
+impl traits::core::Positions for ExtendingBus {
+    fn write_head(&self) -> usize {
+        self.inner
+            .write_head
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Nothing is ever overwritten: the whole history is readable.
+    fn oldest(&self) -> usize {
+        0
+    }
+}
+
+impl traits::core::MessageBus for ExtendingBus {
+    fn positions(&self) -> Option<&dyn traits::core::Positions> {
+        Some(self)
+    }
+
+    fn committed_offset(&self, consumer: &str) -> Option<usize> {
+        self.inner.offsets.get(consumer)
+    }
+
+    /// Rewrites the offsets file; see `ExtendingBus` for what survives a
+    /// crash.
+    fn commit_offset(&self, consumer: &str, position: usize) -> std::io::Result<()> {
+        self.inner.offsets.commit(consumer, position)
+    }
+
+    fn history_end(&self) -> usize {
+        self.inner.history_end
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -236,12 +479,14 @@ mod tests {
         const ID: u16 = 1;
     }
 
-    /// One MsgA slot: 16 byte header prefix + 16 byte aligned payload.
+    /// One MsgA slot: header prefix + 16 byte aligned payload.
     const SLOT: usize = messenger::ALIGNED_HEADER_SIZE + 16;
 
     struct Cfg {
         path: std::path::PathBuf,
+        min_page_len: usize,
         max_pages: usize,
+        schema_id: u64,
     }
 
     impl Config for Cfg {
@@ -249,11 +494,14 @@ mod tests {
             self.path.clone()
         }
         fn get_min_page_len(&self) -> usize {
-            1 // rounded up to the system page size
+            self.min_page_len
         }
         fn get_max_pages(&self) -> usize {
             self.max_pages
         }
+        fn get_schema_id(&self) -> u64 {
+            self.schema_id
+        }
     }
 
     fn temp_cfg(name: &str, max_pages: usize) -> Cfg {
@@ -262,7 +510,12 @@ mod tests {
             std::process::id()
         ));
         let _ = std::fs::remove_file(&path);
-        Cfg { path, max_pages }
+        Cfg {
+            path,
+            min_page_len: 1, // rounded up to the system page size
+            max_pages,
+            schema_id: 0,
+        }
     }
 
     fn send(bus: &ExtendingBus, first: u16) {
@@ -356,7 +609,7 @@ mod tests {
         let bus = ExtendingBus::new(&cfg);
 
         // One message more than fits in the whole reservation.
-        let capacity = bus.inner.mmap.reserved_len();
+        let capacity = bus.inner.capacity();
         for i in 0..=(capacity / SLOT) as u16 {
             send(&bus, i);
         }
@@ -365,7 +618,7 @@ mod tests {
     #[test]
     #[cfg_attr(miri, ignore)]
     fn test_concurrent_writers() {
-        let cfg = temp_cfg("concurrent", 8);
+        let cfg = temp_cfg("concurrent", 16);
         let bus = ExtendingBus::new(&cfg);
 
         let threads = 4;
@@ -399,4 +652,115 @@ mod tests {
         drop(bus);
         std::fs::remove_file(&cfg.path).unwrap();
     }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_committed_offsets_survive_reopen() {
+        use crate::traits::core::MessageBus;
+
+        let cfg = temp_cfg("offsets", 4);
+        let offsets = std::path::PathBuf::from(format!("{}.offsets", cfg.path.display()));
+        let _ = std::fs::remove_file(&offsets);
+        {
+            let bus = ExtendingBus::new(&cfg);
+            for i in 0..3 {
+                send(&bus, i);
+            }
+            assert_eq!(bus.committed_offset("Mailer"), None);
+            bus.commit_offset("Mailer", SLOT).unwrap();
+            bus.commit_offset("Audit.1", 3 * SLOT).unwrap();
+            bus.commit_offset("Mailer", 2 * SLOT).unwrap();
+        }
+
+        let bus = ExtendingBus::new(&cfg);
+        assert_eq!(bus.committed_offset("Mailer"), Some(2 * SLOT));
+        assert_eq!(bus.committed_offset("Audit.1"), Some(3 * SLOT));
+        assert_eq!(bus.committed_offset("Audit.0"), None);
+
+        // Past the history, or inside a message: not a resumable position.
+        for (consumer, position) in [("Audit.1", 4 * SLOT), ("Audit.0", SLOT + 8)] {
+            bus.commit_offset(consumer, position).unwrap();
+            let error = ExtendingBus::try_new(&cfg).err().expect("a stray offset must not open");
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+            assert!(error.to_string().contains(consumer), "{error}");
+            bus.commit_offset(consumer, 3 * SLOT).unwrap();
+        }
+        drop(bus);
+        let bus = ExtendingBus::new(&cfg);
+        assert_eq!(bus.committed_offset("Audit.0"), Some(3 * SLOT));
+
+        drop(bus);
+        std::fs::write(&offsets, "12 Mailer\nnot a line\n").unwrap();
+        let reopened = std::panic::catch_unwind(|| ExtendingBus::new(&cfg));
+        assert!(reopened.is_err(), "a corrupt offsets file must not be ignored");
+
+        std::fs::remove_file(&cfg.path).unwrap();
+        std::fs::remove_file(&offsets).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn test_header_mismatches_are_rejected() {
+        let cfg = Cfg {
+            schema_id: 7,
+            ..temp_cfg("header", 4)
+        };
+        let before = std::time::SystemTime::now();
+        let page_size = {
+            let bus = ExtendingBus::new(&cfg);
+            let header = bus.header();
+            assert_eq!(header.version, bus_file::VERSION);
+            assert_eq!(header.layout, bus_file::Layout::native());
+            assert_eq!(header.schema_id, 7);
+            assert!(header.created >= before);
+            send(&bus, 42);
+            header.page_size
+        };
+        let reopened = ExtendingBus::new(&cfg);
+        let (_, buffer) = reopened.read(0).expect("history should replay");
+        assert_eq!(first_value(buffer), 42);
+        drop(reopened);
+
+        let rejected = |min_page_len: usize, schema_id: u64| {
+            let other = Cfg {
+                path: cfg.path.clone(),
+                min_page_len,
+                max_pages: 4,
+                schema_id,
+            };
+            let e = ExtendingBus::try_new(&other).err().expect("the file must not open");
+            assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+            *e.into_inner()
+                .unwrap()
+                .downcast::<bus_file::FileHeaderError>()
+                .unwrap()
+        };
+        assert_eq!(
+            rejected(1, 8),
+            bus_file::FileHeaderError::SchemaId { file: 7, config: 8 }
+        );
+        assert_eq!(
+            rejected(2 * page_size, 7),
+            bus_file::FileHeaderError::PageSize {
+                file: page_size,
+                config: 2 * page_size
+            }
+        );
+
+        // Written by a build with the other setting of `timestamps`.
+        let mut bytes = std::fs::read(&cfg.path).unwrap();
+        bytes[12] ^= 2;
+        std::fs::write(&cfg.path, &bytes).unwrap();
+        assert!(matches!(
+            rejected(1, 7),
+            bus_file::FileHeaderError::Layout { file, build }
+                if file.timestamps != build.timestamps
+        ));
+
+        // Slots from byte 0, as written before files had a header.
+        bytes.drain(..page_size);
+        std::fs::write(&cfg.path, &bytes).unwrap();
+        assert_eq!(rejected(1, 7), bus_file::FileHeaderError::NotABusFile);
+        std::fs::remove_file(&cfg.path).unwrap();
+    }
 }
@@ -1,3 +1,5 @@
+use crate::traits;
+
 #[repr(C)]
 pub struct Header {
     pub source: u16,
@@ -10,9 +12,15 @@ pub struct Header {
     /// `u32` (vs the old `u16`) raises the per-message payload limit from 64 KiB
     /// to whatever the bus ring allows.
     pub size: u32,
+    /// With the `timestamps` feature: wall-clock time of the write, in
+    /// nanoseconds since the Unix epoch, taken when the slot is reserved.
+    /// Recordings keep their original pace through it; see `replay`.
+    #[cfg(feature = "timestamps")]
+    pub timestamp: u64,
     /// Publication stamp: 0 while the slot is unwritten or in flight,
     /// [`Header::commit_stamp_for`]`(position)` once the message is
-    /// committed. Maintained exclusively by the bus implementations.
+    /// committed. A `CircularBus` sets [`Header::sized_stamp_for`] in
+    /// between. Maintained exclusively by the bus implementations.
     pub(crate) commit_stamp: std::sync::atomic::AtomicU64,
 }
 
@@ -24,6 +32,14 @@ impl Header {
         position as u64 + 1
     }
 
+    /// Stamp of a slot whose header is written but whose payload is not
+    /// committed (still being written, or its writer panicked): its length
+    /// is known, yet readers must not see it. Positions are usize-aligned,
+    /// so it never equals a commit stamp.
+    pub(crate) const fn sized_stamp_for(position: usize) -> u64 {
+        position as u64 + 2
+    }
+
     /// Padded payload length — the number of payload bytes the slot occupies,
     /// derived from the exact [`Header::size`].
     #[inline]
@@ -44,6 +60,14 @@ impl Header {
 const _: () = assert!(std::mem::align_of::<Header>() <= std::mem::size_of::<usize>());
 
 pub const HEADER_SIZE: usize = std::mem::size_of::<Header>();
+
+/// The current wall-clock time as stored in [`Header::timestamp`].
+#[cfg(feature = "timestamps")]
+pub fn timestamp_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |since_epoch| since_epoch.as_nanos() as u64)
+}
 /// Size of the per-message slot prefix. The message payload starts at this
 /// offset within a slot.
 pub const ALIGNED_HEADER_SIZE: usize = align_to_usize(HEADER_SIZE);
@@ -54,18 +78,938 @@ pub const fn align_to_usize(from: usize) -> usize {
     ((from + (1 << BITS) - 1) >> BITS) << BITS
 }
 
+/// A reader fell so far behind the writers that the message at `position`
+/// was overwritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lapped {
+    pub position: usize,
+    pub write_head: usize,
+}
+
+impl std::fmt::Display for Lapped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "reader at position {} fell behind the writers (write head {}) and its \
+             messages were overwritten; increase the bus buffer size or consume faster",
+            self.position, self.write_head
+        )
+    }
+}
+
+impl std::error::Error for Lapped {}
+
+/// Where a message came from and where it sits on the bus, passed to
+/// [`HandleWithContext`](traits::core::HandleWithContext) handlers.
+#[derive(Clone, Copy)]
+pub struct MessageContext<'a> {
+    /// Source id of the sender, for replying to it or auditing.
+    pub source: u16,
+    pub message_id: u16,
+    /// Payload length in bytes, without the header and alignment padding.
+    pub size: usize,
+    /// Bus position of the message.
+    pub position: usize,
+    /// Name of the worker in `Messenger!`.
+    pub worker: &'static str,
+    pub replica: usize,
+    /// Whether the message was on the bus when it was opened (see
+    /// [`MessageBus::history_end`](traits::core::MessageBus::history_end)).
+    pub replaying: bool,
+    #[cfg(feature = "timestamps")]
+    timestamp: u64,
+    /// Commits the offset of the worker, given the position to resume at.
+    commit: Option<&'a dyn Fn(usize) -> std::io::Result<()>>,
+}
+
+impl MessageContext<'static> {
+    pub fn new(header: &Header, position: usize, worker: &'static str, replica: usize, replaying: bool) -> MessageContext<'static> {
+        MessageContext {
+            source: header.source,
+            message_id: header.message_id,
+            size: header.size as usize,
+            position,
+            worker,
+            replica,
+            replaying,
+            #[cfg(feature = "timestamps")]
+            timestamp: header.timestamp,
+            commit: None,
+        }
+    }
+}
+
+impl<'a> MessageContext<'a> {
+    /// The context with `commit` storing the worker's offset, as workers
+    /// generated by `Messenger!` pass it (see [`commit`](MessageContext::commit)).
+    pub fn with_commit(self, commit: &'a dyn Fn(usize) -> std::io::Result<()>) -> MessageContext<'a> {
+        MessageContext {
+            commit: Some(commit),
+            ..self
+        }
+    }
+
+    /// The context without its commit handle, for keeping past the handler
+    /// call.
+    pub fn without_commit(&self) -> MessageContext<'static> {
+        MessageContext { commit: None, ..*self }
+    }
+
+    /// Commits the offset of the worker once the side effects of this
+    /// message are durable: a worker declared `start: Committed` resumes
+    /// after this message. The offset is stored by the bus (see
+    /// [`MessageBus::commit_offset`](traits::core::MessageBus::commit_offset))
+    /// before this returns, and its error is returned; a context without a
+    /// commit handle fails with `ErrorKind::Unsupported`.
+    ///
+    /// The offset belongs to the worker, not the handler: it covers every
+    /// handler of the worker, so give handlers that make their effects
+    /// durable at different times their own workers.
+    pub fn commit(&self) -> std::io::Result<()> {
+        let commit = self.commit.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("the context of the message at {} has no commit handle", self.position),
+            )
+        })?;
+        commit(self.next_position())
+    }
+
+    /// When the message was written, from [`Header::timestamp`].
+    #[cfg(feature = "timestamps")]
+    pub fn timestamp(&self) -> std::time::SystemTime {
+        std::time::UNIX_EPOCH + std::time::Duration::from_nanos(self.timestamp)
+    }
+
+    /// Position of the next message, where a reader resumes once this one
+    /// is done.
+    pub fn next_position(&self) -> usize {
+        self.position + ALIGNED_HEADER_SIZE + align_to_usize(self.size)
+    }
+}
+
+impl std::fmt::Debug for MessageContext<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("MessageContext");
+        debug
+            .field("source", &self.source)
+            .field("message_id", &self.message_id)
+            .field("size", &self.size)
+            .field("position", &self.position)
+            .field("worker", &self.worker)
+            .field("replica", &self.replica)
+            .field("replaying", &self.replaying);
+        #[cfg(feature = "timestamps")]
+        debug.field("timestamp", &self.timestamp);
+        debug.field("commit", &self.commit.is_some()).finish()
+    }
+}
+
+/// Compares the message fields; the commit handles are not compared.
+impl PartialEq for MessageContext<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        #[cfg(feature = "timestamps")]
+        if self.timestamp != other.timestamp {
+            return false;
+        }
+        (self.source, self.message_id, self.size, self.position)
+            == (other.source, other.message_id, other.size, other.position)
+            && (self.worker, self.replica, self.replaying) == (other.worker, other.replica, other.replaying)
+    }
+}
+
+impl Eq for MessageContext<'_> {}
+
+/// Source-id marker: a [`Handler`](traits::core::Handler) whose `ID` is the
+/// const parameter. Route entries name a handler instance (or any other
+/// synthetic source) by its id through this type, e.g.
+/// `messenger::Source<10>, messages::Quote: [ ... ]`.
+pub struct Source<const SOURCE: u16>;
+
+impl<const SOURCE: u16> traits::core::Handler for Source<SOURCE> {
+    type Id = u16;
+    const ID: u16 = SOURCE;
+}
+
+/// A handler instance with its own source id, generated by `Messenger!` for
+/// handlers declared as `name @ 10: Type`.
+///
+/// Every hook and `handle` call is forwarded to the wrapped handler with an
+/// [`InstanceWriter`], so everything the instance sends is stamped with
+/// `SOURCE` instead of the shared `H::ID`.
+pub struct Instance<H, const SOURCE: u16> {
+    handler: H,
+}
+
+impl<H, const SOURCE: u16> Instance<H, SOURCE> {
+    pub fn new(handler: H) -> Instance<H, SOURCE> {
+        Instance { handler }
+    }
+
+    pub fn inner(&self) -> &H {
+        &self.handler
+    }
+
+    pub fn inner_mut(&mut self) -> &mut H {
+        &mut self.handler
+    }
+}
+
+impl<H: traits::core::Handler + 'static, const SOURCE: u16> traits::core::Handler for Instance<H, SOURCE> {
+    type Id = u16;
+    const ID: u16 = SOURCE;
+
+    fn on_start<W: traits::core::Writer>(&mut self, writer: &W) {
+        self.handler
+            .on_start(InstanceWriter::<W, H, SOURCE>::from_ref(writer));
+    }
+
+    fn on_loop<W: traits::core::Writer>(&mut self, writer: &W) {
+        self.handler
+            .on_loop(InstanceWriter::<W, H, SOURCE>::from_ref(writer));
+    }
+
+    fn on_stop(&mut self) {
+        self.handler.on_stop();
+    }
+
+    const ON_PANIC: Supervision = H::ON_PANIC;
+
+    fn on_panic(&mut self, event: &PanicEvent) {
+        self.handler.on_panic(event);
+    }
+
+    const REPLAY_WRITES: ReplayWrites = H::REPLAY_WRITES;
+
+    fn on_replay_complete<W: traits::core::Writer>(&mut self, writer: &W) {
+        self.handler
+            .on_replay_complete(InstanceWriter::<W, H, SOURCE>::from_ref(writer));
+    }
+}
+
+impl<H, const SOURCE: u16> Instance<H, SOURCE> {
+    /// Forwards to the wrapped handler's
+    /// [`HandleWithContext`](traits::core::HandleWithContext), also for
+    /// handlers that implement it directly instead of `Handle`. An inherent
+    /// method rather than trait impls, which would overlap the blanket impls
+    /// for every `Handle` and every [`rpc::Caller`](crate::rpc::Caller).
+    #[inline]
+    pub fn handle_with_context<M, W>(&mut self, message: &M, context: &MessageContext, writer: &W)
+    where
+        M: traits::core::Message,
+        H: traits::core::Handler + traits::core::HandleWithContext<M> + 'static,
+        W: traits::core::Writer,
+    {
+        self.handler.handle_with_context(
+            message,
+            context,
+            InstanceWriter::<W, H, SOURCE>::from_ref(writer),
+        );
+    }
+}
+
+/// Writer of an instance of the handler `I`, stamping every message with
+/// `SOURCE` regardless of the sending handler type.
+///
+/// `repr(transparent)` over the wrapped writer, so a `&W` can be viewed as a
+/// `&InstanceWriter<W, I, SOURCE>` for free: the worker loop keeps passing
+/// the bus it already holds, with no clone per dispatch.
+#[repr(transparent)]
+pub struct InstanceWriter<W, I, const SOURCE: u16>(W, std::marker::PhantomData<fn() -> I>);
+
+impl<W: Clone, I, const SOURCE: u16> Clone for InstanceWriter<W, I, SOURCE> {
+    fn clone(&self) -> InstanceWriter<W, I, SOURCE> {
+        InstanceWriter(self.0.clone(), std::marker::PhantomData)
+    }
+}
+
+impl<W, I, const SOURCE: u16> InstanceWriter<W, I, SOURCE> {
+    pub fn new(writer: W) -> InstanceWriter<W, I, SOURCE> {
+        InstanceWriter(writer, std::marker::PhantomData)
+    }
+
+    pub fn from_ref(writer: &W) -> &InstanceWriter<W, I, SOURCE> {
+        // SAFETY: InstanceWriter is repr(transparent) over W, so both types
+        // have the same layout and the reference stays valid for as long as
+        // the borrowed writer.
+        unsafe { &*(writer as *const W as *const InstanceWriter<W, I, SOURCE>) }
+    }
+}
+
+impl<W, I, const SOURCE: u16> traits::core::Writer for InstanceWriter<W, I, SOURCE>
+where
+    W: traits::core::Writer,
+    I: traits::core::Handler + 'static,
+{
+    #[inline]
+    fn write<M: traits::core::Message, H: traits::core::Handler, F: FnOnce(&mut [u8])>(
+        &self,
+        size: usize,
+        callback: F,
+    ) {
+        self.0.write::<M, Source<SOURCE>, F>(size, callback);
+    }
+}
+
+impl<W, I, const SOURCE: u16> traits::core::RawWriter for InstanceWriter<W, I, SOURCE>
+where
+    W: traits::core::Writer,
+    I: traits::core::Handler + 'static,
+{
+    /// Raw writes stamped as `I`, such as the timers the instance
+    /// scheduled as `Self`, are stamped with `SOURCE`; other sources pass
+    /// through unchanged.
+    #[inline]
+    fn write_raw<F: FnOnce(&mut [u8])>(
+        &self,
+        source: u16,
+        message_id: u16,
+        size: usize,
+        callback: F,
+    ) {
+        let source = if source == I::ID.into() { SOURCE } else { source };
+        self.0.write_raw(source, message_id, size, callback);
+    }
+}
+
+/// What happens to the writes of a handler while its worker replays history
+/// written before the bus was opened, chosen per handler type through
+/// [`Handler::REPLAY_WRITES`](traits::core::Handler::REPLAY_WRITES).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayWrites {
+    /// Write as usual, e.g. for handlers that only publish derived state.
+    Publish,
+    /// Drop the writes: the responses to replayed messages are already part
+    /// of the history.
+    Suppress,
+}
+
+/// The [`ReplayWrites`] policy of `H`, usable where the handler type is
+/// only known through a value, as in the code generated by `Messenger!`.
+pub fn replay_writes_of<H: traits::core::Handler>(_handler: &H) -> ReplayWrites {
+    H::REPLAY_WRITES
+}
+
+/// Writer that drops every write, handed to handlers with
+/// [`ReplayWrites::Suppress`] while their worker replays history.
+///
+/// `repr(transparent)` like [`InstanceWriter`], so the worker can view the
+/// bus it holds as a `&SuppressedWriter` without cloning it.
+#[repr(transparent)]
+#[derive(Clone)]
+pub struct SuppressedWriter<W>(W);
+
+impl<W: traits::core::Writer> SuppressedWriter<W> {
+    pub fn from_ref(writer: &W) -> &SuppressedWriter<W> {
+        // SAFETY: SuppressedWriter is repr(transparent) over W.
+        unsafe { &*(writer as *const W as *const SuppressedWriter<W>) }
+    }
+}
+
+impl<W: traits::core::Writer> traits::core::Writer for SuppressedWriter<W> {
+    #[inline]
+    fn write<M: traits::core::Message, H: traits::core::Handler, F: FnOnce(&mut [u8])>(
+        &self,
+        _size: usize,
+        _callback: F,
+    ) {
+    }
+}
+
+impl<W: traits::core::Writer> traits::core::RawWriter for SuppressedWriter<W> {
+    #[inline]
+    fn write_raw<F: FnOnce(&mut [u8])>(
+        &self,
+        _source: u16,
+        _message_id: u16,
+        _size: usize,
+        _callback: F,
+    ) {
+    }
+}
+
+/// Publishes onto the bus from code outside the Messenger (`main`, signal
+/// handlers, third-party callbacks), stamped with the synthetic source id
+/// `SOURCE`. Obtained from `Messenger::injector`; routes name it as a source
+/// through [`Source<SOURCE>`].
+#[derive(Clone)]
+pub struct Injector<W, const SOURCE: u16> {
+    writer: W,
+}
+
+impl<W: traits::core::Writer, const SOURCE: u16> Injector<W, SOURCE> {
+    pub fn new(writer: W) -> Injector<W, SOURCE> {
+        Injector { writer }
+    }
+
+    pub fn send<M: traits::extended::ExtendedMessage>(&self, message: &M) {
+        <Source<SOURCE> as traits::extended::Sender>::send(message, &self.writer);
+    }
+
+    /// Zero-copy send: `callback` writes the message in place on the bus.
+    pub fn send_zero_copy<M: traits::zero_copy::ZeroCopyMessage, F: FnOnce(*mut M)>(
+        &self,
+        callback: F,
+    ) {
+        <Source<SOURCE> as traits::zero_copy::Sender>::send::<M, W, F>(&self.writer, callback);
+    }
+}
+
+/// Where a worker starts reading, set per worker in `Messenger!` with
+/// `start: Tail` (default `Beginning`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StartAt {
+    /// Position 0. On a `CircularBus` that has already wrapped, the first
+    /// read panics because the reader fell behind.
+    #[default]
+    Beginning,
+    /// The write head: only messages written after the worker started.
+    Tail,
+    /// The oldest message still intact in the bus.
+    Oldest,
+    /// The offset the worker last committed with
+    /// [`MessageContext::commit`], or position 0 if it never did. A
+    /// replicated worker resumes from the lowest offset of its replicas, so
+    /// some messages may be handled twice.
+    Committed,
+}
+
+impl StartAt {
+    /// The start position of `worker`, run as `replicas` replicas.
+    ///
+    /// Panics for `Tail` and `Oldest` if the bus has no
+    /// [`positions`](traits::core::MessageBus::positions).
+    pub fn position<MB: traits::core::MessageBus>(self, message_bus: &MB, worker: &str, replicas: usize) -> usize {
+        let positions = || {
+            message_bus
+                .positions()
+                .unwrap_or_else(|| panic!("worker {worker} starts at {self:?}, but the bus has no positions"))
+        };
+        match self {
+            StartAt::Beginning => 0,
+            StartAt::Tail => positions().write_head(),
+            StartAt::Oldest => positions().oldest(),
+            StartAt::Committed => Replica::group(replicas)
+                .iter()
+                .map(|replica| message_bus.committed_offset(&replica.worker_name(worker)).unwrap_or(0))
+                .min()
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// One copy of a worker declared with `replicas: N` in `Messenger!`.
+///
+/// Every replica reads every slot and handles broadcast routes itself;
+/// messages of `balanced` routes are handled by exactly one replica of the
+/// group, whichever claims them first, and messages of `sharded` routes by
+/// the replica owning their partition key.
+pub struct Replica {
+    index: usize,
+    count: usize,
+    claims: std::sync::Arc<ClaimCursor>,
+}
+
+impl Replica {
+    /// Creates the `count` replicas of one worker, sharing one claim cursor.
+    pub fn group(count: usize) -> Vec<Replica> {
+        assert!(count > 0, "a worker needs at least one replica");
+        let claims = std::sync::Arc::new(ClaimCursor::new());
+        (0..count)
+            .map(|index| Replica {
+                index,
+                count,
+                claims: claims.clone(),
+            })
+            .collect()
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// `worker` for a single replica, `worker.index` within a group.
+    pub fn worker_name(&self, worker: &str) -> String {
+        Replica::name(worker, self.index, self.count)
+    }
+
+    /// The [`worker_name`](Replica::worker_name) of replica `index` of
+    /// `count`.
+    pub fn name(worker: &str, index: usize, count: usize) -> String {
+        if count == 1 {
+            worker.to_string()
+        } else {
+            format!("{worker}.{index}")
+        }
+    }
+
+    /// Whether `name` is the name of `worker` or of one of its `count`
+    /// replicas.
+    pub fn names(name: &str, worker: &str, count: usize) -> bool {
+        if count == 1 {
+            return name == worker;
+        }
+        name == worker || (0..count).any(|index| name == format!("{worker}.{index}"))
+    }
+
+    /// Claims the balanced message at `position` for this replica; `false`
+    /// if another replica of the group already did.
+    #[inline]
+    pub fn try_claim(&self, position: usize, slot_len: usize) -> bool {
+        self.claims.try_claim(position, slot_len)
+    }
+
+    /// Whether `key` falls into this replica's shard, `hash(key) % count`,
+    /// with the [`ShardHasher`] hash.
+    #[inline]
+    pub fn owns<K: std::hash::Hash>(&self, key: &K) -> bool {
+        use std::hash::Hasher;
+
+        if self.count == 1 {
+            return true;
+        }
+        let mut hasher = ShardHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.count as u64) as usize == self.index
+    }
+}
+
+/// The hash that assigns partition keys to shards: 64-bit FNV-1a over the
+/// bytes `Hash` feeds it, with integers fed little-endian and `usize` as a
+/// `u64`. Unlike `std`'s `DefaultHasher` it is fixed across releases and
+/// platforms, so keys stay with their replica (and its persisted offsets)
+/// after a toolchain upgrade.
+pub struct ShardHasher(u64);
+
+impl ShardHasher {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    pub fn new() -> ShardHasher {
+        ShardHasher(ShardHasher::OFFSET_BASIS)
+    }
+}
+
+impl Default for ShardHasher {
+    fn default() -> ShardHasher {
+        ShardHasher::new()
+    }
+}
+
+impl std::hash::Hasher for ShardHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(ShardHasher::PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes());
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16);
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32);
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64);
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128);
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as i64 as u64);
+    }
+}
+
+/// Claim cursor shared by the replicas of a worker: the end position of the
+/// last claimed balanced message.
+///
+/// Replicas walk the bus in order, and every replica reaching a balanced
+/// message either claims it or finds the cursor already past it. So when a
+/// replica arrives at `position`, every balanced message before it has been
+/// claimed, and the cursor alone decides ownership of `position`: moving it
+/// from below `position` to the message end wins the message, finding it
+/// beyond `position` means another replica won. A slow replica never holds
+/// up the others, which skip what it claimed and take the next message.
+struct ClaimCursor {
+    claimed_until: std::sync::atomic::AtomicUsize,
+}
+
+impl ClaimCursor {
+    fn new() -> ClaimCursor {
+        ClaimCursor {
+            claimed_until: std::sync::atomic::AtomicUsize::new(0),
+        }
+    }
+
+    fn try_claim(&self, position: usize, slot_len: usize) -> bool {
+        let mut claimed_until = self
+            .claimed_until
+            .load(std::sync::atomic::Ordering::Acquire);
+        loop {
+            if claimed_until > position {
+                return false;
+            }
+            match self.claimed_until.compare_exchange_weak(
+                claimed_until,
+                position + slot_len,
+                std::sync::atomic::Ordering::AcqRel,
+                std::sync::atomic::Ordering::Acquire,
+            ) {
+                Ok(_) => return true,
+                Err(current) => claimed_until = current,
+            }
+        }
+    }
+}
+
+/// Thread settings of a worker, given to the Messenger with
+/// `with_thread_config` under a worker name (`Pool`) or a replica name
+/// (`Pool.1`); the replica name takes precedence.
+#[derive(Debug, Clone, Default)]
+pub struct ThreadConfig {
+    name: Option<String>,
+    affinity: Option<Vec<usize>>,
+    stack_size: Option<usize>,
+}
+
+impl ThreadConfig {
+    pub fn new() -> ThreadConfig {
+        ThreadConfig::default()
+    }
+
+    /// Thread name shown by debuggers, profilers and `top -H`; defaults to
+    /// the worker name. Replicas of a group get `name.index`. Linux keeps
+    /// the first 15 bytes only.
+    pub fn name(mut self, name: impl Into<String>) -> ThreadConfig {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// CPUs the thread may run on, applied with `sched_setaffinity` before
+    /// any handler is built. Linux only; ignored on other platforms.
+    pub fn affinity(mut self, cpus: impl IntoIterator<Item = usize>) -> ThreadConfig {
+        self.affinity = Some(cpus.into_iter().collect());
+        self
+    }
+
+    /// Stack size in bytes; defaults to the `std::thread` default.
+    pub fn stack_size(mut self, bytes: usize) -> ThreadConfig {
+        self.stack_size = Some(bytes);
+        self
+    }
+
+    /// Spawns the thread running `task(replica)` for `replica` of `worker`
+    /// with these settings, returning it under the worker's name.
+    ///
+    /// Panics if the thread cannot be spawned. A failure to set the affinity
+    /// panics the new thread, before it runs `task`.
+    pub fn spawn<F: FnOnce(Replica) + Send + 'static>(
+        &self,
+        worker: &str,
+        replica: Replica,
+        task: F,
+    ) -> (String, std::thread::JoinHandle<()>) {
+        let worker_name = replica.worker_name(worker);
+        let thread_name = match &self.name {
+            Some(name) => replica.worker_name(name),
+            None => worker_name.clone(),
+        };
+        let mut builder = std::thread::Builder::new().name(thread_name);
+        if let Some(stack_size) = self.stack_size {
+            builder = builder.stack_size(stack_size);
+        }
+        let affinity = self.affinity.clone();
+        let handle = builder
+            .spawn(move || {
+                if let Some(cpus) = affinity {
+                    pin_current_thread(&cpus).unwrap_or_else(|e| {
+                        panic!("failed to pin worker thread to cpus {cpus:?}: {e}")
+                    });
+                }
+                task(replica)
+            })
+            .unwrap_or_else(|e| panic!("failed to spawn worker {worker_name}: {e}"));
+        (worker_name, handle)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn pin_current_thread(cpus: &[usize]) -> std::io::Result<()> {
+    // `CPU_SET` panics on out-of-range cpus; report them as errors instead.
+    let max = 8 * std::mem::size_of::<libc::cpu_set_t>();
+    if let Some(cpu) = cpus.iter().find(|&&cpu| cpu >= max) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("cpu {cpu} out of range"),
+        ));
+    }
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for &cpu in cpus {
+            libc::CPU_SET(cpu, &mut set);
+        }
+        libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn pin_current_thread(_cpus: &[usize]) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// What a worker does with a handler that panicked, chosen per handler type
+/// through [`Handler::ON_PANIC`](traits::core::Handler::ON_PANIC).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Supervision {
+    /// Drop the handler and build a fresh one with `new(config, writer)`,
+    /// followed by its `on_start`. The panicking message is not retried.
+    /// Escalates instead once the handler exceeds its
+    /// [`RESTART_LIMIT`](traits::core::Handler::RESTART_LIMIT), or if the
+    /// rebuilt handler panics in `new` or `on_start`.
+    Restart,
+    /// Keep the handler as it is and move on to the next message.
+    Skip,
+    /// Stop the whole Messenger, as if `Messenger::stop` had been called.
+    Escalate,
+}
+
+/// How often a handler with [`Supervision::Restart`] may be restarted: a
+/// panic that would take it past `restarts` restarts `within` the window
+/// escalates instead, so a handler failing on every message cannot restart
+/// in a loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RestartLimit {
+    pub restarts: u32,
+    pub within: std::time::Duration,
+}
+
+impl RestartLimit {
+    /// 10 restarts a minute.
+    pub const DEFAULT: RestartLimit = RestartLimit {
+        restarts: 10,
+        within: std::time::Duration::from_secs(60),
+    };
+}
+
+/// The restarts of a worker's handlers within their [`RestartLimit`]
+/// windows.
+#[derive(Debug, Default)]
+pub struct Restarts {
+    by_handler: std::collections::HashMap<&'static str, std::collections::VecDeque<std::time::Instant>>,
+}
+
+impl Restarts {
+    /// Records a restart of `handler`, unless it would exceed `limit`.
+    pub fn try_restart(&mut self, handler: &'static str, limit: RestartLimit) -> bool {
+        let now = std::time::Instant::now();
+        let restarts = self.by_handler.entry(handler).or_default();
+        while restarts
+            .front()
+            .is_some_and(|restart| now.duration_since(*restart) >= limit.within)
+        {
+            restarts.pop_front();
+        }
+        if restarts.len() >= limit.restarts as usize {
+            return false;
+        }
+        restarts.push_back(now);
+        true
+    }
+}
+
+/// The message a handler was handling when it panicked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PanickedMessage {
+    pub source: u16,
+    pub message_id: u16,
+    pub position: usize,
+}
+
+/// A handler panic caught by its worker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanicEvent {
+    pub worker: &'static str,
+    pub replica: usize,
+    /// Field name of the handler in `Messenger!`.
+    pub handler: &'static str,
+    /// `None` if the panic came from `on_start`, `on_loop` or
+    /// `on_replay_complete`.
+    pub message: Option<PanickedMessage>,
+    /// The panic payload if it was a string, as for `panic!("...")`.
+    pub reason: String,
+    pub action: Supervision,
+}
+
+/// Text of a panic payload, for the usual `&str` and `String` payloads.
+pub fn panic_reason(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(reason) = payload.downcast_ref::<&str>() {
+        reason.to_string()
+    } else if let Some(reason) = payload.downcast_ref::<String>() {
+        reason.clone()
+    } else {
+        String::from("Box<dyn Any>")
+    }
+}
+
+/// Panic log shared by the Messenger and its workers.
+///
+/// Workers record every caught panic here; an escalated panic also raises the
+/// Messenger's stop flag.
+#[derive(Clone)]
+pub struct Supervisor {
+    events: std::sync::Arc<std::sync::Mutex<Vec<PanicEvent>>>,
+    escalated: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+impl Supervisor {
+    pub fn new(stop: std::sync::Arc<std::sync::atomic::AtomicBool>) -> Supervisor {
+        Supervisor {
+            events: Default::default(),
+            escalated: Default::default(),
+            stop,
+        }
+    }
+
+    pub fn report(&self, event: PanicEvent) {
+        if event.action == Supervision::Escalate {
+            self.escalated
+                .store(true, std::sync::atomic::Ordering::Relaxed);
+            self.stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        }
+        self.lock().push(event);
+    }
+
+    /// Whether a panic was escalated to stop the Messenger.
+    pub fn escalated(&self) -> bool {
+        self.escalated.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Removes and returns the events recorded so far, oldest first.
+    pub fn take_events(&self) -> Vec<PanicEvent> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<PanicEvent>> {
+        // A poisoned log still holds valid events.
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// The [`Supervision`] policy of `handler`'s type.
+pub fn policy_of<H: traits::core::Handler>(_handler: &H) -> Supervision {
+    H::ON_PANIC
+}
+
+/// The [`RestartLimit`] of `handler`'s type.
+pub fn restart_limit_of<H: traits::core::Handler>(_handler: &H) -> RestartLimit {
+    H::RESTART_LIMIT
+}
+
+/// Outcome of every worker thread, keyed by worker name; a worker that
+/// panicked outside of supervision carries its panic payload (see
+/// [`panic_reason`]).
+pub type WorkerResults = std::collections::BTreeMap<String, std::thread::Result<()>>;
+
+/// The worker threads of a running Messenger.
+///
+/// Dropping it stops the Messenger and joins every worker, so the handles
+/// must be kept alive for as long as the Messenger should run.
+#[must_use = "dropping JoinHandles stops the Messenger"]
 pub struct JoinHandles {
-    handles: Vec<std::thread::JoinHandle<()>>,
+    workers: Vec<(String, std::thread::JoinHandle<()>)>,
+    stop: Option<Box<dyn FnOnce() + Send>>,
 }
 
 impl JoinHandles {
-    pub fn new(handles: Vec<std::thread::JoinHandle<()>>) -> JoinHandles {
-        JoinHandles { handles }
+    /// `stop` is called when the handles are dropped before being joined.
+    pub fn new<F: FnOnce() + Send + 'static>(
+        workers: Vec<(String, std::thread::JoinHandle<()>)>,
+        stop: F,
+    ) -> JoinHandles {
+        JoinHandles {
+            workers,
+            stop: Some(Box::new(stop)),
+        }
     }
 
-    pub fn join(self) {
-        for handle in self.handles {
-            handle.join().unwrap();
+    /// Names of the workers, in spawn order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.workers.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Whether every worker thread has exited.
+    pub fn is_finished(&self) -> bool {
+        self.workers.iter().all(|(_, handle)| handle.is_finished())
+    }
+
+    /// Waits for every worker; one worker panicking does not stop the others
+    /// from being joined.
+    pub fn join(mut self) -> WorkerResults {
+        self.stop = None;
+        std::mem::take(&mut self.workers)
+            .into_iter()
+            .map(|(name, handle)| (name, handle.join()))
+            .collect()
+    }
+
+    /// Like [`join`](JoinHandles::join), but gives the handles back if the
+    /// workers are still running after `timeout`.
+    pub fn join_timeout(self, timeout: std::time::Duration) -> Result<WorkerResults, JoinHandles> {
+        let deadline = std::time::Instant::now() + timeout;
+        while !self.is_finished() {
+            if std::time::Instant::now() >= deadline {
+                return Err(self);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        Ok(self.join())
+    }
+}
+
+impl Drop for JoinHandles {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            stop();
+        }
+        for (_, handle) in self.workers.drain(..) {
+            let _ = handle.join();
         }
     }
 }
@@ -74,6 +1018,26 @@ impl JoinHandles {
 mod tests {
     use super::*;
 
+    #[test]
+    fn restarts_are_limited_per_handler_and_window() {
+        let mut restarts = Restarts::default();
+        let limit = RestartLimit {
+            restarts: 2,
+            within: std::time::Duration::from_secs(3600),
+        };
+        assert!(restarts.try_restart("parser", limit));
+        assert!(restarts.try_restart("parser", limit));
+        assert!(!restarts.try_restart("parser", limit));
+        assert!(restarts.try_restart("mailer", limit));
+
+        // Restarts older than the window no longer count.
+        let expired = RestartLimit {
+            within: std::time::Duration::ZERO,
+            ..limit
+        };
+        assert!(restarts.try_restart("parser", expired));
+    }
+
     #[test]
     fn test_align_to_usize() {
         assert_eq!(align_to_usize(0), 0);
@@ -90,6 +1054,8 @@ mod tests {
             source: 0,
             message_id: 0,
             size,
+            #[cfg(feature = "timestamps")]
+            timestamp: 0,
             commit_stamp: std::sync::atomic::AtomicU64::new(0),
         }
     }
@@ -106,14 +1072,276 @@ mod tests {
     }
 
     #[test]
+    #[cfg(not(feature = "timestamps"))]
     fn header_is_sixteen_bytes() {
         // u16 source + u16 message_id + u32 size + u64 commit_stamp = 16 bytes.
         assert_eq!(HEADER_SIZE, 16);
     }
 
+    #[test]
+    #[cfg(feature = "timestamps")]
+    fn timestamped_header_is_twenty_four_bytes() {
+        // The u64 timestamp sits between size and commit_stamp.
+        assert_eq!(HEADER_SIZE, 24);
+    }
+
     #[test]
     fn size_supports_payloads_past_the_old_u16_limit() {
         // 70_000 bytes would have overflowed the old u16 `size` field.
         assert_eq!(header_with_len(70_000).size, 70_000);
     }
+
+    #[test]
+    fn contexts_commit_the_position_after_their_message() {
+        let context = MessageContext::new(&header_with_len(5), 64, "Mailer", 0, false);
+        let error = context.commit().unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::Unsupported);
+
+        let committed = std::cell::Cell::new(None);
+        let commit = |position| {
+            committed.set(Some(position));
+            Ok(())
+        };
+        let context = context.with_commit(&commit);
+        context.commit().unwrap();
+        assert_eq!(committed.get(), Some(64 + ALIGNED_HEADER_SIZE + 8));
+        assert_eq!(context, context.without_commit());
+    }
+
+    #[test]
+    fn instance_writer_stamps_the_instance_id() {
+        use crate::message_bus::atomic_circular_bus::{CircularBus, Config};
+        use crate::traits::core::{RawWriter, Reader};
+        use crate::traits::extended::Sender;
+
+        struct Cfg;
+        impl Config for Cfg {
+            fn get_buffer_size(&self) -> usize {
+                16384
+            }
+        }
+
+        struct Ping;
+        impl traits::core::Message for Ping {
+            type Id = u16;
+            const ID: u16 = 3;
+        }
+        impl traits::extended::ExtendedMessage for Ping {
+            fn get_size(&self) -> usize {
+                0
+            }
+            fn write_into(&self, _buffer: &mut [u8]) {}
+        }
+
+        struct Connector;
+        impl traits::core::Handler for Connector {
+            type Id = u16;
+            const ID: u16 = 1;
+        }
+
+        let bus = CircularBus::new(&Cfg);
+        Connector::send(&Ping, &bus);
+        Connector::send(&Ping, InstanceWriter::<_, Connector, 10>::from_ref(&bus));
+        Connector::send(&Ping, &InstanceWriter::<_, Connector, 11>::new(bus.clone()));
+        // Raw writes as the instance's handler type, as timers do.
+        let instance = InstanceWriter::<_, Connector, 12>::from_ref(&bus);
+        instance.write_raw(1, 3, 0, |_| {});
+        instance.write_raw(2, 3, 0, |_| {});
+
+        let mut position = 0;
+        let mut sources = Vec::new();
+        while let Some((header, _)) = bus.read(position) {
+            assert_eq!(header.message_id, 3);
+            sources.push(header.source);
+            position += header.slot_len();
+        }
+        assert_eq!(sources, [1, 10, 11, 12, 2]);
+    }
+
+    #[test]
+    fn replicas_claim_every_balanced_message_exactly_once() {
+        const SLOT: usize = 32;
+        const MESSAGES: usize = if cfg!(miri) { 200 } else { 20_000 };
+
+        let handles: Vec<_> = Replica::group(4)
+            .into_iter()
+            .map(|replica| {
+                std::thread::spawn(move || {
+                    (0..MESSAGES)
+                        .filter(|i| replica.try_claim(i * SLOT, SLOT))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        let mut claimed: Vec<usize> = handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect();
+        claimed.sort();
+        assert_eq!(claimed, (0..MESSAGES).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn every_key_is_owned_by_exactly_one_replica() {
+        let group = Replica::group(5);
+        for key in 0..1000u64 {
+            let owners = group.iter().filter(|replica| replica.owns(&key)).count();
+            assert_eq!(owners, 1, "key {key}");
+        }
+        let single = Replica::group(1);
+        assert!(single[0].owns(&"any key"));
+    }
+
+    #[test]
+    fn shards_are_pinned() {
+        use std::hash::BuildHasher;
+
+        // FNV-1a of the little-endian bytes, and of a str's bytes and 0xff.
+        let hasher = std::hash::BuildHasherDefault::<ShardHasher>::default();
+        assert_eq!(hasher.hash_one(42u64), 0xff3a_dd6b_3789_daef);
+        assert_eq!(hasher.hash_one(1000u64), 0xad63_2382_5fa7_66dc);
+        assert_eq!(hasher.hash_one("AAPL"), 0xef52_f23f_3b4f_dc5c);
+        assert_eq!(hasher.hash_one(42usize), hasher.hash_one(42u64));
+
+        let group = Replica::group(4);
+        let shard = |key: &dyn Fn(&Replica) -> bool| group.iter().position(key).unwrap();
+        assert_eq!(shard(&|replica| replica.owns(&0u64)), 1);
+        assert_eq!(shard(&|replica| replica.owns(&1u64)), 0);
+        assert_eq!(shard(&|replica| replica.owns(&42u64)), 3);
+        assert_eq!(shard(&|replica| replica.owns(&"")), 2);
+    }
+
+    /// A bus implemented outside the crate, without positions.
+    #[derive(Clone)]
+    struct EmptyBus;
+
+    impl traits::core::Reader for EmptyBus {
+        fn read(&self, _position: usize) -> Option<(&Header, &[u8])> {
+            None
+        }
+    }
+
+    impl traits::core::RawWriter for EmptyBus {
+        fn write_raw<F: FnOnce(&mut [u8])>(&self, _source: u16, _message_id: u16, _size: usize, _callback: F) {}
+    }
+
+    impl traits::core::Writer for EmptyBus {
+        fn write<M: traits::core::Message, H: traits::core::Handler, F: FnOnce(&mut [u8])>(&self, _size: usize, _callback: F) {}
+    }
+
+    impl traits::core::MessageBus for EmptyBus {}
+
+    #[test]
+    fn start_positions_without_bus_positions() {
+        assert_eq!(StartAt::Beginning.position(&EmptyBus, "Worker", 1), 0);
+        assert_eq!(StartAt::Committed.position(&EmptyBus, "Worker", 2), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "worker Live starts at Tail, but the bus has no positions")]
+    fn tail_start_needs_bus_positions() {
+        StartAt::Tail.position(&EmptyBus, "Live", 1);
+    }
+
+    #[test]
+    fn replica_names_match_worker_names() {
+        assert!(Replica::names("Pool", "Pool", 3));
+        assert!(Replica::names("Pool.2", "Pool", 3));
+        assert!(!Replica::names("Pool.3", "Pool", 3));
+        assert!(!Replica::names("Pool.0", "Pool", 1));
+        assert!(!Replica::names("Poo", "Pool", 1));
+        for replica in Replica::group(3) {
+            assert!(Replica::names(&replica.worker_name("Pool"), "Pool", 3));
+        }
+    }
+
+    fn flagged_worker(
+        name: &str,
+        stop: &std::sync::Arc<std::sync::atomic::AtomicBool>,
+    ) -> (String, std::thread::JoinHandle<()>) {
+        let stop = stop.clone();
+        let handle = std::thread::spawn(move || {
+            while !stop.load(std::sync::atomic::Ordering::Relaxed) {
+                std::thread::yield_now();
+            }
+        });
+        (name.to_string(), handle)
+    }
+
+    #[test]
+    fn join_reports_every_worker_by_name() {
+        let workers = vec![
+            ("Ok".to_string(), std::thread::spawn(|| {})),
+            ("Failed".to_string(), std::thread::spawn(|| panic!("worker failed"))),
+            ("AlsoOk".to_string(), std::thread::spawn(|| {})),
+        ];
+        let results = JoinHandles::new(workers, || {}).join();
+
+        assert_eq!(results.len(), 3);
+        assert!(results["Ok"].is_ok());
+        assert!(results["AlsoOk"].is_ok());
+        let payload = results["Failed"].as_ref().unwrap_err();
+        assert_eq!(panic_reason(&**payload), "worker failed");
+    }
+
+    #[test]
+    fn join_timeout_gives_running_workers_back() {
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let handles = JoinHandles::new(vec![flagged_worker("Pool.0", &stop)], || {});
+        assert!(!handles.is_finished());
+
+        let handles = handles
+            .join_timeout(std::time::Duration::from_millis(10))
+            .unwrap_err();
+        assert_eq!(handles.names().collect::<Vec<_>>(), ["Pool.0"]);
+
+        stop.store(true, std::sync::atomic::Ordering::Relaxed);
+        let results = handles
+            .join_timeout(std::time::Duration::from_secs(5))
+            .ok()
+            .unwrap();
+        assert!(results["Pool.0"].is_ok());
+    }
+
+    #[test]
+    fn dropping_join_handles_stops_and_joins() {
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let flag = stop.clone();
+        let handles = JoinHandles::new(vec![flagged_worker("Worker", &stop)], move || {
+            flag.store(true, std::sync::atomic::Ordering::Relaxed)
+        });
+        drop(handles);
+        assert!(stop.load(std::sync::atomic::Ordering::Relaxed));
+    }
+
+    #[test]
+    fn thread_config_names_and_pins_worker_threads() {
+        let mut group = Replica::group(2).into_iter();
+        let _first = group.next().unwrap();
+        let config = ThreadConfig::new()
+            .name("feed")
+            .affinity([0])
+            .stack_size(256 * 1024);
+        let (name, handle) = config.spawn("Pool", group.next().unwrap(), |replica| {
+            assert_eq!(replica.index(), 1);
+            assert_eq!(std::thread::current().name(), Some("feed.1"));
+            #[cfg(target_os = "linux")]
+            unsafe {
+                let mut set: libc::cpu_set_t = std::mem::zeroed();
+                let size = std::mem::size_of::<libc::cpu_set_t>();
+                assert_eq!(libc::sched_getaffinity(0, size, &mut set), 0);
+                assert_eq!(libc::CPU_COUNT(&set), 1);
+                assert!(libc::CPU_ISSET(0, &set));
+            }
+        });
+        assert_eq!(name, "Pool.1");
+        handle.join().unwrap();
+
+        let (name, handle) = ThreadConfig::new().spawn("Front", Replica::group(1).pop().unwrap(), |_| {
+            assert_eq!(std::thread::current().name(), Some("Front"));
+        });
+        assert_eq!(name, "Front");
+        handle.join().unwrap();
+    }
 }
@@ -45,6 +45,11 @@ impl AnonymousMmap {
     }
 }
 
+/// The size of a memory page on this system.
+pub(crate) fn page_size() -> usize {
+    platform::page_size()
+}
+
 impl Drop for AnonymousMmap {
     fn drop(&mut self) {
         unsafe { platform::unmap(self.ptr, self.len) }
@@ -254,7 +259,7 @@ mod tests {
                         break;
                     }
                     // loop until val % step == 0
-                    if (val as usize + step - start) % step == 0 {
+                    if (val as usize + step - start).is_multiple_of(step) {
                         mb.write((val + 1).to_ne_bytes().as_ref());
                     }
                 }
@@ -105,6 +105,7 @@ impl ExtendingMmap {
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(path)?;
 
         let file_len = file.metadata()?.len();
@@ -260,8 +261,8 @@ fn system_page_size() -> usize {
 }
 
 /// Rounds `min_len` up to the next power of two that is at least the system
-/// page size.
-fn align_page_size(min_len: usize) -> Result<usize, ExtendingMmapError> {
+/// page size: the page size of a mapping created with `min_len`.
+pub fn align_page_size(min_len: usize) -> Result<usize, ExtendingMmapError> {
     debug_assert!(min_len > 0);
     let target = (min_len - 1) | (system_page_size() - 1);
     if target.leading_zeros() == 0 {
@@ -1 +1,2 @@
 pub mod extending_mmap;
+pub mod shared_mmap;
@@ -0,0 +1,122 @@
+use std::os::fd::{AsFd, AsRawFd, FromRawFd};
+
+/// A memory mapping of a `memfd`, shared by every process holding the file
+/// descriptor.
+///
+/// [`create`](SharedMmap::create) makes a new zero-filled memfd of `len`
+/// bytes; [`from_fd`](SharedMmap::from_fd) maps one received from another
+/// process, e.g. over a Unix socket with `SCM_RIGHTS`. Both map the whole
+/// file `MAP_SHARED`, so writes are visible to every mapping of it.
+///
+/// Deliberately not `Clone`: the struct owns the mapping and unmaps it on
+/// drop. Share it through an `Arc` instead.
+pub struct SharedMmap {
+    fd: std::os::fd::OwnedFd,
+    ptr: *mut u8,
+    len: usize,
+}
+
+// SAFETY: SharedMmap is the unique owner of its mapping; the raw pointer is
+// only an address, and synchronization of the memory behind it (also with
+// other processes) is the responsibility of the atomic-based users of
+// `as_ptr`.
+unsafe impl Send for SharedMmap {}
+unsafe impl Sync for SharedMmap {}
+
+impl SharedMmap {
+    /// Creates a memfd named `name` (shown in `/proc/<pid>/fd`) of `len`
+    /// bytes and maps it.
+    pub fn create(name: &str, len: usize) -> std::io::Result<SharedMmap> {
+        if len == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "len must be non-zero",
+            ));
+        }
+        let name = std::ffi::CString::new(name)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+        let fd = unsafe { libc::memfd_create(name.as_ptr(), libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        let fd = unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) };
+        if unsafe { libc::ftruncate(fd.as_raw_fd(), len as libc::off_t) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        SharedMmap::map(fd, len)
+    }
+
+    /// Maps the whole file behind `fd`.
+    pub fn from_fd(fd: std::os::fd::OwnedFd) -> std::io::Result<SharedMmap> {
+        let len = std::fs::File::from(fd.try_clone()?).metadata()?.len();
+        if len == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the shared memory file is empty",
+            ));
+        }
+        let len = usize::try_from(len)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        SharedMmap::map(fd, len)
+    }
+
+    fn map(fd: std::os::fd::OwnedFd, len: usize) -> std::io::Result<SharedMmap> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(SharedMmap {
+            fd,
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The memfd, to hand to another process.
+    pub fn fd(&self) -> std::os::fd::BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Drop for SharedMmap {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mappings_of_one_fd_share_memory() {
+        let first = SharedMmap::create("shared-mmap-test", 4096).unwrap();
+        let second = SharedMmap::from_fd(first.fd().try_clone_to_owned().unwrap()).unwrap();
+        assert_eq!(second.len(), 4096);
+        assert_ne!(first.as_ptr(), second.as_ptr());
+
+        unsafe { first.as_ptr().add(100).write(42) };
+        assert_eq!(unsafe { second.as_ptr().add(100).read() }, 42);
+    }
+
+    static_assertions::assert_not_impl_any!(SharedMmap: Clone);
+}
@@ -2,3 +2,6 @@ pub mod anonymous_mmap;
 
 #[cfg(target_os = "linux")]
 pub mod linux;
+
+#[cfg(unix)]
+pub mod read_only_mmap;
@@ -0,0 +1,91 @@
+use std::os::fd::AsRawFd;
+
+/// A read-only, private mapping of a whole file, as long as the file was
+/// when mapped.
+///
+/// Pages are read from the page cache on first access, so mapping a large
+/// file costs no memory up front. Writes by other processes to the mapped
+/// range stay visible. The file must not shrink while mapped: touching a
+/// page beyond its end raises `SIGBUS`.
+///
+/// Deliberately not `Clone`: the struct owns the mapping and unmaps it on
+/// drop.
+pub struct ReadOnlyMmap {
+    ptr: *const u8,
+    len: usize,
+}
+
+// SAFETY: ReadOnlyMmap is the unique owner of its mapping, and the mapping
+// is never written through.
+unsafe impl Send for ReadOnlyMmap {}
+unsafe impl Sync for ReadOnlyMmap {}
+
+impl ReadOnlyMmap {
+    /// Maps `file`, which must be open for reading. An empty file maps to an
+    /// empty slice.
+    pub fn new(file: &std::fs::File) -> std::io::Result<ReadOnlyMmap> {
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        if len == 0 {
+            // mmap rejects empty mappings.
+            return Ok(ReadOnlyMmap {
+                ptr: std::ptr::NonNull::dangling().as_ptr(),
+                len,
+            });
+        }
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(ReadOnlyMmap {
+            ptr: ptr as *const u8,
+            len,
+        })
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl Drop for ReadOnlyMmap {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            unsafe {
+                libc::munmap(self.ptr as *mut libc::c_void, self.len);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static_assertions::assert_not_impl_any!(ReadOnlyMmap: Clone);
+
+    #[test]
+    fn maps_the_file_as_it_is_and_empty_files_as_empty() {
+        let path = std::env::temp_dir().join(format!(
+            "rust-messenger-read-only-mmap-{}",
+            std::process::id()
+        ));
+        std::fs::write(&path, b"").unwrap();
+        let map = ReadOnlyMmap::new(&std::fs::File::open(&path).unwrap()).unwrap();
+        assert!(map.as_slice().is_empty());
+
+        std::fs::write(&path, b"recorded bytes").unwrap();
+        let map = ReadOnlyMmap::new(&std::fs::File::open(&path).unwrap()).unwrap();
+        assert_eq!(map.as_slice(), b"recorded bytes");
+        std::fs::remove_file(path).unwrap();
+    }
+}
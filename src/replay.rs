@@ -0,0 +1,262 @@
+use crate::bridge;
+use crate::inspect;
+use crate::traits;
+
+/// How fast [`Replay::run`] publishes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    /// As fast as the target bus takes the messages.
+    Fastest,
+    /// With the gaps between the messages as recorded.
+    #[cfg(feature = "timestamps")]
+    Original,
+    /// With the recorded gaps divided by the factor: `Speed(10.0)` replays
+    /// ten times faster than recorded, `Speed(0.5)` at half speed.
+    #[cfg(feature = "timestamps")]
+    Speed(f64),
+}
+
+/// Re-publishes a recording, an `ExtendingBus` file or a flight recorder
+/// dump, into a live bus such as a `CircularBus` under test, to reproduce
+/// what a production process saw.
+///
+/// Every message keeps its recorded source and message id, so the target
+/// routes it as the original bus did:
+///
+/// ```ignore
+/// let published = replay::Replay::open("incident.bus")?
+///     .filter(bridge::Filter::new().route(HandlerId::Gateway, MessageId::Order))
+///     .positions(1 << 20..)
+///     .pace(replay::Pace::Speed(10.0))
+///     .run(&bus);
+/// ```
+///
+/// Pacing by the recorded time, and selecting by it, needs the timestamps
+/// in the headers: both the recording process and the replaying one must be
+/// built with the `timestamps` feature.
+pub struct Replay {
+    file: inspect::BusFile,
+    filter: bridge::Filter,
+    positions: (std::ops::Bound<usize>, std::ops::Bound<usize>),
+    #[cfg(feature = "timestamps")]
+    times: (std::ops::Bound<u64>, std::ops::Bound<u64>),
+    pace: Pace,
+}
+
+impl Replay {
+    /// Replays the recording at `path`.
+    pub fn open(path: impl AsRef<std::path::Path>) -> std::io::Result<Replay> {
+        Ok(Replay::new(inspect::BusFile::open(path)?))
+    }
+
+    /// Replays every message of `file`, as fast as possible.
+    pub fn new(file: inspect::BusFile) -> Replay {
+        Replay {
+            file,
+            filter: bridge::Filter::all(),
+            positions: (std::ops::Bound::Unbounded, std::ops::Bound::Unbounded),
+            #[cfg(feature = "timestamps")]
+            times: (std::ops::Bound::Unbounded, std::ops::Bound::Unbounded),
+            pace: Pace::Fastest,
+        }
+    }
+
+    /// Replays only the messages `filter` selects.
+    pub fn filter(mut self, filter: bridge::Filter) -> Replay {
+        self.filter = filter;
+        self
+    }
+
+    /// Replays only the messages recorded at these bus positions.
+    pub fn positions(mut self, range: impl std::ops::RangeBounds<usize>) -> Replay {
+        self.positions = (range.start_bound().cloned(), range.end_bound().cloned());
+        self
+    }
+
+    /// Replays only the messages written within this time range.
+    #[cfg(feature = "timestamps")]
+    pub fn times(mut self, range: impl std::ops::RangeBounds<std::time::SystemTime>) -> Replay {
+        let nanos = |time: &std::time::SystemTime| {
+            time.duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_nanos() as u64)
+        };
+        self.times = (range.start_bound().map(nanos), range.end_bound().map(nanos));
+        self
+    }
+
+    pub fn pace(mut self, pace: Pace) -> Replay {
+        #[cfg(feature = "timestamps")]
+        if let Pace::Speed(factor) = pace {
+            assert!(
+                factor.is_finite() && factor > 0.0,
+                "the replay speed must be positive, got {factor}"
+            );
+        }
+        self.pace = pace;
+        self
+    }
+
+    /// Publishes the selected messages onto `target`, in recorded order,
+    /// and returns how many it published. Blocks until done; tombstones and
+    /// holes in the recording are skipped.
+    pub fn run<W: traits::core::RawWriter>(&self, target: &W) -> usize {
+        use std::ops::RangeBounds;
+
+        #[cfg(feature = "timestamps")]
+        let mut pacer = Pacer::new(self.pace);
+        let mut published = 0;
+        for entry in self.file.entries() {
+            let inspect::Entry::Slot(slot) = entry else {
+                continue;
+            };
+            if !self.positions.contains(&slot.position) {
+                if matches!(self.positions.1, std::ops::Bound::Excluded(end) | std::ops::Bound::Included(end) if slot.position > end)
+                {
+                    break;
+                }
+                continue;
+            }
+            if !self.filter.allows(slot.source, slot.message_id) {
+                continue;
+            }
+            #[cfg(feature = "timestamps")]
+            {
+                if !self.times.contains(&slot.timestamp) {
+                    continue;
+                }
+                pacer.wait_for(slot.timestamp);
+            }
+            let payload = slot.payload;
+            target.write_raw(slot.source, slot.message_id, payload.len(), |buffer| {
+                buffer[..payload.len()].copy_from_slice(payload)
+            });
+            published += 1;
+        }
+        published
+    }
+}
+
+/// Maps recorded timestamps onto the wall clock of the replay, anchored at
+/// the first message replayed.
+#[cfg(feature = "timestamps")]
+struct Pacer {
+    speed: Option<f64>,
+    anchor: Option<(u64, std::time::Instant)>,
+}
+
+#[cfg(feature = "timestamps")]
+impl Pacer {
+    fn new(pace: Pace) -> Pacer {
+        let speed = match pace {
+            Pace::Fastest => None,
+            Pace::Original => Some(1.0),
+            Pace::Speed(factor) => Some(factor),
+        };
+        Pacer {
+            speed,
+            anchor: None,
+        }
+    }
+
+    /// Sleeps until the message recorded at `timestamp` is due.
+    fn wait_for(&mut self, timestamp: u64) {
+        let Some(speed) = self.speed else {
+            return;
+        };
+        let (first, started) = *self
+            .anchor
+            .get_or_insert_with(|| (timestamp, std::time::Instant::now()));
+        let gap = timestamp.saturating_sub(first) as f64 / speed;
+        let due = started + std::time::Duration::from_nanos(gap as u64);
+        let now = std::time::Instant::now();
+        if due > now {
+            std::thread::sleep(due - now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cursor::Cursor;
+    use crate::flight_recorder::FlightRecorder;
+    use crate::message_bus::atomic_circular_bus::{CircularBus, Config};
+    use crate::traits::core::RawWriter;
+
+    struct Cfg;
+
+    impl Config for Cfg {
+        fn get_buffer_size(&self) -> usize {
+            16384
+        }
+    }
+
+    fn publish(bus: &CircularBus, source: u16, value: u8) {
+        bus.write_raw(source, 5, 1, |buffer| buffer[0] = value);
+    }
+
+    /// Records what `record` publishes into a file, and opens it for replay.
+    fn recording(name: &str, record: impl FnOnce(&CircularBus)) -> Replay {
+        let bus = CircularBus::new(&Cfg);
+        record(&bus);
+        let path = std::env::temp_dir().join(format!(
+            "rust-messenger-replay-{name}-{}.bus",
+            std::process::id()
+        ));
+        FlightRecorder::new(bus, &path).dump().unwrap();
+        let replay = Replay::open(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        replay
+    }
+
+    fn replayed(replay: &Replay) -> Vec<(u16, u8)> {
+        let target = CircularBus::new(&Cfg);
+        let published = replay.run(&target);
+        let mut cursor = Cursor::new();
+        let messages: Vec<_> = std::iter::from_fn(|| {
+            let (header, payload) = cursor.next(&target).unwrap()?;
+            assert_eq!(header.message_id, 5);
+            Some((header.source, payload[0]))
+        })
+        .collect();
+        assert_eq!(messages.len(), published);
+        messages
+    }
+
+    #[test]
+    fn replays_selected_positions_and_routes() {
+        let replay = recording("select", |bus| {
+            for value in 0..6 {
+                publish(bus, 1 + value as u16 % 2, value);
+            }
+        });
+        assert_eq!(replayed(&replay).len(), 6);
+
+        let slot = crate::messenger::ALIGNED_HEADER_SIZE + crate::messenger::align_to_usize(1);
+        let replay = replay.positions(slot..4 * slot);
+        assert_eq!(replayed(&replay), [(2, 1), (1, 2), (2, 3)]);
+        let replay = replay.filter(bridge::Filter::new().route(2u16, 5u16));
+        assert_eq!(replayed(&replay), [(2, 1), (2, 3)]);
+    }
+
+    #[cfg(feature = "timestamps")]
+    #[test]
+    fn paced_replay_keeps_the_recorded_gaps() {
+        let gap = std::time::Duration::from_millis(40);
+        let replay = recording("pace", |bus| {
+            for value in 0..3 {
+                publish(bus, 1, value);
+                std::thread::sleep(gap);
+            }
+        });
+
+        let started = std::time::Instant::now();
+        assert_eq!(replayed(&replay).len(), 3);
+        assert!(started.elapsed() < gap);
+
+        let replay = replay.pace(Pace::Speed(2.0));
+        let started = std::time::Instant::now();
+        assert_eq!(replayed(&replay).len(), 3);
+        assert!(started.elapsed() >= gap);
+    }
+}
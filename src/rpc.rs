@@ -0,0 +1,585 @@
+use crate::clock;
+use crate::traits;
+
+/// Bytes in front of the wrapped message in a [`Call`] or [`Reply`]: the
+/// correlation id.
+const ENVELOPE_SIZE: usize = std::mem::size_of::<u64>();
+
+/// A message sent in [`Call`]s, with the message id of its calls.
+///
+/// The id must differ from the message's own [`ID`](traits::core::Message::ID),
+/// so routes tell calls from bare messages of the same source.
+pub trait CallMessage: traits::core::Message {
+    const CALL_ID: Self::Id;
+}
+
+/// A message sent in [`Reply`]s, with the message id of its replies and of
+/// the [`Timeout`]s of calls expecting it.
+///
+/// The ids must differ from each other and from the message's own
+/// [`ID`](traits::core::Message::ID), so routes tell replies and timeouts
+/// from bare messages of the same source.
+pub trait ReplyMessage: traits::core::Message {
+    const REPLY_ID: Self::Id;
+    const TIMEOUT_ID: Self::Id;
+}
+
+/// A request sent with [`Client::request`], wrapping the message `M` with a
+/// correlation id. Route it like any other message, e.g.
+/// `handlers::Gateway, rpc::Call<messages::Quote>: [ pricer ]`, and answer it
+/// with [`reply`]. Sent under [`CallMessage::CALL_ID`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call<M> {
+    pub id: u64,
+    pub message: M,
+}
+
+/// The answer to a [`Call`], routed back to the caller's handler, which
+/// hands it to its [`Client`] (see [`Caller`]). Sent under
+/// [`ReplyMessage::REPLY_ID`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reply<M> {
+    pub id: u64,
+    pub message: M,
+}
+
+macro_rules! impl_envelope {
+    ($envelope:ident, $kind:ident, $id:ident) => {
+        impl<M: $kind> traits::core::Message for $envelope<M> {
+            type Id = M::Id;
+            const ID: M::Id = M::$id;
+        }
+
+        impl<M: traits::extended::DeserializeFrom> $envelope<M> {
+            /// Decodes an envelope; `None` if `buffer` is too short to hold
+            /// the correlation id. The wrapped `M` decodes the rest.
+            pub fn try_deserialize_from(buffer: &[u8]) -> Option<$envelope<M>> {
+                if buffer.len() < ENVELOPE_SIZE {
+                    return None;
+                }
+                let (id, message) = buffer.split_at(ENVELOPE_SIZE);
+                Some($envelope {
+                    id: u64::from_ne_bytes(id.try_into().unwrap()),
+                    message: M::deserialize_from(message),
+                })
+            }
+
+            /// Decodes an envelope, as routes do. Panics if `buffer` is too
+            /// short, see [`try_deserialize_from`](Self::try_deserialize_from).
+            pub fn deserialize_from(buffer: &[u8]) -> $envelope<M> {
+                Self::try_deserialize_from(buffer).unwrap_or_else(|| {
+                    panic!("{} of {} bytes is too short to decode", stringify!($envelope), buffer.len())
+                })
+            }
+        }
+
+        impl<M: $kind + traits::extended::ExtendedMessage> traits::extended::ExtendedMessage for $envelope<M> {
+            fn get_size(&self) -> usize {
+                ENVELOPE_SIZE + self.message.get_size()
+            }
+
+            fn write_into(&self, buffer: &mut [u8]) {
+                write_envelope(self.id, &self.message, buffer);
+            }
+        }
+    };
+}
+
+impl_envelope!(Call, CallMessage, CALL_ID);
+impl_envelope!(Reply, ReplyMessage, REPLY_ID);
+
+/// Announces that the call `id` expecting an `M` timed out: written by
+/// [`Client::expire`] as the calling handler, under
+/// [`ReplyMessage::TIMEOUT_ID`], so the callee and the bus history see the
+/// calls that were given up on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeout<M> {
+    pub id: u64,
+    reply: std::marker::PhantomData<fn() -> M>,
+}
+
+impl<M> Timeout<M> {
+    pub fn new(id: u64) -> Timeout<M> {
+        Timeout {
+            id,
+            reply: std::marker::PhantomData,
+        }
+    }
+
+    /// Decodes a timeout; `None` if `buffer` is too short to hold the
+    /// correlation id.
+    pub fn try_deserialize_from(buffer: &[u8]) -> Option<Timeout<M>> {
+        let id = buffer.get(..ENVELOPE_SIZE)?;
+        Some(Timeout::new(u64::from_ne_bytes(id.try_into().unwrap())))
+    }
+
+    /// Decodes a timeout, as routes do. Panics if `buffer` is too short, see
+    /// [`try_deserialize_from`](Self::try_deserialize_from).
+    pub fn deserialize_from(buffer: &[u8]) -> Timeout<M> {
+        Self::try_deserialize_from(buffer)
+            .unwrap_or_else(|| panic!("Timeout of {} bytes is too short to decode", buffer.len()))
+    }
+}
+
+impl<M: ReplyMessage> traits::core::Message for Timeout<M> {
+    type Id = M::Id;
+    const ID: M::Id = M::TIMEOUT_ID;
+}
+
+impl<M: ReplyMessage> traits::extended::ExtendedMessage for Timeout<M> {
+    fn get_size(&self) -> usize {
+        ENVELOPE_SIZE
+    }
+
+    fn write_into(&self, buffer: &mut [u8]) {
+        buffer[..ENVELOPE_SIZE].copy_from_slice(&self.id.to_ne_bytes());
+    }
+}
+
+fn write_envelope<M: traits::extended::ExtendedMessage>(id: u64, message: &M, buffer: &mut [u8]) {
+    let (envelope, payload) = buffer.split_at_mut(ENVELOPE_SIZE);
+    envelope.copy_from_slice(&id.to_ne_bytes());
+    message.write_into(payload);
+}
+
+/// Sends `envelope` wrapping `message` as `H` without cloning the message.
+fn send_enveloped<E, H, M, W>(id: u64, message: &M, writer: &W)
+where
+    E: traits::core::Message,
+    H: traits::core::Handler,
+    M: traits::extended::ExtendedMessage,
+    W: traits::core::Writer,
+{
+    let size = ENVELOPE_SIZE + message.get_size();
+    writer.write::<E, H, _>(size, |buffer| write_envelope(id, message, &mut buffer[..size]));
+}
+
+/// Answers `call` with `response`, sent as `H`.
+pub fn reply<H, Req, Resp, W>(call: &Call<Req>, response: &Resp, writer: &W)
+where
+    H: traits::core::Handler,
+    Resp: ReplyMessage + traits::extended::ExtendedMessage,
+    W: traits::core::Writer,
+{
+    send_enveloped::<Reply<Resp>, H, Resp, W>(call.id, response, writer);
+}
+
+/// Why a call did not get its reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcError {
+    /// No reply within the client's timeout.
+    Timeout,
+    /// The [`Client`] was dropped before the reply arrived.
+    Cancelled,
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RpcError::Timeout => f.write_str("no reply before the call timed out"),
+            RpcError::Cancelled => f.write_str("the client was dropped before the reply arrived"),
+        }
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+// Correlation ids are unique per process, so clients sharing a reply route
+// ignore each other's replies.
+static NEXT_CALL_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(1);
+
+type Callback<Resp> = Box<dyn FnOnce(Result<Resp, RpcError>) + Send>;
+
+/// Outstanding calls expecting a `Resp`, owned by the calling handler.
+///
+/// [`request`](Client::request) sends a [`Call`] and returns a future of the
+/// reply, [`request_with`](Client::request_with) takes a callback instead.
+/// The handler implements [`Caller`] and routes [`Reply<Resp>`] to itself,
+/// which hands every reply to the client, and calls
+/// [`expire`](Client::expire) from `on_loop` to fail calls that timed out:
+///
+/// ```ignore
+/// impl rpc::Caller<messages::Price> for Gateway {
+///     fn client(&self) -> &rpc::Client<messages::Price> {
+///         &self.prices
+///     }
+/// }
+/// ```
+///
+/// Clones share the outstanding calls, so async tasks of the handler can
+/// issue requests too. Dropping the last clone fails the outstanding calls
+/// with [`RpcError::Cancelled`].
+pub struct Client<Resp> {
+    inner: std::sync::Arc<Inner<Resp>>,
+}
+
+impl<Resp> Clone for Client<Resp> {
+    fn clone(&self) -> Client<Resp> {
+        Client {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+struct Inner<Resp> {
+    timeout: std::time::Duration,
+    clock: std::sync::Arc<dyn clock::Clock>,
+    pending: std::sync::Mutex<std::collections::HashMap<u64, Pending<Resp>>>,
+}
+
+struct Pending<Resp> {
+    deadline: std::time::Instant,
+    waiter: Waiter<Resp>,
+}
+
+enum Waiter<Resp> {
+    Future(std::sync::Arc<Slot<Resp>>),
+    Callback(Callback<Resp>),
+}
+
+impl<Resp> Waiter<Resp> {
+    fn resolve(self, result: Result<Resp, RpcError>) {
+        match self {
+            Waiter::Future(slot) => slot.fill(result),
+            Waiter::Callback(callback) => callback(result),
+        }
+    }
+}
+
+impl<Resp> Client<Resp> {
+    /// A client failing calls not answered within `timeout` of the system
+    /// clock.
+    pub fn new(timeout: std::time::Duration) -> Client<Resp> {
+        Client::with_clock(timeout, std::sync::Arc::new(clock::SystemClock))
+    }
+
+    /// Like [`new`](Client::new), measuring timeouts on `clock`, e.g. a
+    /// [`VirtualClock`](clock::VirtualClock) under simulation.
+    pub fn with_clock(timeout: std::time::Duration, clock: std::sync::Arc<dyn clock::Clock>) -> Client<Resp> {
+        Client {
+            inner: std::sync::Arc::new(Inner {
+                timeout,
+                clock,
+                pending: Default::default(),
+            }),
+        }
+    }
+
+    /// Sends `request` as `H` and returns a future of the reply.
+    pub fn request<H, Req, W>(&self, request: &Req, writer: &W) -> ReplyFuture<Resp>
+    where
+        H: traits::core::Handler,
+        Req: CallMessage + traits::extended::ExtendedMessage,
+        W: traits::core::Writer,
+    {
+        let slot = std::sync::Arc::new(Slot::default());
+        self.call::<H, Req, W>(request, writer, Waiter::Future(slot.clone()));
+        ReplyFuture { slot }
+    }
+
+    /// Sends `request` as `H`; `callback` runs with the reply on the thread
+    /// that passes it to [`complete`](Client::complete), or with the error on
+    /// the thread that expires or drops the client.
+    pub fn request_with<H, Req, W, F>(&self, request: &Req, writer: &W, callback: F)
+    where
+        H: traits::core::Handler,
+        Req: CallMessage + traits::extended::ExtendedMessage,
+        W: traits::core::Writer,
+        F: FnOnce(Result<Resp, RpcError>) + Send + 'static,
+    {
+        self.call::<H, Req, W>(request, writer, Waiter::Callback(Box::new(callback)));
+    }
+
+    fn call<H, Req, W>(&self, request: &Req, writer: &W, waiter: Waiter<Resp>)
+    where
+        H: traits::core::Handler,
+        Req: CallMessage + traits::extended::ExtendedMessage,
+        W: traits::core::Writer,
+    {
+        let id = NEXT_CALL_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let deadline = self.inner.clock.now() + self.inner.timeout;
+        // Registered before sending, so a reply racing the send finds it.
+        self.inner.lock().insert(id, Pending { deadline, waiter });
+        send_enveloped::<Call<Req>, H, Req, W>(id, request, writer);
+    }
+
+    /// Resolves the call `reply` answers. Returns `false` for replies to
+    /// calls of other clients, or that already timed out.
+    pub fn complete(&self, reply: Reply<Resp>) -> bool {
+        let pending = self.inner.lock().remove(&reply.id);
+        match pending {
+            Some(pending) => {
+                pending.waiter.resolve(Ok(reply.message));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Fails every call past its deadline with [`RpcError::Timeout`] and
+    /// writes a [`Timeout`] for each as `H`; returns how many.
+    pub fn expire<H, W>(&self, writer: &W) -> usize
+    where
+        H: traits::core::Handler,
+        Resp: ReplyMessage,
+        W: traits::core::Writer,
+    {
+        let now = self.inner.clock.now();
+        let expired: Vec<(u64, Pending<Resp>)> = {
+            let mut pending = self.inner.lock();
+            let ids: Vec<u64> = pending
+                .iter()
+                .filter(|(_, call)| call.deadline <= now)
+                .map(|(&id, _)| id)
+                .collect();
+            ids.into_iter().filter_map(|id| Some((id, pending.remove(&id)?))).collect()
+        };
+        // Outside the lock: callbacks may issue new requests.
+        let count = expired.len();
+        for (id, call) in expired {
+            <H as traits::extended::Sender>::send(&Timeout::<Resp>::new(id), writer);
+            call.waiter.resolve(Err(RpcError::Timeout));
+        }
+        count
+    }
+
+    /// Number of calls waiting for a reply.
+    pub fn outstanding(&self) -> usize {
+        self.inner.lock().len()
+    }
+}
+
+/// A handler owning the [`Client`] of its calls expecting a `Resp`: the
+/// [`Reply<Resp>`]s routed to it are handed to the client, resolving the
+/// call each answers, without a `Handle` impl of its own.
+pub trait Caller<Resp> {
+    fn client(&self) -> &Client<Resp>;
+}
+
+impl<Resp: ReplyMessage + Clone, H: Caller<Resp>> traits::core::Handle<Reply<Resp>> for H {
+    /// Replies to calls of other clients, or that already timed out, are
+    /// dropped.
+    fn handle<W: traits::core::Writer>(&mut self, reply: &Reply<Resp>, _writer: &W) {
+        self.client().complete(reply.clone());
+    }
+}
+
+impl<Resp> Inner<Resp> {
+    fn lock(&self) -> std::sync::MutexGuard<'_, std::collections::HashMap<u64, Pending<Resp>>> {
+        self.pending.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl<Resp> Drop for Inner<Resp> {
+    fn drop(&mut self) {
+        let pending = std::mem::take(&mut *self.lock());
+        for (_, call) in pending {
+            call.waiter.resolve(Err(RpcError::Cancelled));
+        }
+    }
+}
+
+struct Slot<Resp> {
+    state: std::sync::Mutex<SlotState<Resp>>,
+}
+
+struct SlotState<Resp> {
+    result: Option<Result<Resp, RpcError>>,
+    waker: Option<std::task::Waker>,
+}
+
+impl<Resp> Default for Slot<Resp> {
+    fn default() -> Slot<Resp> {
+        Slot {
+            state: std::sync::Mutex::new(SlotState {
+                result: None,
+                waker: None,
+            }),
+        }
+    }
+}
+
+impl<Resp> Slot<Resp> {
+    fn fill(&self, result: Result<Resp, RpcError>) {
+        let waker = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            state.result = Some(result);
+            state.waker.take()
+        };
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+/// The reply to a [`Client::request`], resolved by [`Client::complete`] or
+/// failed by [`Client::expire`]. Works on any executor: it only needs the
+/// waker it is polled with.
+pub struct ReplyFuture<Resp> {
+    slot: std::sync::Arc<Slot<Resp>>,
+}
+
+impl<Resp> ReplyFuture<Resp> {
+    /// The result if it has arrived, without waiting; taken only once.
+    pub fn try_take(&self) -> Option<Result<Resp, RpcError>> {
+        self.slot
+            .state
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .result
+            .take()
+    }
+}
+
+impl<Resp> std::future::Future for ReplyFuture<Resp> {
+    type Output = Result<Resp, RpcError>;
+
+    fn poll(self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Self::Output> {
+        let mut state = self.slot.state.lock().unwrap_or_else(|e| e.into_inner());
+        match state.result.take() {
+            Some(result) => std::task::Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                std::task::Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::RecordingWriter;
+
+    struct Caller;
+    impl traits::core::Handler for Caller {
+        type Id = u16;
+        const ID: u16 = 1;
+    }
+    struct Callee;
+    impl traits::core::Handler for Callee {
+        type Id = u16;
+        const ID: u16 = 2;
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Num(u32);
+    impl traits::core::Message for Num {
+        type Id = u16;
+        const ID: u16 = 9;
+    }
+    impl CallMessage for Num {
+        const CALL_ID: u16 = 10;
+    }
+    impl ReplyMessage for Num {
+        const REPLY_ID: u16 = 11;
+        const TIMEOUT_ID: u16 = 12;
+    }
+    impl traits::extended::ExtendedMessage for Num {
+        fn get_size(&self) -> usize {
+            4
+        }
+        fn write_into(&self, buffer: &mut [u8]) {
+            buffer[..4].copy_from_slice(&self.0.to_ne_bytes());
+        }
+    }
+    impl traits::extended::DeserializeFrom for Num {
+        fn deserialize_from(buffer: &[u8]) -> Num {
+            Num(u32::from_ne_bytes(buffer[..4].try_into().unwrap()))
+        }
+    }
+
+    fn poll<F: std::future::Future + Unpin>(future: &mut F) -> std::task::Poll<F::Output> {
+        let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+        std::pin::Pin::new(future).poll(&mut cx)
+    }
+
+    /// Plays the callee: answers the `n`th recorded call with `call + 1`.
+    fn answer(writer: &RecordingWriter, n: usize) -> Reply<Num> {
+        let call = writer.decode(n, Call::<Num>::deserialize_from);
+        let replies = RecordingWriter::new();
+        reply::<Callee, _, _, _>(&call, &Num(call.message.0 + 1), &replies);
+        assert_eq!((replies.record(0).source, replies.record(0).message_id), (2, 11));
+        replies.decode(0, Reply::<Num>::deserialize_from)
+    }
+
+    #[test]
+    fn replies_resolve_their_own_calls() {
+        let writer = RecordingWriter::new();
+        let client = Client::<Num>::new(std::time::Duration::from_secs(60));
+        let mut first = client.request::<Caller, _, _>(&Num(10), &writer);
+        let mut second = client.request::<Caller, _, _>(&Num(20), &writer);
+        assert_eq!((writer.record(0).source, writer.record(0).message_id), (1, 10));
+        assert!(poll(&mut first).is_pending());
+
+        assert!(client.complete(answer(&writer, 1)));
+        assert!(poll(&mut first).is_pending());
+        assert_eq!(poll(&mut second), std::task::Poll::Ready(Ok(Num(21))));
+
+        let reply = answer(&writer, 0);
+        assert!(!Client::<Num>::new(std::time::Duration::from_secs(1)).complete(reply.clone()), "another client's call");
+        assert!(client.complete(reply.clone()));
+        assert!(!client.complete(reply), "completed once");
+        assert_eq!(first.try_take(), Some(Ok(Num(11))));
+        assert_eq!(client.outstanding(), 0);
+    }
+
+    #[test]
+    fn short_envelopes_are_rejected() {
+        assert_eq!(Call::<Num>::try_deserialize_from(&[0; 7]), None);
+        assert_eq!(Reply::<Num>::try_deserialize_from(&[]), None);
+        assert_eq!(Timeout::<Num>::try_deserialize_from(&[0; 7]), None);
+        let mut buffer = [0; 12];
+        buffer[..8].copy_from_slice(&7u64.to_ne_bytes());
+        buffer[8..].copy_from_slice(&3u32.to_ne_bytes());
+        assert_eq!(
+            Reply::<Num>::try_deserialize_from(&buffer),
+            Some(Reply { id: 7, message: Num(3) })
+        );
+    }
+
+    #[test]
+    fn calls_time_out_on_the_client_clock() {
+        let writer = RecordingWriter::new();
+        let clock = clock::VirtualClock::new();
+        let client = Client::<Num>::with_clock(std::time::Duration::from_millis(100), std::sync::Arc::new(clock.clone()));
+        let results = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink = results.clone();
+        client.request_with::<Caller, _, _, _>(&Num(1), &writer, move |result| sink.lock().unwrap().push(result));
+        let mut late = client.request::<Caller, _, _>(&Num(2), &writer);
+
+        clock.advance(std::time::Duration::from_millis(99));
+        assert_eq!(client.expire::<Caller, _>(&writer), 0);
+        clock.advance(std::time::Duration::from_millis(1));
+        assert_eq!(client.expire::<Caller, _>(&writer), 2);
+
+        // Both timeouts are on the bus, as the caller.
+        let mut timeouts: Vec<u64> = (2..4)
+            .map(|n| {
+                assert_eq!((writer.record(n).source, writer.record(n).message_id), (1, 12));
+                writer.decode(n, Timeout::<Num>::deserialize_from).id
+            })
+            .collect();
+        timeouts.sort();
+        let mut calls: Vec<u64> = (0..2).map(|n| writer.decode(n, Call::<Num>::deserialize_from).id).collect();
+        calls.sort();
+        assert_eq!(timeouts, calls);
+        assert_eq!(client.expire::<Caller, _>(&writer), 0, "announced once");
+
+        assert_eq!(*results.lock().unwrap(), [Err(RpcError::Timeout)]);
+        assert_eq!(poll(&mut late), std::task::Poll::Ready(Err(RpcError::Timeout)));
+        assert!(!client.complete(answer(&writer, 1)), "too late");
+    }
+
+    #[test]
+    fn dropping_the_client_cancels_outstanding_calls() {
+        let writer = RecordingWriter::new();
+        let client = Client::<Num>::new(std::time::Duration::from_secs(60));
+        let mut call = client.request::<Caller, _, _>(&Num(1), &writer);
+        let clone = client.clone();
+        drop(client);
+        assert!(poll(&mut call).is_pending(), "a clone keeps the call alive");
+        drop(clone);
+        assert_eq!(poll(&mut call), std::task::Poll::Ready(Err(RpcError::Cancelled)));
+    }
+}
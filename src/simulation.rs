@@ -0,0 +1,206 @@
+use crate::clock::VirtualClock;
+use crate::traits;
+
+/// A worker that can be driven one iteration at a time; implemented by every
+/// worker generated by `Messenger!`.
+pub trait Step<MB>: std::any::Any {
+    /// Routes at most one message, then runs every handler's `on_loop`.
+    fn poll_once(&mut self, message_bus: &MB) -> bool;
+    fn has_pending(&self, message_bus: &MB) -> bool;
+    fn is_stopped(&self) -> bool;
+}
+
+/// Runs the workers of a Messenger on the calling thread in a seeded,
+/// reproducible interleaving, built by `Messenger::simulation`.
+///
+/// Each step polls one worker with a message waiting, picked by the seeded
+/// generator, so the same seed replays the same interleaving. Time only
+/// moves through [`run_for`](Simulation::run_for), which advances a
+/// [`VirtualClock`]; handling messages takes no virtual time.
+///
+/// `has_pending` must not block, so the bus must not be a `CondvarBus`.
+pub struct Simulation<MB: traits::core::MessageBus> {
+    message_bus: MB,
+    workers: Vec<(String, Box<dyn Step<MB>>)>,
+    rng: SplitMix64,
+    clock: Option<(VirtualClock, std::time::Duration)>,
+    max_steps: usize,
+}
+
+impl<MB: traits::core::MessageBus> Simulation<MB> {
+    pub fn new(message_bus: MB, seed: u64) -> Simulation<MB> {
+        Simulation {
+            message_bus,
+            workers: Vec::new(),
+            rng: SplitMix64(seed),
+            clock: None,
+            max_steps: 1_000_000,
+        }
+    }
+
+    pub fn add_worker<S: Step<MB>>(&mut self, name: String, worker: S) {
+        self.workers.push((name, Box::new(worker)));
+    }
+
+    /// The clock [`run_for`](Simulation::run_for) advances, `tick` at a time.
+    /// Handlers should read the same clock, e.g. through their config.
+    pub fn with_clock(mut self, clock: VirtualClock, tick: std::time::Duration) -> Simulation<MB> {
+        assert!(!tick.is_zero(), "the clock tick must be positive");
+        self.clock = Some((clock, tick));
+        self
+    }
+
+    /// Polls allowed per [`run_until_quiescent`](Simulation::run_until_quiescent)
+    /// before it panics, to catch handlers that never stop sending.
+    pub fn with_max_steps(mut self, max_steps: usize) -> Simulation<MB> {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn message_bus(&self) -> &MB {
+        &self.message_bus
+    }
+
+    /// Names of the workers, `Pool.0`, `Pool.1`, ... for replicas.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.workers.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// The worker named `name`, if it is a `W`.
+    pub fn worker<W: Step<MB>>(&self, name: &str) -> Option<&W> {
+        let (_, worker) = self.workers.iter().find(|(n, _)| n == name)?;
+        let worker: &dyn std::any::Any = &**worker;
+        worker.downcast_ref()
+    }
+
+    pub fn worker_mut<W: Step<MB>>(&mut self, name: &str) -> Option<&mut W> {
+        let (_, worker) = self.workers.iter_mut().find(|(n, _)| n == name)?;
+        let worker: &mut dyn std::any::Any = &mut **worker;
+        worker.downcast_mut()
+    }
+
+    /// Polls one worker with a message waiting, chosen by the seeded
+    /// generator; `false` if no worker has one.
+    pub fn step(&mut self) -> bool {
+        let pending: Vec<usize> = (0..self.workers.len())
+            .filter(|&i| {
+                let worker = &self.workers[i].1;
+                !worker.is_stopped() && worker.has_pending(&self.message_bus)
+            })
+            .collect();
+        if pending.is_empty() {
+            return false;
+        }
+        let i = pending[self.rng.below(pending.len())];
+        self.workers[i].1.poll_once(&self.message_bus);
+        true
+    }
+
+    /// Steps until every message has been handled and one more round of
+    /// every worker's `on_loop` sends nothing new. The first call also runs
+    /// the handlers' `on_start`. Returns the number of polls.
+    ///
+    /// Panics after `max_steps` polls.
+    pub fn run_until_quiescent(&mut self) -> usize {
+        let mut steps = 0;
+        loop {
+            while self.step() {
+                steps += 1;
+                self.check_steps(steps);
+            }
+            // Nothing left to read: give every `on_loop` (and on the first
+            // round every `on_start`) a chance to send more.
+            let mut order: Vec<usize> = (0..self.workers.len()).collect();
+            self.rng.shuffle(&mut order);
+            for i in order {
+                if !self.workers[i].1.is_stopped() {
+                    self.workers[i].1.poll_once(&self.message_bus);
+                    steps += 1;
+                    self.check_steps(steps);
+                }
+            }
+            if !self.workers.iter().any(|(_, worker)| {
+                !worker.is_stopped() && worker.has_pending(&self.message_bus)
+            }) {
+                return steps;
+            }
+        }
+    }
+
+    /// Advances the clock by `duration`, one tick at a time, running until
+    /// quiescent after every tick. Returns the number of polls.
+    ///
+    /// Panics if the simulation has no clock.
+    pub fn run_for(&mut self, duration: std::time::Duration) -> usize {
+        let (clock, tick) = self
+            .clock
+            .clone()
+            .expect("run_for needs a clock, see Simulation::with_clock");
+        let end = clock.elapsed() + duration;
+        let mut steps = self.run_until_quiescent();
+        while clock.elapsed() < end {
+            clock.advance(tick.min(end - clock.elapsed()));
+            steps += self.run_until_quiescent();
+        }
+        steps
+    }
+
+    fn check_steps(&self, steps: usize) {
+        assert!(
+            steps <= self.max_steps,
+            "simulation not quiescent after {} steps",
+            self.max_steps
+        );
+    }
+}
+
+/// splitmix64: tiny, seedable and good enough to pick interleavings.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = SplitMix64(7);
+        let mut b = SplitMix64(7);
+        let mut c = SplitMix64(8);
+        let a: Vec<_> = (0..8).map(|_| a.next()).collect();
+        let b: Vec<_> = (0..8).map(|_| b.next()).collect();
+        let c: Vec<_> = (0..8).map(|_| c.next()).collect();
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn shuffle_is_a_permutation() {
+        let mut rng = SplitMix64(42);
+        let mut items: Vec<u32> = (0..100).collect();
+        rng.shuffle(&mut items);
+        assert_ne!(items, (0..100).collect::<Vec<_>>());
+        items.sort();
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,221 @@
+use crate::traits;
+
+/// One message captured by a [`RecordingWriter`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub source: u16,
+    pub message_id: u16,
+    pub payload: Vec<u8>,
+}
+
+/// A [`Writer`](traits::core::Writer) that records every write instead of
+/// publishing it, for unit-testing handlers without a bus or threads:
+///
+/// ```ignore
+/// let writer = testing::RecordingWriter::new();
+/// let mut handler = Ponger::new(&config, &writer);
+/// handler.handle(&Ping(7), &writer);
+/// assert_eq!(writer.decode(0, Pong::deserialize_from).0, 7);
+/// ```
+///
+/// Clones share the same records.
+#[derive(Debug, Clone, Default)]
+pub struct RecordingWriter {
+    records: std::sync::Arc<std::sync::Mutex<Vec<Record>>>,
+}
+
+impl RecordingWriter {
+    pub fn new() -> RecordingWriter {
+        RecordingWriter::default()
+    }
+
+    /// Every write so far, oldest first.
+    pub fn records(&self) -> Vec<Record> {
+        self.lock().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Removes and returns the records, so a test can check what one call
+    /// sent.
+    pub fn take(&self) -> Vec<Record> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    /// The `n`th write; panics if there are fewer.
+    pub fn record(&self, n: usize) -> Record {
+        let records = self.lock();
+        records
+            .get(n)
+            .unwrap_or_else(|| panic!("message {n} was not sent, only {} were", records.len()))
+            .clone()
+    }
+
+    /// Decodes the `n`th write as `M` with `decode`, usually the message's
+    /// `deserialize_from`. Panics if it is not an `M`.
+    pub fn decode<M: traits::core::Message, F: FnOnce(&[u8]) -> M>(&self, n: usize, decode: F) -> M {
+        let record = self.record(n);
+        assert_message_id::<M>(n, &record);
+        decode(&record.payload)
+    }
+
+    /// Reads the `n`th write as the zero-copy message `M`. Panics if it is
+    /// not an `M`.
+    pub fn read<M: traits::zero_copy::ZeroCopyMessage>(&self, n: usize) -> M {
+        let record = self.record(n);
+        assert_message_id::<M>(n, &record);
+        assert!(record.payload.len() >= M::SIZE, "message {n} is too short");
+        // SAFETY: the payload holds at least `M::SIZE` bytes, written as an
+        // `M` (checked by id); `ZeroCopyMessage` types are valid from bytes.
+        unsafe { std::ptr::read_unaligned(record.payload.as_ptr() as *const M) }
+    }
+
+    #[track_caller]
+    pub fn assert_nothing_sent(&self) {
+        let records = self.lock();
+        assert!(records.is_empty(), "expected no messages, got {:?}", *records);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Record>> {
+        // A handler panicking mid-test poisons the lock; the records are
+        // still worth reporting.
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[track_caller]
+fn assert_message_id<M: traits::core::Message>(n: usize, record: &Record) {
+    let expected = Into::<u16>::into(M::ID);
+    assert_eq!(
+        record.message_id,
+        expected,
+        "message {n} has id {}, expected {} ({})",
+        record.message_id,
+        expected,
+        std::any::type_name::<M>()
+    );
+}
+
+impl traits::core::Writer for RecordingWriter {
+    #[inline]
+    fn write<M: traits::core::Message, H: traits::core::Handler, F: FnOnce(&mut [u8])>(
+        &self,
+        size: usize,
+        callback: F,
+    ) {
+        traits::core::RawWriter::write_raw(self, H::ID.into(), M::ID.into(), size, callback);
+    }
+}
+
+impl traits::core::RawWriter for RecordingWriter {
+    fn write_raw<F: FnOnce(&mut [u8])>(
+        &self,
+        source: u16,
+        message_id: u16,
+        size: usize,
+        callback: F,
+    ) {
+        // usize-aligned and padded like a bus slot, so zero-copy senders may
+        // cast it and fill it whole; only `size` bytes are the payload.
+        let mut words = vec![0usize; size.div_ceil(std::mem::size_of::<usize>())];
+        let aligned_size = words.len() * std::mem::size_of::<usize>();
+        // SAFETY: `words` owns `aligned_size` initialized bytes.
+        let buffer = unsafe { std::slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, aligned_size) };
+        callback(buffer);
+        let record = Record {
+            source,
+            message_id,
+            payload: buffer[..size].to_vec(),
+        };
+        self.lock().push(record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct H;
+    impl traits::core::Handler for H {
+        type Id = u16;
+        const ID: u16 = 4;
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    #[repr(C)]
+    struct Point {
+        x: u64,
+        y: u32,
+    }
+    impl traits::core::Message for Point {
+        type Id = u16;
+        const ID: u16 = 2;
+    }
+    impl traits::zero_copy::ZeroCopyMessage for Point {}
+
+    struct Text(String);
+    impl traits::core::Message for Text {
+        type Id = u16;
+        const ID: u16 = 3;
+    }
+    impl traits::extended::ExtendedMessage for Text {
+        fn get_size(&self) -> usize {
+            self.0.len()
+        }
+        fn write_into(&self, buffer: &mut [u8]) {
+            buffer[..self.0.len()].copy_from_slice(self.0.as_bytes());
+        }
+    }
+    impl Text {
+        fn deserialize_from(buffer: &[u8]) -> Text {
+            Text(String::from_utf8(buffer.to_vec()).unwrap())
+        }
+    }
+
+    #[test]
+    fn records_and_decodes_both_sender_kinds() {
+        let writer = RecordingWriter::new();
+        writer.assert_nothing_sent();
+
+        <H as traits::extended::Sender>::send(&Text("hello".into()), &writer);
+        <H as traits::zero_copy::Sender>::send::<Point, _, _>(&writer, |point| unsafe {
+            point.write(Point { x: 1, y: 2 })
+        });
+
+        assert_eq!(writer.len(), 2);
+        let first = writer.record(0);
+        assert_eq!((first.source, first.message_id), (4, 3));
+        assert_eq!(first.payload, b"hello");
+        assert_eq!(writer.decode(0, Text::deserialize_from).0, "hello");
+        assert_eq!(writer.read::<Point>(1), Point { x: 1, y: 2 });
+
+        assert_eq!(writer.clone().take().len(), 2, "clones share records");
+        writer.assert_nothing_sent();
+    }
+
+    #[test]
+    fn callbacks_get_the_padded_slot_like_a_bus() {
+        use traits::core::RawWriter;
+
+        let writer = RecordingWriter::new();
+        writer.write_raw(1, 2, 5, |buffer| {
+            assert_eq!(buffer.len(), 5usize.next_multiple_of(std::mem::size_of::<usize>()));
+            buffer.fill(7);
+        });
+        assert_eq!(writer.record(0).payload, [7; 5]);
+    }
+
+    #[test]
+    #[should_panic(expected = "message 0 has id 3, expected 2")]
+    fn decoding_the_wrong_type_panics() {
+        let writer = RecordingWriter::new();
+        <H as traits::extended::Sender>::send(&Text("x".into()), &writer);
+        writer.read::<Point>(0);
+    }
+}
@@ -0,0 +1,274 @@
+use crate::clock;
+use crate::traits;
+
+/// Identifies a scheduled message, for [`Timers::cancel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimerHandle(u64);
+
+/// Delayed and periodic messages, published onto the bus when due.
+///
+/// A message is serialized when it is scheduled and published by
+/// [`fire`](Timers::fire), stamped with the handler type that scheduled it;
+/// fired through an [`InstanceWriter`](crate::messenger::InstanceWriter),
+/// with the instance id instead. To deliver it back to that handler, route
+/// the message from the handler to itself. Handlers usually own their `Timers` and call `fire` from
+/// `on_loop`, which the worker runs on every iteration:
+///
+/// ```ignore
+/// fn on_start<W: traits::core::Writer>(&mut self, _writer: &W) {
+///     self.heartbeat = self.timers.every::<Self, _>(Duration::from_secs(1), &messages::Heartbeat);
+/// }
+///
+/// fn on_loop<W: traits::core::Writer>(&mut self, writer: &W) {
+///     self.timers.fire(writer);
+/// }
+/// ```
+///
+/// Clones share the schedule, so one handler can fire the timers of several.
+/// Time comes from a [`Clock`](clock::Clock), so under simulation a
+/// [`VirtualClock`](clock::VirtualClock) decides when timers are due.
+#[derive(Clone)]
+pub struct Timers {
+    clock: std::sync::Arc<dyn clock::Clock>,
+    schedule: std::sync::Arc<std::sync::Mutex<Schedule>>,
+}
+
+/// Timers ordered by due time; `due` maps each live timer to its key, so
+/// cancelling is a lookup.
+#[derive(Default)]
+struct Schedule {
+    next_id: u64,
+    queue: std::collections::BTreeMap<(std::time::Instant, u64), Timer>,
+    due: std::collections::HashMap<u64, std::time::Instant>,
+}
+
+#[derive(Clone)]
+struct Timer {
+    interval: Option<std::time::Duration>,
+    source: u16,
+    message_id: u16,
+    payload: std::sync::Arc<[u8]>,
+}
+
+impl Timers {
+    /// Timers on the system clock.
+    pub fn new() -> Timers {
+        Timers::with_clock(std::sync::Arc::new(clock::SystemClock))
+    }
+
+    pub fn with_clock(clock: std::sync::Arc<dyn clock::Clock>) -> Timers {
+        Timers {
+            clock,
+            schedule: Default::default(),
+        }
+    }
+
+    /// Publishes `message` as `H` once, `delay` from now.
+    pub fn after<H: traits::core::Handler, M: traits::extended::ExtendedMessage>(
+        &self,
+        delay: std::time::Duration,
+        message: &M,
+    ) -> TimerHandle {
+        self.schedule(delay, None, H::ID.into(), M::ID.into(), serialize(message))
+    }
+
+    /// Publishes `message` as `H` every `interval`, starting one interval
+    /// from now, until cancelled. Periods missed because `fire` was not
+    /// called in time are skipped, not published late.
+    pub fn every<H: traits::core::Handler, M: traits::extended::ExtendedMessage>(
+        &self,
+        interval: std::time::Duration,
+        message: &M,
+    ) -> TimerHandle {
+        assert!(!interval.is_zero(), "the timer interval must be positive");
+        self.schedule(interval, Some(interval), H::ID.into(), M::ID.into(), serialize(message))
+    }
+
+    /// Stops the timer; `false` if it already fired (for one-shot timers)
+    /// or was cancelled.
+    pub fn cancel(&self, handle: TimerHandle) -> bool {
+        let mut schedule = self.lock();
+        match schedule.due.remove(&handle.0) {
+            Some(due) => schedule.queue.remove(&(due, handle.0)).is_some(),
+            None => false,
+        }
+    }
+
+    /// Publishes every message that is due, earliest first, and reschedules
+    /// periodic ones. Returns the number published.
+    pub fn fire<W: traits::core::RawWriter>(&self, writer: &W) -> usize {
+        let now = self.clock.now();
+        let mut fired = 0;
+        loop {
+            // Write outside the lock, so a clone may schedule from another
+            // thread meanwhile.
+            let timer = {
+                let mut schedule = self.lock();
+                let Some(entry) = schedule.queue.first_entry() else {
+                    break;
+                };
+                let (due, id) = *entry.key();
+                if due > now {
+                    break;
+                }
+                let timer = entry.remove();
+                match timer.interval {
+                    Some(interval) => {
+                        let mut next = due + interval;
+                        while next <= now {
+                            next += interval;
+                        }
+                        schedule.due.insert(id, next);
+                        schedule.queue.insert((next, id), timer.clone());
+                    }
+                    None => {
+                        schedule.due.remove(&id);
+                    }
+                }
+                timer
+            };
+            let payload = &timer.payload;
+            writer.write_raw(timer.source, timer.message_id, payload.len(), |buffer| {
+                buffer[..payload.len()].copy_from_slice(payload)
+            });
+            fired += 1;
+        }
+        fired
+    }
+
+    /// When the next timer is due, e.g. to park a thread until then.
+    pub fn next_due(&self) -> Option<std::time::Instant> {
+        self.lock().queue.keys().next().map(|&(due, _)| due)
+    }
+
+    /// Number of scheduled timers.
+    pub fn len(&self) -> usize {
+        self.lock().queue.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().queue.is_empty()
+    }
+
+    fn schedule(
+        &self,
+        delay: std::time::Duration,
+        interval: Option<std::time::Duration>,
+        source: u16,
+        message_id: u16,
+        payload: std::sync::Arc<[u8]>,
+    ) -> TimerHandle {
+        let due = self.clock.now() + delay;
+        let mut schedule = self.lock();
+        let id = schedule.next_id;
+        schedule.next_id += 1;
+        schedule.due.insert(id, due);
+        schedule.queue.insert(
+            (due, id),
+            Timer {
+                interval,
+                source,
+                message_id,
+                payload,
+            },
+        );
+        TimerHandle(id)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Schedule> {
+        self.schedule.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Timers {
+        Timers::new()
+    }
+}
+
+fn serialize<M: traits::extended::ExtendedMessage>(message: &M) -> std::sync::Arc<[u8]> {
+    let mut payload = vec![0; message.get_size()];
+    message.write_into(&mut payload);
+    payload.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::Clock;
+    use crate::testing::RecordingWriter;
+
+    struct H;
+    impl traits::core::Handler for H {
+        type Id = u16;
+        const ID: u16 = 3;
+    }
+
+    struct Num(u32);
+    impl traits::core::Message for Num {
+        type Id = u16;
+        const ID: u16 = 5;
+    }
+    impl traits::extended::ExtendedMessage for Num {
+        fn get_size(&self) -> usize {
+            4
+        }
+        fn write_into(&self, buffer: &mut [u8]) {
+            buffer[..4].copy_from_slice(&self.0.to_ne_bytes());
+        }
+    }
+
+    fn sent(writer: &RecordingWriter) -> Vec<u32> {
+        writer
+            .take()
+            .iter()
+            .map(|record| {
+                assert_eq!((record.source, record.message_id), (3, 5));
+                u32::from_ne_bytes(record.payload[..4].try_into().unwrap())
+            })
+            .collect()
+    }
+
+    fn ms(millis: u64) -> std::time::Duration {
+        std::time::Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn one_shot_timers_fire_once_in_due_order() {
+        let clock = clock::VirtualClock::new();
+        let timers = Timers::with_clock(std::sync::Arc::new(clock.clone()));
+        let writer = RecordingWriter::new();
+        timers.after::<H, _>(ms(20), &Num(2));
+        timers.after::<H, _>(ms(10), &Num(1));
+        let cancelled = timers.after::<H, _>(ms(15), &Num(99));
+        assert_eq!(timers.next_due(), Some(clock.now() + ms(10)));
+
+        assert_eq!(timers.fire(&writer), 0);
+        assert!(timers.cancel(cancelled));
+        assert!(!timers.cancel(cancelled));
+        clock.advance(ms(20));
+        assert_eq!(timers.fire(&writer), 2);
+        assert_eq!(sent(&writer), [1, 2]);
+        assert!(timers.is_empty());
+    }
+
+    #[test]
+    fn periodic_timers_skip_missed_periods() {
+        let clock = clock::VirtualClock::new();
+        let timers = Timers::with_clock(std::sync::Arc::new(clock.clone()));
+        let writer = RecordingWriter::new();
+        let start = clock.now();
+        let beat = timers.every::<H, _>(ms(10), &Num(7));
+
+        clock.advance(ms(10));
+        timers.fire(&writer);
+        clock.advance(ms(35));
+        timers.fire(&writer);
+        assert_eq!(sent(&writer), [7, 7]);
+        assert_eq!(timers.next_due(), Some(start + ms(50)));
+
+        assert!(timers.cancel(beat));
+        clock.advance(ms(100));
+        assert_eq!(timers.fire(&writer), 0);
+    }
+}
@@ -6,17 +6,76 @@ pub trait Handler {
     fn on_start<W: Writer>(&mut self, _writer: &W) {}
     fn on_loop<W: Writer>(&mut self, _writer: &W) {}
     fn on_stop(&mut self) {}
+
+    /// What the worker does after this handler panics in `on_start`,
+    /// `handle` or `on_loop`; by default the panic stops the Messenger.
+    const ON_PANIC: messenger::Supervision = messenger::Supervision::Escalate;
+
+    /// How often [`Restart`](messenger::Supervision::Restart) may rebuild
+    /// this handler before a panic escalates instead.
+    const RESTART_LIMIT: messenger::RestartLimit = messenger::RestartLimit::DEFAULT;
+
+    /// Called on the panicking handler after a caught panic, before
+    /// [`ON_PANIC`](Handler::ON_PANIC) is applied.
+    fn on_panic(&mut self, _event: &messenger::PanicEvent) {}
+
+    /// Called once the worker has replayed the messages that were already on
+    /// the bus when it was opened (see
+    /// [`MessageBus::history_end`]); right after
+    /// `on_start` if there are none to replay.
+    fn on_replay_complete<W: Writer>(&mut self, _writer: &W) {}
+
+    /// What happens to this handler's writes from `handle` and `on_loop`
+    /// while its worker replays history; by default they are published.
+    const REPLAY_WRITES: messenger::ReplayWrites = messenger::ReplayWrites::Publish;
 }
 
 pub trait Handle<M: Message> {
     fn handle<W: Writer>(&mut self, message: &M, writer: &W);
 }
 
+/// Like [`Handle`], with the [`MessageContext`](messenger::MessageContext)
+/// of the message: its source, bus position and receiving worker. Every
+/// `Handle<M>` implements it by ignoring the context, so a handler
+/// implements either trait for a given message; routes generated by
+/// `Messenger!` call this one.
+pub trait HandleWithContext<M: Message> {
+    fn handle_with_context<W: Writer>(
+        &mut self,
+        message: &M,
+        context: &messenger::MessageContext,
+        writer: &W,
+    );
+}
+
+impl<M: Message, H: Handle<M>> HandleWithContext<M> for H {
+    #[inline]
+    fn handle_with_context<W: Writer>(
+        &mut self,
+        message: &M,
+        _context: &messenger::MessageContext,
+        writer: &W,
+    ) {
+        self.handle(message, writer)
+    }
+}
+
 pub trait Message {
     type Id: Into<u16>;
     const ID: Self::Id;
 }
 
+/// Key of a message on `sharded` routes of `Messenger!`: the message goes to
+/// the one worker replica that owns `hash(key)`, so all messages with the
+/// same key are handled by the same replica, in bus order.
+///
+/// Implemented on the type the route deserializes to; a zero-copy message
+/// routed as `&M` works through auto-deref.
+pub trait PartitionKey {
+    type Key: std::hash::Hash;
+    fn partition_key(&self) -> Self::Key;
+}
+
 pub trait Reader {
     /// Returns the header and payload of the message written at `position`,
     /// or `None` if no valid message exists there. The references borrow the
@@ -25,9 +84,52 @@ pub trait Reader {
     /// Implementations with bounded storage may panic if the reader has
     /// fallen so far behind that the message at `position` was overwritten.
     fn read(&self, position: usize) -> Option<(&messenger::Header, &[u8])>;
+
+    /// Like [`read`](Reader::read), but reports a reader that has fallen
+    /// behind as [`Lapped`](messenger::Lapped) instead of panicking. Buses
+    /// that never overwrite messages keep this default.
+    fn try_read(
+        &self,
+        position: usize,
+    ) -> Result<Option<(&messenger::Header, &[u8])>, messenger::Lapped> {
+        Ok(self.read(position))
+    }
+}
+
+/// Bus positions for readers that do not start at position 0.
+pub trait Positions {
+    /// The position the next message will be written at: a reader starting
+    /// here sees only messages written from now on.
+    fn write_head(&self) -> usize;
+
+    /// The position of the oldest message still readable, or the write head
+    /// if there is none. On a bus that overwrites old messages, a reader
+    /// starting here may still be lapped if writers are fast. Buses that
+    /// never drop messages keep this default.
+    fn oldest(&self) -> usize {
+        0
+    }
+
+    /// The position after the slot at `position` when a reader cannot read
+    /// it but its length is known: its writer is still writing it, or
+    /// panicked. `None` otherwise. Lets a reader that must not stall, such
+    /// as a flight recorder dump, step over the slot. Buses that do not
+    /// track unreadable slots keep this default.
+    fn skip(&self, _position: usize) -> Option<usize> {
+        None
+    }
 }
 
-pub trait Writer: Sync + Send + Clone + 'static {
+/// Writes messages whose header ids are only known at run time, such as
+/// scheduled timers, bridged messages and replays, which take it as a bound.
+/// Every [`Writer`] is one.
+pub trait RawWriter: Sync + Send + Clone + 'static {
+    /// Like [`Writer::write`], with the header's source and message ids
+    /// given as values.
+    fn write_raw<F: FnOnce(&mut [u8])>(&self, source: u16, message_id: u16, size: usize, callback: F);
+}
+
+pub trait Writer: RawWriter {
     /// Reserves `size` bytes (rounded up to alignment) and passes the payload
     /// buffer to `callback`. The buffer is only valid inside the callback.
     ///
@@ -40,8 +142,39 @@ pub trait Writer: Sync + Send + Clone + 'static {
 
 pub trait MessageBus: Reader + Writer {
     fn on_stop(&self) {}
+
+    /// The bus's [`Positions`], needed by workers declared `start: Tail` or
+    /// `start: Oldest`. Buses without them keep this default.
+    fn positions(&self) -> Option<&dyn Positions> {
+        None
+    }
+
+    /// The position `consumer` last committed with
+    /// [`commit_offset`](MessageBus::commit_offset), or `None` if it never
+    /// did. Buses that do not persist offsets keep this default.
+    fn committed_offset(&self, _consumer: &str) -> Option<usize> {
+        None
+    }
+
+    /// Records that `consumer` has durably processed every message before
+    /// `position`, for workers declared `start: Committed` to resume from.
+    /// Buses that do not persist offsets keep this default, which fails
+    /// with `ErrorKind::Unsupported`.
+    fn commit_offset(&self, consumer: &str, _position: usize) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            format!("{} does not persist the offset of {consumer}", std::any::type_name::<Self>()),
+        ))
+    }
+
+    /// The write head when the bus was opened: messages before it were
+    /// written by an earlier process, and workers reading them are replaying
+    /// history. Buses that start empty keep this default.
+    fn history_end(&self) -> usize {
+        0
+    }
 }
 
 pub trait Router {
-    fn route<'a, W: Writer>(&mut self, header: &'a messenger::Header, buffer: &'a [u8], writer: &W);
+    fn route<'a, MB: MessageBus>(&mut self, header: &'a messenger::Header, buffer: &'a [u8], message_bus: &MB);
 }
@@ -6,6 +6,14 @@ pub trait ExtendedMessage: super::core::Message {
     fn write_into(&self, buffer: &mut [u8]);
 }
 
+/// Decoding counterpart of [`ExtendedMessage`], for generic wrappers such as
+/// [`rpc::Call`](crate::rpc::Call) that decode the message they wrap. Routes
+/// do not need it: they call the message's inherent `deserialize_from`, which
+/// implementations usually forward to.
+pub trait DeserializeFrom: ExtendedMessage + Sized {
+    fn deserialize_from(buffer: &[u8]) -> Self;
+}
+
 /// Helper trait that provides a serialization default implementation
 /// using the functions provided by the ExtendedMessage trait
 pub trait Sender {
@@ -0,0 +1,211 @@
+//! Bridges carry the selected messages of one bus onto another with their
+//! header ids: in process with a `BusBridge`, or over TCP, reconnecting when
+//! the receiving side comes back.
+
+use rust_messenger::bridge::{bus::BusBridge, tcp, Filter};
+use rust_messenger::cursor::Cursor;
+use rust_messenger::message_bus::atomic_circular_bus::{CircularBus, Config};
+use rust_messenger::traits::core::{Positions, RawWriter};
+
+const SENSOR: u16 = 7;
+const OTHER: u16 = 8;
+const READING: u16 = 3;
+
+struct Cfg;
+
+impl Config for Cfg {
+    fn get_buffer_size(&self) -> usize {
+        1 << 16
+    }
+}
+
+fn publish(bus: &CircularBus, source: u16, value: u32) {
+    bus.write_raw(source, READING, 4, |buffer| {
+        buffer[..4].copy_from_slice(&value.to_ne_bytes())
+    });
+}
+
+/// Waits until `count` messages reached `bus` past `cursor`, returning
+/// their `(source, message_id, value)`.
+fn receive(bus: &CircularBus, cursor: &mut Cursor, count: usize) -> Vec<(u16, u16, u32)> {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let mut received = Vec::new();
+    while received.len() < count {
+        assert!(std::time::Instant::now() < deadline, "received only {received:?}");
+        match cursor.next(bus).unwrap() {
+            Some((header, payload)) => received.push((
+                header.source,
+                header.message_id,
+                u32::from_ne_bytes(payload[..4].try_into().unwrap()),
+            )),
+            None => std::thread::sleep(std::time::Duration::from_millis(1)),
+        }
+    }
+    received
+}
+
+#[test]
+fn forwards_selected_routes_with_their_ids() {
+    let local = CircularBus::new(&Cfg);
+    let remote = CircularBus::new(&Cfg);
+    let mut cursor = Cursor::tail(&remote);
+
+    let receiver = tcp::TcpReceiver::bind("127.0.0.1:0").unwrap();
+    let addr = receiver.local_addr().unwrap();
+    let inbound = receiver.spawn(remote.clone());
+    let outbound = tcp::TcpForwarder::new(addr.to_string(), Filter::new().route(SENSOR, READING))
+        .spawn(local.clone());
+
+    for value in 0..100 {
+        publish(&local, SENSOR, value);
+        publish(&local, OTHER, value);
+    }
+    let received = receive(&remote, &mut cursor, 100);
+    let expected: Vec<_> = (0..100).map(|value| (SENSOR, READING, value)).collect();
+    assert_eq!(received, expected);
+
+    outbound.stop();
+    inbound.stop();
+    // Nothing from `OTHER` followed.
+    assert_eq!(cursor.position(), remote.write_head());
+}
+
+#[test]
+fn receiver_can_restamp_the_source() {
+    let local = CircularBus::new(&Cfg);
+    let remote = CircularBus::new(&Cfg);
+    let mut cursor = Cursor::tail(&remote);
+
+    let receiver = tcp::TcpReceiver::bind("127.0.0.1:0").unwrap().source(42u16);
+    let addr = receiver.local_addr().unwrap();
+    let _inbound = receiver.spawn(remote.clone());
+    let _outbound = tcp::TcpForwarder::new(addr.to_string(), Filter::all()).spawn(local.clone());
+
+    publish(&local, SENSOR, 1);
+    assert_eq!(receive(&remote, &mut cursor, 1), [(42, READING, 1)]);
+}
+
+#[test]
+fn receiver_reports_connections_it_drops() {
+    let local = CircularBus::new(&Cfg);
+    let remote = CircularBus::new(&Cfg);
+
+    let receiver = tcp::TcpReceiver::bind("127.0.0.1:0").unwrap().max_message_size(2);
+    let addr = receiver.local_addr().unwrap();
+    let inbound = receiver.spawn(remote.clone());
+    let _outbound = tcp::TcpForwarder::new(addr.to_string(), Filter::all()).spawn(local.clone());
+
+    publish(&local, SENSOR, 1);
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while inbound.failed() == 0 {
+        assert!(std::time::Instant::now() < deadline, "the oversized message was accepted");
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    let errors = inbound.take_errors();
+    assert_eq!(errors[0].error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(errors[0].peer.is_some());
+    assert!(errors[0].to_string().contains("exceeds the 2 byte limit"), "{}", errors[0]);
+    assert_eq!(remote.write_head(), 0, "nothing was written");
+}
+
+#[test]
+fn forwarder_waits_for_the_receiver_and_reconnects() {
+    let local = CircularBus::new(&Cfg);
+    let remote = CircularBus::new(&Cfg);
+    let mut cursor = Cursor::tail(&remote);
+
+    // Reserve a port, then close it so the first connection attempts fail.
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let outbound = tcp::TcpForwarder::new(addr.to_string(), Filter::all())
+        .backoff(
+            std::time::Duration::from_millis(1),
+            std::time::Duration::from_millis(20),
+        )
+        .spawn(local.clone());
+    publish(&local, SENSOR, 1);
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert_eq!(outbound.connections(), 0);
+
+    // Messages written while disconnected wait in the bus.
+    let inbound = tcp::TcpReceiver::bind(addr).unwrap().spawn(remote.clone());
+    assert_eq!(receive(&remote, &mut cursor, 1), [(SENSOR, READING, 1)]);
+    inbound.stop();
+
+    let _inbound = tcp::TcpReceiver::bind(addr).unwrap().spawn(remote.clone());
+    // The forwarder only notices the dropped connection when it writes.
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let mut value = 2;
+    while outbound.connections() < 2 {
+        assert!(std::time::Instant::now() < deadline, "did not reconnect");
+        publish(&local, SENSOR, value);
+        value += 1;
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    // Some of the messages written into the dead connection are lost, but
+    // the ones after the reconnect arrive.
+    publish(&local, SENSOR, 1000);
+    while receive(&remote, &mut cursor, 1) != [(SENSOR, READING, 1000)] {}
+}
+
+#[test]
+fn bus_bridge_copies_selected_routes() {
+    let hot = CircularBus::new(&Cfg);
+    let audit = CircularBus::new(&Cfg);
+    let mut cursor = Cursor::tail(&audit);
+
+    publish(&hot, SENSOR, 0);
+    let mut bridge = BusBridge::new(hot.clone(), audit.clone(), Filter::new().route(SENSOR, READING));
+    for value in 1..=3 {
+        publish(&hot, SENSOR, value);
+        publish(&hot, OTHER, value);
+    }
+    assert_eq!(bridge.pump(), 3);
+    assert_eq!(bridge.pump(), 0);
+    assert_eq!(bridge.copied(), 3);
+    assert_eq!(
+        receive(&audit, &mut cursor, 3),
+        [(SENSOR, READING, 1), (SENSOR, READING, 2), (SENSOR, READING, 3)]
+    );
+    assert_eq!(cursor.position(), audit.write_head());
+}
+
+#[test]
+fn bus_bridge_counts_laps_and_skips_to_the_oldest_message() {
+    let hot = CircularBus::new(&Cfg);
+    let audit = CircularBus::new(&Cfg);
+    let mut cursor = Cursor::tail(&audit);
+
+    let mut bridge = BusBridge::new(hot.clone(), audit.clone(), Filter::all());
+    // More than the ring holds: the first messages are overwritten.
+    let written = (Cfg.get_buffer_size() / 24 + 100) as u32;
+    for value in 0..written {
+        publish(&hot, SENSOR, value);
+    }
+    let copied = bridge.pump();
+    assert_eq!(bridge.lapped(), 1);
+    let (_, _, first) = receive(&audit, &mut cursor, copied)[0];
+    assert!(first > 0, "the overwritten messages are lost");
+    while bridge.pump() > 0 {}
+    assert_eq!(bridge.copied(), u64::from(written - first));
+}
+
+#[test]
+fn bus_bridge_thread_can_start_at_the_oldest_message() {
+    let hot = CircularBus::new(&Cfg);
+    let mirror = CircularBus::new(&Cfg);
+    let mut cursor = Cursor::tail(&mirror);
+
+    publish(&hot, SENSOR, 0);
+    let _bridge = BusBridge::new(hot.clone(), mirror.clone(), Filter::all())
+        .start(rust_messenger::messenger::StartAt::Oldest)
+        .spawn();
+    publish(&hot, OTHER, 1);
+
+    assert_eq!(
+        receive(&mirror, &mut cursor, 2),
+        [(SENSOR, READING, 0), (OTHER, READING, 1)]
+    );
+}
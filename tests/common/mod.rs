@@ -0,0 +1,49 @@
+//! Messages, config and bus shared by the `Messenger!` integration tests.
+
+#![allow(dead_code)]
+
+/// The config of tests whose handlers need none.
+#[derive(Clone)]
+pub struct Config;
+
+/// Defines `$type`, a message holding one `u32`, with the id `$id` of the
+/// test's own `MessageId`.
+#[macro_export]
+macro_rules! impl_u32_message {
+    ($type:ident, $id:expr) => {
+        pub struct $type(pub u32);
+
+        impl rust_messenger::traits::core::Message for $type {
+            type Id = MessageId;
+            const ID: MessageId = $id;
+        }
+
+        impl $type {
+            pub fn deserialize_from(buffer: &[u8]) -> Self {
+                $type(u32::from_ne_bytes(buffer[..4].try_into().unwrap()))
+            }
+        }
+
+        impl rust_messenger::traits::extended::ExtendedMessage for $type {
+            fn get_size(&self) -> usize {
+                4
+            }
+            fn write_into(&self, buffer: &mut [u8]) {
+                buffer[..4].copy_from_slice(&self.0.to_ne_bytes());
+            }
+        }
+    };
+}
+
+struct RingConfig(usize);
+
+impl rust_messenger::message_bus::atomic_circular_bus::Config for RingConfig {
+    fn get_buffer_size(&self) -> usize {
+        self.0
+    }
+}
+
+/// A `CircularBus` of `buffer_size` bytes.
+pub fn ring(buffer_size: usize) -> rust_messenger::message_bus::atomic_circular_bus::CircularBus {
+    rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&RingConfig(buffer_size))
+}
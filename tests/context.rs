@@ -0,0 +1,122 @@
+//! Handlers implementing `HandleWithContext` instead of `Handle` receive the
+//! source, size and bus position of each message, also as instances.
+
+#[derive(Clone)]
+pub struct Config;
+
+const DESK_A: u16 = 80;
+const DESK_B: u16 = 81;
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Auditor = 1,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Order = 1,
+    }
+);
+
+pub struct Order(pub String);
+
+impl traits::core::Message for Order {
+    type Id = MessageId;
+    const ID: MessageId = MessageId::Order;
+}
+
+impl Order {
+    pub fn deserialize_from(buffer: &[u8]) -> Self {
+        Order(String::from_utf8(buffer.to_vec()).unwrap())
+    }
+}
+
+impl traits::extended::ExtendedMessage for Order {
+    fn get_size(&self) -> usize {
+        self.0.len()
+    }
+    fn write_into(&self, buffer: &mut [u8]) {
+        buffer[..self.0.len()].copy_from_slice(self.0.as_bytes());
+    }
+}
+
+pub struct Auditor {
+    pub seen: Vec<(String, messenger::MessageContext<'static>)>,
+}
+
+impl Auditor {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Auditor { seen: Vec::new() }
+    }
+}
+
+impl traits::core::Handler for Auditor {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Auditor;
+}
+
+impl traits::core::HandleWithContext<Order> for Auditor {
+    fn handle_with_context<W: traits::core::Writer>(
+        &mut self,
+        order: &Order,
+        context: &messenger::MessageContext,
+        _writer: &W,
+    ) {
+        self.seen.push((order.0.clone(), context.without_commit()));
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Audit:
+        handlers: [
+            auditor: Auditor,
+            instance @ 90: Auditor,
+        ]
+        routes: [
+            messenger::Source<DESK_A>, Order: [ auditor, instance ],
+            messenger::Source<DESK_B>, Order: [ auditor ],
+        ]
+}
+
+#[test]
+fn handlers_see_the_context_of_each_message() {
+    struct BusConfig;
+    impl rust_messenger::message_bus::atomic_circular_bus::Config for BusConfig {
+        fn get_buffer_size(&self) -> usize {
+            1 << 14
+        }
+    }
+    let bus = rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&BusConfig);
+    let messenger = Messenger::new(bus.clone());
+    #[cfg(feature = "timestamps")]
+    let before = std::time::SystemTime::now();
+    messenger.injector::<DESK_A>().send(&Order("buy 10".into()));
+    messenger.injector::<DESK_B>().send(&Order("sell 250".into()));
+
+    let mut audit = Audit::new(&bus, &Config);
+    while audit.poll_once(&bus) {}
+
+    let seen = &audit.auditor.seen;
+    assert_eq!(seen.len(), 2);
+    let (order, first) = &seen[0];
+    assert_eq!(order, "buy 10");
+    assert_eq!((first.source, first.message_id, first.size), (DESK_A, 1, 6));
+    assert_eq!((first.position, first.worker, first.replica), (0, "Audit", 0));
+    assert!(!first.replaying);
+
+    let (order, second) = &seen[1];
+    assert_eq!(order, "sell 250");
+    assert_eq!(second.source, DESK_B);
+    assert_eq!(second.position, first.next_position());
+    assert_eq!(second.next_position(), audit.position());
+    #[cfg(feature = "timestamps")]
+    {
+        assert!(first.timestamp() >= before);
+        assert!(second.timestamp() >= first.timestamp());
+        assert!(second.timestamp() <= std::time::SystemTime::now());
+    }
+
+    assert_eq!(audit.instance.inner().seen, seen[..1]);
+}
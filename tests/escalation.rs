@@ -0,0 +1,111 @@
+//! A panic in a handler with the default `Escalate` policy must stop every
+//! worker of the Messenger, so `join` returns without a call to `stop`.
+
+#[derive(Clone)]
+pub struct Config;
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Bomb = 1,
+        Idle = 2,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Never = 1,
+    }
+);
+
+pub struct Never;
+
+impl traits::core::Message for Never {
+    type Id = MessageId;
+    const ID: MessageId = MessageId::Never;
+}
+
+impl Never {
+    pub fn deserialize_from(_buffer: &[u8]) -> Self {
+        Never
+    }
+}
+
+pub struct Bomb;
+
+impl Bomb {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Bomb
+    }
+}
+
+impl traits::core::Handler for Bomb {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Bomb;
+
+    fn on_loop<W: traits::core::Writer>(&mut self, _writer: &W) {
+        panic!("boom");
+    }
+}
+
+impl traits::core::Handle<Never> for Bomb {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Never, _writer: &W) {}
+}
+
+pub struct Idle;
+
+impl Idle {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Idle
+    }
+}
+
+impl traits::core::Handler for Idle {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Idle;
+}
+
+impl traits::core::Handle<Never> for Idle {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Never, _writer: &W) {}
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Bombs:
+        handlers: [
+            bomb: Bomb,
+        ]
+        routes: [
+            Idle, Never: [ bomb ],
+        ]
+    Idlers:
+        handlers: [
+            idle: Idle,
+        ]
+        routes: [
+            Bomb, Never: [ idle ],
+        ]
+}
+
+#[test]
+fn escalated_panic_stops_the_messenger() {
+    struct BusConfig;
+    impl rust_messenger::message_bus::atomic_circular_bus::Config for BusConfig {
+        fn get_buffer_size(&self) -> usize {
+            1 << 16
+        }
+    }
+
+    let bus = rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&BusConfig);
+    let messenger = Messenger::new(bus);
+    let results = messenger.run(&Config).join();
+    assert_eq!(results.keys().collect::<Vec<_>>(), ["Bombs", "Idlers"]);
+    assert!(results.values().all(Result::is_ok), "panics are supervised");
+
+    let events = messenger.panic_events();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].worker, "Bombs");
+    assert_eq!(events[0].handler, "bomb");
+    assert_eq!(events[0].message, None);
+    assert_eq!(events[0].reason, "boom");
+    assert_eq!(events[0].action, messenger::Supervision::Escalate);
+}
@@ -0,0 +1,154 @@
+//! A flight recorder dumps what a `CircularBus` still holds into a file that
+//! opens as an `ExtendingBus`, on request or when a thread panics.
+
+#![cfg(target_os = "linux")]
+
+use rust_messenger::flight_recorder::FlightRecorder;
+use rust_messenger::message_bus::atomic_circular_bus::{self, CircularBus};
+use rust_messenger::message_bus::extending_bus::{self, ExtendingBus};
+use rust_messenger::traits::core::{RawWriter, Reader};
+
+const SENSOR: u16 = 7;
+const READING: u16 = 3;
+
+struct RingConfig;
+
+impl atomic_circular_bus::Config for RingConfig {
+    fn get_buffer_size(&self) -> usize {
+        1 << 14
+    }
+}
+
+struct DumpConfig {
+    path: std::path::PathBuf,
+    min_page_len: usize,
+}
+
+impl extending_bus::Config for DumpConfig {
+    fn get_file_path(&self) -> std::path::PathBuf {
+        self.path.clone()
+    }
+    fn get_min_page_len(&self) -> usize {
+        self.min_page_len
+    }
+    fn get_max_pages(&self) -> usize {
+        64
+    }
+}
+
+fn dump_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("rust-messenger-{name}-{}.bus", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+/// Publishes readings with payloads of varying length, so slots differ in
+/// size.
+fn publish(bus: &CircularBus, value: u32) {
+    let size = 4 + value as usize % 13;
+    bus.write_raw(SENSOR, READING, size, |buffer| {
+        buffer[..4].copy_from_slice(&value.to_ne_bytes());
+        buffer[4..size].fill(value as u8);
+    });
+}
+
+/// The readings in the dump at `path`, opened with `min_page_len`, checking
+/// every payload.
+fn replay(path: &std::path::Path, min_page_len: usize) -> Vec<u32> {
+    let dump = ExtendingBus::new(&DumpConfig {
+        path: path.to_path_buf(),
+        min_page_len,
+    });
+    let mut values = Vec::new();
+    let mut position = 0;
+    while let Some((header, payload)) = dump.read(position) {
+        assert_eq!((header.source, header.message_id), (SENSOR, READING));
+        let value = u32::from_ne_bytes(payload[..4].try_into().unwrap());
+        assert_eq!(payload.len(), 4 + value as usize % 13);
+        assert!(payload[4..].iter().all(|&byte| byte == value as u8));
+        values.push(value);
+        position += header.slot_len();
+    }
+    values
+}
+
+#[test]
+fn dump_holds_the_intact_tail_of_a_wrapped_ring() {
+    let path = dump_path("dump");
+    let bus = CircularBus::new(&RingConfig);
+    for value in 0..2000 {
+        publish(&bus, value);
+    }
+
+    let recorder = FlightRecorder::new(bus.clone(), &path);
+    let count = recorder.dump().unwrap();
+    let values = replay(&path, 1);
+    assert_eq!(values.len(), count);
+    // The ring lapped, so only a suffix survived, in order.
+    assert!(values[0] > 0);
+    assert_eq!(values, (values[0]..2000).collect::<Vec<_>>());
+
+    let last = recorder.last(200).dump().unwrap();
+    let tail = replay(&path, 1);
+    assert_eq!(tail.len(), last);
+    assert!(last > 0 && last < count);
+    assert_eq!(tail, values[values.len() - last..]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn panics_trigger_a_dump() {
+    let path = dump_path("panic");
+    let bus = CircularBus::new(&RingConfig);
+    let (dumped, dumps) = std::sync::mpsc::channel();
+    let dumped = std::sync::Mutex::new(dumped);
+    // Other tests of this binary panic too: dump on every panic.
+    FlightRecorder::new(bus.clone(), &path).dump_on_panic(std::time::Duration::ZERO, move |result| {
+        dumped.lock().unwrap().send(result.map_err(|e| e.kind())).unwrap()
+    });
+
+    publish(&bus, 1);
+    publish(&bus, 2);
+    let worker = std::thread::spawn(|| panic!("handler failed"));
+    assert!(worker.join().is_err());
+
+    assert!(dumps.try_iter().any(|result| result == Ok(2)));
+    assert_eq!(replay(&path, 1), [1, 2]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn dumps_use_the_page_length_of_the_bus() {
+    let path = dump_path("pages");
+    let bus = CircularBus::new(&RingConfig);
+    for value in 0..100 {
+        publish(&bus, value);
+    }
+
+    // Above any system page size, so it rounds up to the next power of two.
+    let min_page_len = (1 << 16) + 1;
+    let count = FlightRecorder::new(bus.clone(), &path)
+        .min_page_len(min_page_len)
+        .dump()
+        .unwrap();
+    assert_eq!(count, 100);
+    assert_eq!(std::fs::metadata(&path).unwrap().len() % (1 << 17), 0);
+    assert_eq!(replay(&path, min_page_len), (0..100).collect::<Vec<_>>());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn dumps_step_over_a_panicked_write() {
+    let path = dump_path("panicked-write");
+    let bus = CircularBus::new(&RingConfig);
+    publish(&bus, 1);
+    let failed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        bus.write_raw(SENSOR, READING, 24, |_| panic!("encoding failed"));
+    }));
+    assert!(failed.is_err());
+    publish(&bus, 2);
+
+    assert_eq!(FlightRecorder::new(bus.clone(), &path).dump().unwrap(), 2);
+    assert_eq!(replay(&path, 1), [1, 2]);
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,141 @@
+//! An `Injector` must publish from outside the Messenger with its synthetic
+//! source id, through both the extended and the zero-copy senders, and routes
+//! naming `messenger::Source<id>` must deliver what it sends.
+
+mod common;
+
+use common::Config;
+use rust_messenger::traits::extended::Sender;
+
+const INJECTOR: u16 = 100;
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        App = 1,
+        Ticker = 2,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Command = 1,
+        Tick = 2,
+        Ack = 3,
+    }
+);
+
+impl_u32_message!(Command, MessageId::Command);
+impl_u32_message!(Ack, MessageId::Ack);
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+pub struct Tick {
+    pub count: u64,
+}
+
+impl traits::core::Message for Tick {
+    type Id = MessageId;
+    const ID: MessageId = MessageId::Tick;
+}
+
+impl traits::zero_copy::ZeroCopyMessage for Tick {}
+
+impl Tick {
+    pub fn deserialize_from(buffer: &[u8]) -> &Self {
+        assert!(buffer.len() >= std::mem::size_of::<Self>());
+        let ptr = buffer.as_ptr() as *const Self;
+        assert!(ptr.is_aligned());
+        unsafe { &*ptr }
+    }
+}
+
+pub struct App;
+
+impl App {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        App
+    }
+}
+
+impl traits::core::Handler for App {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::App;
+}
+
+impl traits::core::Handle<Command> for App {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Command, writer: &W) {
+        Self::send(&Ack(message.0), writer);
+    }
+}
+
+/// Zero-copy messages reach `handle` as `&&Tick` and rely on deref
+/// coercion, which needs the handler to have a single `Handle` impl.
+pub struct Ticker;
+
+impl Ticker {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Ticker
+    }
+}
+
+impl traits::core::Handler for Ticker {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Ticker;
+}
+
+impl traits::core::Handle<Tick> for Ticker {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Tick, writer: &W) {
+        Self::send(&Ack(message.count as u32), writer);
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Apps:
+        handlers: [
+            app: App,
+            ticker: Ticker,
+        ]
+        routes: [
+            messenger::Source<INJECTOR>, Command: [ app ],
+            messenger::Source<INJECTOR>, Tick: [ ticker ],
+        ]
+}
+
+#[test]
+fn injected_messages_are_routed_from_the_injector_source() {
+    use rust_messenger::traits::core::Reader;
+
+    let bus = common::ring(1 << 16);
+    let messenger = Messenger::new(bus.clone());
+    let injector = messenger.injector::<INJECTOR>();
+    let handles = messenger.run(&Config);
+
+    let from_another_thread = injector.clone();
+    std::thread::spawn(move || from_another_thread.send(&Command(7)))
+        .join()
+        .unwrap();
+    injector.send_zero_copy::<Tick, _>(|tick| unsafe { tick.write(Tick { count: 9 }) });
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let mut position = 0;
+    let mut injected = Vec::new();
+    let mut acks = Vec::new();
+    while std::time::Instant::now() < deadline && acks.len() < 2 {
+        while let Some((header, buffer)) = bus.read(position) {
+            position += header.slot_len();
+            if header.message_id == u16::from(MessageId::Ack) {
+                acks.push(Ack::deserialize_from(buffer).0);
+            } else {
+                injected.push(header.source);
+            }
+        }
+        std::thread::yield_now();
+    }
+
+    messenger.stop();
+    handles.join();
+
+    assert_eq!(injected, [INJECTOR, INJECTOR]);
+    assert_eq!(acks, [7, 9]);
+}
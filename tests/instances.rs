@@ -0,0 +1,169 @@
+//! Two instances of one handler type must stamp their own source ids, and a
+//! route naming one instance (by its name) must not deliver the other
+//! instance's messages.
+
+use rust_messenger::traits::extended::Sender;
+
+#[derive(Clone)]
+pub struct Config;
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Connector = 1,
+        Strategy = 2,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Quote = 1,
+        Echo = 2,
+    }
+);
+
+pub struct Quote;
+
+impl traits::core::Message for Quote {
+    type Id = MessageId;
+    const ID: MessageId = MessageId::Quote;
+}
+
+impl Quote {
+    pub fn deserialize_from(_buffer: &[u8]) -> Self {
+        Quote
+    }
+}
+
+impl traits::extended::ExtendedMessage for Quote {
+    fn get_size(&self) -> usize {
+        0
+    }
+    fn write_into(&self, _buffer: &mut [u8]) {}
+}
+
+pub struct Echo;
+
+impl traits::core::Message for Echo {
+    type Id = MessageId;
+    const ID: MessageId = MessageId::Echo;
+}
+
+impl Echo {
+    pub fn deserialize_from(_buffer: &[u8]) -> Self {
+        Echo
+    }
+}
+
+impl traits::extended::ExtendedMessage for Echo {
+    fn get_size(&self) -> usize {
+        0
+    }
+    fn write_into(&self, _buffer: &mut [u8]) {}
+}
+
+pub struct Connector;
+
+impl Connector {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Connector
+    }
+}
+
+impl traits::core::Handler for Connector {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Connector;
+
+    fn on_start<W: traits::core::Writer>(&mut self, writer: &W) {
+        Self::send(&Quote, writer);
+    }
+}
+
+impl traits::core::Handle<Echo> for Connector {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Echo, _writer: &W) {}
+}
+
+pub struct Strategy;
+
+impl Strategy {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Strategy
+    }
+}
+
+impl traits::core::Handler for Strategy {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Strategy;
+}
+
+impl traits::core::Handle<Quote> for Strategy {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Quote, writer: &W) {
+        Self::send(&Echo, writer);
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Connectors:
+        handlers: [
+            conn_a @ 10: Connector,
+            conn_b @ { 5 + 6 }: Connector,
+        ]
+        routes: [
+            Strategy, Echo: [ conn_a ],
+        ]
+    Strategies:
+        handlers: [
+            strategy: Strategy,
+        ]
+        routes: [
+            conn_a, Quote: [ strategy ],
+        ]
+}
+
+#[test]
+fn instances_stamp_and_route_by_their_own_ids() {
+    use rust_messenger::traits::core::Reader;
+
+    struct BusConfig;
+    impl rust_messenger::message_bus::atomic_circular_bus::Config for BusConfig {
+        fn get_buffer_size(&self) -> usize {
+            1 << 16
+        }
+    }
+
+    let bus = rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&BusConfig);
+    let messenger = Messenger::new(bus.clone());
+    let handles = messenger.run(&Config);
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let mut position = 0;
+    let mut quote_sources = Vec::new();
+    let mut echoes = 0;
+    while std::time::Instant::now() < deadline && (quote_sources.len() < 2 || echoes < 1) {
+        while let Some((header, _)) = bus.read(position) {
+            position += header.slot_len();
+            if header.message_id == u16::from(MessageId::Quote) {
+                quote_sources.push(header.source);
+            } else if header.message_id == u16::from(MessageId::Echo) {
+                assert_eq!(header.source, u16::from(HandlerId::Strategy));
+                echoes += 1;
+            }
+        }
+        std::thread::yield_now();
+    }
+    // Give a wrongly routed second echo a chance to show up.
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    while let Some((header, _)) = bus.read(position) {
+        position += header.slot_len();
+        if header.message_id == u16::from(MessageId::Echo) {
+            echoes += 1;
+        }
+    }
+
+    messenger.stop();
+    handles.join();
+
+    quote_sources.sort();
+    assert_eq!(quote_sources, [10, 11]);
+    assert_eq!(echoes, 1, "only conn_a's quote is routed to the strategy");
+}
@@ -0,0 +1,169 @@
+//! `JoinHandles` must report each worker's outcome, give the handles back
+//! when `join_timeout` expires, tell whether the workers are finished, and
+//! stop and join the workers when dropped.
+
+mod common;
+
+#[derive(Clone)]
+pub struct Config {
+    stops: std::sync::Arc<std::sync::atomic::AtomicU32>,
+}
+
+impl Config {
+    fn new() -> Config {
+        Config {
+            stops: Default::default(),
+        }
+    }
+
+    /// How many `Idle` handlers ran their `on_stop`.
+    fn stops(&self) -> u32 {
+        self.stops.load(std::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Idle = 1,
+        Sloppy = 2,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Never = 1,
+    }
+);
+
+impl_u32_message!(Never, MessageId::Never);
+
+pub struct Idle {
+    stops: std::sync::Arc<std::sync::atomic::AtomicU32>,
+}
+
+impl Idle {
+    pub fn new<W: traits::core::Writer>(config: &Config, _writer: &W) -> Self {
+        Idle {
+            stops: config.stops.clone(),
+        }
+    }
+}
+
+impl traits::core::Handler for Idle {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Idle;
+
+    fn on_stop(&mut self) {
+        self.stops.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+impl traits::core::Handle<Never> for Idle {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Never, _writer: &W) {}
+}
+
+/// Panics in `on_stop`, which runs outside supervision.
+pub struct Sloppy;
+
+impl Sloppy {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Sloppy
+    }
+}
+
+impl traits::core::Handler for Sloppy {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Sloppy;
+
+    fn on_stop(&mut self) {
+        panic!("flush failed");
+    }
+}
+
+impl traits::core::Handle<Never> for Sloppy {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Never, _writer: &W) {}
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Idlers:
+        handlers: [
+            idle: Idle,
+        ]
+        routes: [
+            Sloppy, Never: [ idle ],
+        ]
+    Sloppies:
+        handlers: [
+            sloppy: Sloppy,
+        ]
+        routes: [
+            Idle, Never: [ sloppy ],
+        ]
+}
+
+fn run() -> (
+    Messenger<rust_messenger::message_bus::atomic_circular_bus::CircularBus>,
+    Config,
+    messenger::JoinHandles,
+) {
+    let messenger = Messenger::new(common::ring(1 << 16));
+    let config = Config::new();
+    let handles = messenger.run(&config);
+    (messenger, config, handles)
+}
+
+#[test]
+fn join_timeout_gives_running_workers_back() {
+    let (messenger, config, handles) = run();
+
+    let handles = handles
+        .join_timeout(std::time::Duration::from_millis(20))
+        .expect_err("the workers never stop by themselves");
+    assert!(!handles.is_finished());
+    assert_eq!(handles.names().collect::<Vec<_>>(), ["Idlers", "Sloppies"]);
+    assert_eq!(config.stops(), 0);
+
+    messenger.stop();
+    let results = handles
+        .join_timeout(std::time::Duration::from_secs(10))
+        .ok()
+        .expect("stopped workers finish");
+    assert!(results["Idlers"].is_ok());
+    assert_eq!(config.stops(), 1);
+}
+
+#[test]
+fn is_finished_once_the_workers_stopped() {
+    let (messenger, config, handles) = run();
+    assert!(!handles.is_finished());
+
+    messenger.stop();
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while !handles.is_finished() {
+        assert!(std::time::Instant::now() < deadline, "workers did not finish");
+        std::thread::yield_now();
+    }
+    assert_eq!(config.stops(), 1);
+    handles.join();
+}
+
+#[test]
+fn dropping_the_handles_stops_and_joins_the_workers() {
+    let (_messenger, config, handles) = run();
+    drop(handles);
+    // Joined: `on_stop` has already run.
+    assert_eq!(config.stops(), 1);
+}
+
+#[test]
+fn join_returns_the_panic_payload_of_a_worker() {
+    let (messenger, _config, handles) = run();
+    messenger.stop();
+    let results = handles.join();
+
+    assert!(results["Idlers"].is_ok());
+    let payload = results["Sloppies"].as_ref().unwrap_err();
+    assert_eq!(payload.downcast_ref::<&str>(), Some(&"flush failed"));
+    assert_eq!(messenger::panic_reason(&**payload), "flush failed");
+}
@@ -0,0 +1,199 @@
+//! Workers declared `start: Committed` on an `ExtendingBus` must resume after
+//! the last offset a handler committed, not replay the whole history.
+
+#![cfg(target_os = "linux")]
+
+use rust_messenger::message_bus::extending_bus::ExtendingBus;
+
+#[derive(Clone)]
+pub struct Config {
+    /// Orders up to this id have been durably mailed; the mailer commits
+    /// after each of them.
+    durable_up_to: u32,
+}
+
+const SHOP: u16 = 60;
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Mailer = 1,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Order = 1,
+    }
+);
+
+pub struct Order(pub u32);
+
+impl traits::core::Message for Order {
+    type Id = MessageId;
+    const ID: MessageId = MessageId::Order;
+}
+
+impl Order {
+    pub fn deserialize_from(buffer: &[u8]) -> Self {
+        Order(u32::from_ne_bytes(buffer[..4].try_into().unwrap()))
+    }
+}
+
+impl traits::extended::ExtendedMessage for Order {
+    fn get_size(&self) -> usize {
+        4
+    }
+    fn write_into(&self, buffer: &mut [u8]) {
+        buffer[..4].copy_from_slice(&self.0.to_ne_bytes());
+    }
+}
+
+pub struct Mailer {
+    durable_up_to: u32,
+    pub mailed: Vec<u32>,
+}
+
+impl Mailer {
+    pub fn new<W: traits::core::Writer>(config: &Config, _writer: &W) -> Self {
+        Mailer {
+            durable_up_to: config.durable_up_to,
+            mailed: Vec::new(),
+        }
+    }
+}
+
+impl traits::core::Handler for Mailer {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Mailer;
+}
+
+impl traits::core::HandleWithContext<Order> for Mailer {
+    fn handle_with_context<W: traits::core::Writer>(
+        &mut self,
+        order: &Order,
+        context: &messenger::MessageContext,
+        _writer: &W,
+    ) {
+        self.mailed.push(order.0);
+        if order.0 <= self.durable_up_to {
+            context.commit().expect("committing the offset failed");
+        }
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Resuming:
+        start: Committed
+        handlers: [
+            mailer: Mailer,
+        ]
+        routes: [
+            messenger::Source<SHOP>, Order: [ mailer ],
+        ]
+    Replaying:
+        handlers: [
+            mailer: Mailer,
+        ]
+        routes: [
+            messenger::Source<SHOP>, Order: [ mailer ],
+        ]
+}
+
+struct BusConfig {
+    path: std::path::PathBuf,
+}
+
+impl rust_messenger::message_bus::extending_bus::Config for BusConfig {
+    fn get_file_path(&self) -> std::path::PathBuf {
+        self.path.clone()
+    }
+    fn get_min_page_len(&self) -> usize {
+        1
+    }
+    fn get_max_pages(&self) -> usize {
+        4
+    }
+}
+
+fn drain(bus: &ExtendingBus, config: &Config) -> (Vec<u32>, Vec<u32>) {
+    let mut resuming = Resuming::new(bus, config);
+    let mut replaying = Replaying::new(bus, config);
+    while resuming.poll_once(bus) | replaying.poll_once(bus) {}
+    (resuming.mailer.mailed, replaying.mailer.mailed)
+}
+
+#[test]
+fn restarted_worker_resumes_after_its_committed_offset() {
+    let cfg = BusConfig {
+        path: std::env::temp_dir().join(format!("rust_messenger_offsets_{}.log", std::process::id())),
+    };
+    let offsets = std::path::PathBuf::from(format!("{}.offsets", cfg.path.display()));
+    let _ = std::fs::remove_file(&cfg.path);
+    let _ = std::fs::remove_file(&offsets);
+
+    {
+        let bus = ExtendingBus::new(&cfg);
+        let messenger = Messenger::new(bus.clone());
+        for order in 1..=5 {
+            messenger.injector::<SHOP>().send(&Order(order));
+        }
+        // Orders 4 and 5 were handled, but the process died before their
+        // mails were durable: they were never committed.
+        let (resuming, _) = drain(&bus, &Config { durable_up_to: 3 });
+        assert_eq!(resuming, [1, 2, 3, 4, 5]);
+    }
+
+    let bus = ExtendingBus::new(&cfg);
+    let messenger = Messenger::new(bus.clone());
+    messenger.injector::<SHOP>().send(&Order(6));
+    let (resuming, replaying) = drain(&bus, &Config { durable_up_to: 6 });
+    assert_eq!(resuming, [4, 5, 6], "resumes after the committed order 3");
+    assert_eq!(replaying, [1, 2, 3, 4, 5, 6], "start: Beginning replays");
+
+    drop((bus, messenger));
+    let bus = ExtendingBus::new(&cfg);
+    let (resuming, _) = drain(&bus, &Config { durable_up_to: 6 });
+    assert!(resuming.is_empty(), "everything was committed");
+
+    std::fs::remove_file(&cfg.path).unwrap();
+    std::fs::remove_file(&offsets).unwrap();
+}
+
+#[test]
+fn recreated_bus_refuses_the_offsets_of_the_old_one() {
+    let cfg = BusConfig {
+        path: std::env::temp_dir().join(format!("rust_messenger_offsets_recreated_{}.log", std::process::id())),
+    };
+    let offsets = std::path::PathBuf::from(format!("{}.offsets", cfg.path.display()));
+    let _ = std::fs::remove_file(&cfg.path);
+    let _ = std::fs::remove_file(&offsets);
+
+    {
+        let bus = ExtendingBus::new(&cfg);
+        let messenger = Messenger::new(bus.clone());
+        for order in 1..=5 {
+            messenger.injector::<SHOP>().send(&Order(order));
+        }
+        let (resuming, _) = drain(&bus, &Config { durable_up_to: 5 });
+        assert_eq!(resuming, [1, 2, 3, 4, 5]);
+    }
+
+    // A new, shorter history at the same path; the sidecar stays behind
+    // and must be deleted before the bus opens.
+    std::fs::remove_file(&cfg.path).unwrap();
+    assert!(offsets.exists());
+    let error = ExtendingBus::try_new(&cfg).err().expect("the old offsets must not apply");
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    std::fs::remove_file(&offsets).unwrap();
+    let bus = ExtendingBus::new(&cfg);
+    let messenger = Messenger::new(bus.clone());
+    for order in 11..=12 {
+        messenger.injector::<SHOP>().send(&Order(order));
+    }
+    let (resuming, _) = drain(&bus, &Config { durable_up_to: 0 });
+    assert_eq!(resuming, [11, 12]);
+
+    std::fs::remove_file(&cfg.path).unwrap();
+    let _ = std::fs::remove_file(&offsets);
+}
@@ -0,0 +1,140 @@
+//! Reopening an `ExtendingBus` replays its history through the same handlers:
+//! they must be able to tell replay from live traffic, and handlers with
+//! `REPLAY_WRITES = Suppress` must not answer replayed requests again.
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use common::Config;
+use rust_messenger::message_bus::extending_bus::ExtendingBus;
+
+const CLIENT: u16 = 70;
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Ponger = 1,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Ping = 1,
+        Pong = 2,
+    }
+);
+
+impl_u32_message!(Ping, MessageId::Ping);
+impl_u32_message!(Pong, MessageId::Pong);
+
+pub struct Ponger {
+    pub replayed: Vec<u32>,
+    pub live: Vec<u32>,
+    pub caught_up: bool,
+}
+
+impl Ponger {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Ponger {
+            replayed: Vec::new(),
+            live: Vec::new(),
+            caught_up: false,
+        }
+    }
+}
+
+impl traits::core::Handler for Ponger {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Ponger;
+    const REPLAY_WRITES: messenger::ReplayWrites = messenger::ReplayWrites::Suppress;
+
+    fn on_replay_complete<W: traits::core::Writer>(&mut self, _writer: &W) {
+        assert!(!self.caught_up, "called once");
+        self.caught_up = true;
+    }
+}
+
+impl traits::core::Handle<Ping> for Ponger {
+    fn handle<W: traits::core::Writer>(&mut self, ping: &Ping, writer: &W) {
+        if self.caught_up {
+            self.live.push(ping.0);
+        } else {
+            self.replayed.push(ping.0);
+        }
+        <Self as traits::extended::Sender>::send(&Pong(ping.0), writer);
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Responder:
+        handlers: [
+            ponger: Ponger,
+        ]
+        routes: [
+            messenger::Source<CLIENT>, Ping: [ ponger ],
+        ]
+}
+
+struct BusConfig {
+    path: std::path::PathBuf,
+}
+
+impl rust_messenger::message_bus::extending_bus::Config for BusConfig {
+    fn get_file_path(&self) -> std::path::PathBuf {
+        self.path.clone()
+    }
+    fn get_min_page_len(&self) -> usize {
+        1
+    }
+    fn get_max_pages(&self) -> usize {
+        4
+    }
+}
+
+fn pongs(bus: &ExtendingBus) -> Vec<u32> {
+    rust_messenger::cursor::Cursor::new()
+        .subscribe(bus, HandlerId::Ponger, Pong::deserialize_from)
+        .map(|pong| pong.unwrap().0)
+        .collect()
+}
+
+#[test]
+fn replayed_requests_are_not_answered_twice() {
+    let cfg = BusConfig {
+        path: std::env::temp_dir().join(format!("rust_messenger_replay_{}.log", std::process::id())),
+    };
+    let _ = std::fs::remove_file(&cfg.path);
+
+    {
+        let bus = ExtendingBus::new(&cfg);
+        let mut responder = Responder::new(&bus, &Config);
+        responder.poll_once(&bus);
+        assert!(!responder.is_replaying(), "a fresh bus has no history");
+        assert!(responder.ponger.caught_up);
+
+        let client = messenger::Injector::<_, CLIENT>::new(bus.clone());
+        client.send(&Ping(1));
+        client.send(&Ping(2));
+        while responder.poll_once(&bus) {}
+        assert_eq!(responder.ponger.live, [1, 2]);
+        assert_eq!(pongs(&bus), [1, 2]);
+    }
+
+    let bus = ExtendingBus::new(&cfg);
+    let mut responder = Responder::new(&bus, &Config);
+    assert!(responder.is_replaying());
+    while responder.poll_once(&bus) {}
+    assert!(!responder.is_replaying());
+    assert_eq!(responder.position(), traits::core::MessageBus::history_end(&bus));
+    assert_eq!(responder.ponger.replayed, [1, 2]);
+    assert_eq!(pongs(&bus), [1, 2], "replayed pings are not answered again");
+
+    messenger::Injector::<_, CLIENT>::new(bus.clone()).send(&Ping(3));
+    while responder.poll_once(&bus) {}
+    assert_eq!(responder.ponger.live, [3]);
+    assert_eq!(pongs(&bus), [1, 2, 3]);
+
+    drop(bus);
+    std::fs::remove_file(&cfg.path).unwrap();
+}
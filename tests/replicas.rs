@@ -0,0 +1,141 @@
+//! A `balanced` route across `replicas: N` copies of a worker must hand each
+//! message to exactly one replica, while broadcast routes still reach all.
+
+mod common;
+
+use common::Config;
+use rust_messenger::traits::extended::Sender;
+
+const JOBS: u32 = 500;
+const REPLICAS: u32 = 4;
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Producer = 1,
+        Cruncher = 2,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Job = 1,
+        Done = 2,
+        Hello = 3,
+    }
+);
+
+impl_u32_message!(Job, MessageId::Job);
+impl_u32_message!(Done, MessageId::Done);
+impl_u32_message!(Hello, MessageId::Hello);
+
+pub struct Producer;
+
+impl Producer {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Producer
+    }
+}
+
+impl traits::core::Handler for Producer {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Producer;
+
+    fn on_start<W: traits::core::Writer>(&mut self, writer: &W) {
+        Self::send(&Hello(0), writer);
+        for job in 0..JOBS {
+            Self::send(&Job(job), writer);
+        }
+    }
+}
+
+impl traits::core::Handle<Done> for Producer {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Done, _writer: &W) {}
+}
+
+pub struct Cruncher;
+
+impl Cruncher {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Cruncher
+    }
+}
+
+impl traits::core::Handler for Cruncher {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Cruncher;
+}
+
+impl traits::core::Handle<Job> for Cruncher {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Job, writer: &W) {
+        Self::send(&Done(message.0), writer);
+    }
+}
+
+impl traits::core::Handle<Hello> for Cruncher {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Hello, writer: &W) {
+        Self::send(&Done(u32::MAX), writer);
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Front:
+        handlers: [
+            producer: Producer,
+        ]
+        routes: [
+            Cruncher, Done: [ producer ],
+        ]
+    Pool:
+        replicas: 4
+        handlers: [
+            cruncher: Cruncher,
+        ]
+        routes: [
+            Producer, Job: balanced [ cruncher ],
+            Producer, Hello: [ cruncher ],
+        ]
+}
+
+#[test]
+fn balanced_route_delivers_each_message_to_one_replica() {
+    use rust_messenger::traits::core::Reader;
+
+    let bus = common::ring(1 << 20);
+    let messenger = Messenger::new(bus.clone())
+        .with_thread_config("Pool", messenger::ThreadConfig::new().name("crunch"))
+        .with_thread_config("Pool.3", messenger::ThreadConfig::new().stack_size(512 * 1024));
+    let handles = messenger.run(&Config);
+
+    let expected = (JOBS + REPLICAS) as usize;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let mut position = 0;
+    let mut done = Vec::new();
+    while std::time::Instant::now() < deadline && done.len() < expected {
+        while let Some((header, buffer)) = bus.read(position) {
+            position += header.slot_len();
+            if header.message_id == u16::from(MessageId::Done) {
+                done.push(Done::deserialize_from(buffer).0);
+            }
+        }
+        std::thread::yield_now();
+    }
+    // A duplicated job would show up as extra Done messages shortly after.
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    while let Some((header, buffer)) = bus.read(position) {
+        position += header.slot_len();
+        if header.message_id == u16::from(MessageId::Done) {
+            done.push(Done::deserialize_from(buffer).0);
+        }
+    }
+
+    messenger.stop();
+    let results = handles.join();
+    let names: Vec<_> = results.keys().map(String::as_str).collect();
+    assert_eq!(names, ["Front", "Pool.0", "Pool.1", "Pool.2", "Pool.3"]);
+
+    done.sort();
+    let mut want: Vec<u32> = (0..JOBS).collect();
+    want.extend(std::iter::repeat_n(u32::MAX, REPLICAS as usize));
+    assert_eq!(done, want, "every job done once, every replica greeted once");
+}
@@ -0,0 +1,143 @@
+//! Supervision must cover `on_start` and the rebuild of a restarted handler,
+//! and a handler that panics on every message must escalate once it exceeds
+//! its `RESTART_LIMIT` instead of restarting forever.
+
+mod common;
+
+use common::Config;
+
+const FEEDER: u16 = 100;
+
+static BUILT: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Flaky = 1,
+        Unstartable = 2,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Value = 1,
+    }
+);
+
+impl_u32_message!(Value, MessageId::Value);
+
+/// Panics on every value.
+pub struct Flaky;
+
+impl Flaky {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        BUILT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        Flaky
+    }
+}
+
+impl traits::core::Handler for Flaky {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Flaky;
+    const ON_PANIC: messenger::Supervision = messenger::Supervision::Restart;
+    const RESTART_LIMIT: messenger::RestartLimit = messenger::RestartLimit {
+        restarts: 3,
+        within: std::time::Duration::from_secs(3600),
+    };
+}
+
+impl traits::core::Handle<Value> for Flaky {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Value, _writer: &W) {
+        panic!("cannot handle {}", message.0);
+    }
+}
+
+/// Panics in every `on_start`.
+pub struct Unstartable;
+
+impl Unstartable {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Unstartable
+    }
+}
+
+impl traits::core::Handler for Unstartable {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Unstartable;
+    const ON_PANIC: messenger::Supervision = messenger::Supervision::Restart;
+
+    fn on_start<W: traits::core::Writer>(&mut self, _writer: &W) {
+        panic!("cannot start");
+    }
+}
+
+impl traits::core::Handle<Value> for Unstartable {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Value, _writer: &W) {}
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Flakes:
+        handlers: [
+            flaky: Flaky,
+        ]
+        routes: [
+            messenger::Source<FEEDER>, Value: [ flaky ],
+        ]
+    Starters:
+        handlers: [
+            unstartable: Unstartable,
+        ]
+        routes: [
+            messenger::Source<FEEDER>, Value: [ unstartable ],
+        ]
+}
+
+#[test]
+fn restarts_past_the_limit_escalate() {
+    let bus = common::ring(1 << 16);
+    let injector = messenger::Injector::<_, FEEDER>::new(bus.clone());
+    for value in 0..10 {
+        injector.send(&Value(value));
+    }
+
+    let mut worker = Flakes::new(&bus, &Config);
+    while !worker.is_stopped() && worker.poll_once(&bus) {}
+
+    assert!(worker.is_stopped());
+    let events = worker.panic_events();
+    let actions: Vec<_> = events.iter().map(|event| event.action).collect();
+    assert_eq!(
+        actions,
+        [
+            messenger::Supervision::Restart,
+            messenger::Supervision::Restart,
+            messenger::Supervision::Restart,
+            messenger::Supervision::Escalate,
+        ]
+    );
+    assert_eq!(events[3].reason, "cannot handle 3");
+    // The first build and three rebuilds.
+    assert_eq!(BUILT.load(std::sync::atomic::Ordering::Relaxed), 4);
+}
+
+#[test]
+fn panics_in_on_start_and_in_the_rebuild_are_supervised() {
+    let bus = common::ring(1 << 16);
+    let mut worker = Starters::new(&bus, &Config);
+    worker.poll_once(&bus);
+
+    assert!(worker.is_stopped());
+    let events = worker.panic_events();
+    assert_eq!(events.len(), 2);
+    // The panic in `on_start` restarts the handler, whose `on_start` panics
+    // again, which escalates.
+    for (event, action) in events.iter().zip([
+        messenger::Supervision::Restart,
+        messenger::Supervision::Escalate,
+    ]) {
+        assert_eq!(event.handler, "unstartable");
+        assert_eq!(event.message, None);
+        assert_eq!(event.reason, "cannot start");
+        assert_eq!(event.action, action);
+    }
+}
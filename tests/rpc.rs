@@ -0,0 +1,173 @@
+//! `rpc::Call` and `rpc::Reply` route through `Messenger!` like any other
+//! message: a handler calls another one and gets the answer to its own call.
+
+use rust_messenger::rpc;
+
+#[derive(Clone)]
+pub struct Config;
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Caller = 1,
+        Doubler = 2,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Number = 1,
+        NumberCall = 2,
+        NumberReply = 3,
+        NumberTimeout = 4,
+    }
+);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Number(pub u64);
+
+impl traits::core::Message for Number {
+    type Id = MessageId;
+    const ID: MessageId = MessageId::Number;
+}
+
+impl rpc::CallMessage for Number {
+    const CALL_ID: MessageId = MessageId::NumberCall;
+}
+
+impl rpc::ReplyMessage for Number {
+    const REPLY_ID: MessageId = MessageId::NumberReply;
+    const TIMEOUT_ID: MessageId = MessageId::NumberTimeout;
+}
+
+impl Number {
+    pub fn deserialize_from(buffer: &[u8]) -> Self {
+        Number(u64::from_ne_bytes(buffer[..8].try_into().unwrap()))
+    }
+}
+
+impl traits::extended::ExtendedMessage for Number {
+    fn get_size(&self) -> usize {
+        8
+    }
+    fn write_into(&self, buffer: &mut [u8]) {
+        buffer[..8].copy_from_slice(&self.0.to_ne_bytes());
+    }
+}
+
+impl traits::extended::DeserializeFrom for Number {
+    fn deserialize_from(buffer: &[u8]) -> Self {
+        Number::deserialize_from(buffer)
+    }
+}
+
+pub struct Caller {
+    doubler: rpc::Client<Number>,
+    pub answers: std::sync::Arc<std::sync::Mutex<Vec<Result<Number, rpc::RpcError>>>>,
+    pub pending: Vec<rpc::ReplyFuture<Number>>,
+}
+
+impl Caller {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Caller {
+            doubler: rpc::Client::new(std::time::Duration::from_secs(60)),
+            answers: Default::default(),
+            pending: Vec::new(),
+        }
+    }
+}
+
+impl traits::core::Handler for Caller {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Caller;
+
+    fn on_start<W: traits::core::Writer>(&mut self, writer: &W) {
+        let answers = self.answers.clone();
+        self.doubler.request_with::<Self, _, _, _>(&Number(21), writer, move |answer| {
+            answers.lock().unwrap().push(answer)
+        });
+        let future = self.doubler.request::<Self, _, _>(&Number(5), writer);
+        self.pending.push(future);
+    }
+
+    fn on_loop<W: traits::core::Writer>(&mut self, writer: &W) {
+        self.doubler.expire::<Self, _>(writer);
+    }
+}
+
+impl rpc::Caller<Number> for Caller {
+    fn client(&self) -> &rpc::Client<Number> {
+        &self.doubler
+    }
+}
+
+pub struct Doubler {
+    pub bare: Vec<Number>,
+}
+
+impl Doubler {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Doubler { bare: Vec::new() }
+    }
+}
+
+impl traits::core::Handler for Doubler {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Doubler;
+}
+
+impl traits::core::Handle<rpc::Call<Number>> for Doubler {
+    fn handle<W: traits::core::Writer>(&mut self, call: &rpc::Call<Number>, writer: &W) {
+        rpc::reply::<Self, _, _, _>(call, &Number(call.message.0 * 2), writer);
+    }
+}
+
+impl traits::core::Handle<Number> for Doubler {
+    fn handle<W: traits::core::Writer>(&mut self, number: &Number, _writer: &W) {
+        self.bare.push(number.clone());
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Front:
+        handlers: [
+            caller: Caller,
+        ]
+        routes: [
+            Doubler, rpc::Reply<Number>: [ caller ],
+        ]
+    Back:
+        handlers: [
+            doubler: Doubler,
+        ]
+        routes: [
+            Caller, rpc::Call<Number>: [ doubler ],
+            Caller, Number: [ doubler ],
+        ]
+}
+
+#[test]
+fn calls_are_answered_through_the_bus() {
+    struct BusConfig;
+    impl rust_messenger::message_bus::atomic_circular_bus::Config for BusConfig {
+        fn get_buffer_size(&self) -> usize {
+            1 << 14
+        }
+    }
+    let bus = rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&BusConfig);
+    let messenger = Messenger::new(bus.clone());
+    // A bare message of the calling source is not taken for a call.
+    {
+        use rust_messenger::traits::extended::Sender;
+        Caller::send(&Number(7), &bus);
+    }
+    let mut simulation = messenger.simulation(&Config, 1);
+    simulation.run_until_quiescent();
+
+    let front = simulation.worker::<Front>("Front").unwrap();
+    assert_eq!(*front.caller.answers.lock().unwrap(), [Ok(Number(42))]);
+    assert_eq!(front.caller.pending[0].try_take(), Some(Ok(Number(10))));
+    assert_eq!(front.caller.doubler.outstanding(), 0);
+    let back = simulation.worker::<Back>("Back").unwrap();
+    assert_eq!(back.doubler.bare, [Number(7)]);
+}
@@ -0,0 +1,185 @@
+//! A `sharded` route must deliver every message to exactly one replica, the
+//! same replica for every message with the same partition key, in bus order.
+
+use rust_messenger::traits::extended::Sender;
+
+#[derive(Clone)]
+pub struct Config;
+
+const ACCOUNTS: u32 = 16;
+const ORDERS_PER_ACCOUNT: u32 = 40;
+
+/// Hands out a distinct token per handler value, so acks reveal which
+/// replica handled an order.
+static NEXT_BOOK: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Gateway = 1,
+        OrderBook = 2,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Order = 1,
+        Ack = 2,
+    }
+);
+
+/// `(account, sequence)` for orders; `(book, account, sequence)` for acks.
+macro_rules! impl_words_message {
+    ($type:ident, $id:expr, $words:literal) => {
+        pub struct $type(pub [u32; $words]);
+
+        impl traits::core::Message for $type {
+            type Id = MessageId;
+            const ID: MessageId = $id;
+        }
+
+        impl $type {
+            pub fn deserialize_from(buffer: &[u8]) -> Self {
+                let mut words = [0; $words];
+                for (i, word) in words.iter_mut().enumerate() {
+                    *word = u32::from_ne_bytes(buffer[i * 4..i * 4 + 4].try_into().unwrap());
+                }
+                $type(words)
+            }
+        }
+
+        impl traits::extended::ExtendedMessage for $type {
+            fn get_size(&self) -> usize {
+                $words * 4
+            }
+            fn write_into(&self, buffer: &mut [u8]) {
+                for (i, word) in self.0.iter().enumerate() {
+                    buffer[i * 4..i * 4 + 4].copy_from_slice(&word.to_ne_bytes());
+                }
+            }
+        }
+    };
+}
+
+impl_words_message!(Order, MessageId::Order, 2);
+impl_words_message!(Ack, MessageId::Ack, 3);
+
+impl traits::core::PartitionKey for Order {
+    type Key = u32;
+    fn partition_key(&self) -> u32 {
+        self.0[0]
+    }
+}
+
+pub struct Gateway;
+
+impl Gateway {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Gateway
+    }
+}
+
+impl traits::core::Handler for Gateway {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Gateway;
+
+    fn on_start<W: traits::core::Writer>(&mut self, writer: &W) {
+        for sequence in 0..ORDERS_PER_ACCOUNT {
+            for account in 0..ACCOUNTS {
+                Self::send(&Order([account, sequence]), writer);
+            }
+        }
+    }
+}
+
+impl traits::core::Handle<Ack> for Gateway {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Ack, _writer: &W) {}
+}
+
+pub struct OrderBook {
+    book: u32,
+}
+
+impl OrderBook {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        OrderBook {
+            book: NEXT_BOOK.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+        }
+    }
+}
+
+impl traits::core::Handler for OrderBook {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::OrderBook;
+}
+
+impl traits::core::Handle<Order> for OrderBook {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Order, writer: &W) {
+        let [account, sequence] = message.0;
+        Self::send(&Ack([self.book, account, sequence]), writer);
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Front:
+        handlers: [
+            gateway: Gateway,
+        ]
+        routes: [
+            OrderBook, Ack: [ gateway ],
+        ]
+    Books:
+        replicas: 3
+        handlers: [
+            book: OrderBook,
+        ]
+        routes: [
+            Gateway, Order: sharded [ book ],
+        ]
+}
+
+#[test]
+fn sharded_route_keeps_each_key_on_one_replica_in_order() {
+    use rust_messenger::traits::core::Reader;
+
+    struct BusConfig;
+    impl rust_messenger::message_bus::atomic_circular_bus::Config for BusConfig {
+        fn get_buffer_size(&self) -> usize {
+            1 << 20
+        }
+    }
+
+    let bus = rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&BusConfig);
+    let messenger = Messenger::new(bus.clone());
+    let handles = messenger.run(&Config);
+
+    let expected = (ACCOUNTS * ORDERS_PER_ACCOUNT) as usize;
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let mut position = 0;
+    let mut acks = Vec::new();
+    while std::time::Instant::now() < deadline && acks.len() < expected {
+        while let Some((header, buffer)) = bus.read(position) {
+            position += header.slot_len();
+            if header.message_id == u16::from(MessageId::Ack) {
+                acks.push(Ack::deserialize_from(buffer).0);
+            }
+        }
+        std::thread::yield_now();
+    }
+
+    messenger.stop();
+    handles.join();
+
+    assert_eq!(acks.len(), expected, "every order acked exactly once");
+    let mut books = std::collections::HashSet::new();
+    for account in 0..ACCOUNTS {
+        let per_account: Vec<_> = acks.iter().filter(|ack| ack[1] == account).collect();
+        let book = per_account[0][0];
+        books.insert(book);
+        for (sequence, ack) in per_account.iter().enumerate() {
+            assert_eq!(ack[0], book, "account {account} moved between replicas");
+            assert_eq!(ack[2], sequence as u32, "account {account} out of order");
+        }
+    }
+    assert!(books.len() > 1, "all accounts landed on a single replica");
+}
@@ -0,0 +1,255 @@
+//! `Messenger::simulation` must run every worker on the test thread in an
+//! interleaving fixed by the seed, handle everything before reporting
+//! quiescence, and move time only through its virtual clock.
+
+mod common;
+
+use rust_messenger::clock::Clock;
+use rust_messenger::traits::extended::Sender;
+
+#[derive(Clone)]
+pub struct Config {
+    clock: std::sync::Arc<dyn Clock>,
+    crunchers: std::sync::Arc<std::sync::atomic::AtomicU32>,
+}
+
+const JOBS: u32 = 20;
+const HEARTBEAT: std::time::Duration = std::time::Duration::from_millis(100);
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Producer = 1,
+        Cruncher = 2,
+        Sink = 3,
+        Heartbeat = 4,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Job = 1,
+        Done = 2,
+        Beat = 3,
+    }
+);
+
+impl_u32_message!(Job, MessageId::Job);
+impl_u32_message!(Done, MessageId::Done);
+impl_u32_message!(Beat, MessageId::Beat);
+
+pub struct Producer;
+
+impl Producer {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Producer
+    }
+}
+
+impl traits::core::Handler for Producer {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Producer;
+
+    fn on_start<W: traits::core::Writer>(&mut self, writer: &W) {
+        for job in 0..JOBS {
+            Self::send(&Job(job), writer);
+        }
+    }
+}
+
+impl traits::core::Handle<Done> for Producer {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Done, _writer: &W) {}
+}
+
+/// Reports `job + 1000 * cruncher`, revealing which replica took the job.
+pub struct Cruncher {
+    cruncher: u32,
+}
+
+impl Cruncher {
+    pub fn new<W: traits::core::Writer>(config: &Config, _writer: &W) -> Self {
+        Cruncher {
+            cruncher: config
+                .crunchers
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+        }
+    }
+}
+
+impl traits::core::Handler for Cruncher {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Cruncher;
+}
+
+impl traits::core::Handle<Job> for Cruncher {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Job, writer: &W) {
+        Self::send(&Done(message.0 + 1000 * self.cruncher), writer);
+    }
+}
+
+pub struct Sink {
+    pub done: Vec<u32>,
+    pub beats: Vec<u32>,
+}
+
+impl Sink {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Sink {
+            done: Vec::new(),
+            beats: Vec::new(),
+        }
+    }
+}
+
+impl traits::core::Handler for Sink {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Sink;
+}
+
+impl traits::core::Handle<Done> for Sink {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Done, _writer: &W) {
+        self.done.push(message.0);
+    }
+}
+
+impl traits::core::Handle<Beat> for Sink {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Beat, _writer: &W) {
+        self.beats.push(message.0);
+    }
+}
+
+/// Sends a numbered beat every `HEARTBEAT` of clock time, from `on_loop`.
+pub struct Heartbeat {
+    clock: std::sync::Arc<dyn Clock>,
+    next: std::time::Instant,
+    count: u32,
+}
+
+impl Heartbeat {
+    pub fn new<W: traits::core::Writer>(config: &Config, _writer: &W) -> Self {
+        Heartbeat {
+            clock: config.clock.clone(),
+            next: config.clock.now() + HEARTBEAT,
+            count: 0,
+        }
+    }
+}
+
+impl traits::core::Handler for Heartbeat {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Heartbeat;
+
+    fn on_loop<W: traits::core::Writer>(&mut self, writer: &W) {
+        if self.clock.now() >= self.next {
+            self.next += HEARTBEAT;
+            self.count += 1;
+            Self::send(&Beat(self.count), writer);
+        }
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Front:
+        handlers: [
+            producer: Producer,
+            heartbeat: Heartbeat,
+        ]
+        routes: [
+            Cruncher, Done: [ producer ],
+        ]
+    Pool:
+        replicas: 3
+        handlers: [
+            cruncher: Cruncher,
+        ]
+        routes: [
+            Producer, Job: balanced [ cruncher ],
+        ]
+    Collect:
+        handlers: [
+            sink: Sink,
+        ]
+        routes: [
+            Cruncher, Done: [ sink ],
+            Heartbeat, Beat: [ sink ],
+        ]
+}
+
+fn messenger(
+    clock: &rust_messenger::clock::VirtualClock,
+) -> (
+    Messenger<rust_messenger::message_bus::atomic_circular_bus::CircularBus>,
+    Config,
+) {
+    let bus = common::ring(1 << 16);
+    let config = Config {
+        clock: std::sync::Arc::new(clock.clone()),
+        crunchers: Default::default(),
+    };
+    (Messenger::new(bus), config)
+}
+
+/// `(source, message_id, value)` of every message on the bus, in bus order.
+fn bus_log<R: traits::core::Reader>(bus: &R) -> Vec<(u16, u16, u32)> {
+    let mut log = Vec::new();
+    let mut position = 0;
+    while let Some((header, buffer)) = bus.read(position) {
+        position += header.slot_len();
+        log.push((header.source, header.message_id, Job::deserialize_from(buffer).0));
+    }
+    log
+}
+
+fn simulate(seed: u64) -> Vec<(u16, u16, u32)> {
+    let clock = rust_messenger::clock::VirtualClock::new();
+    let (messenger, config) = messenger(&clock);
+    let mut simulation = messenger.simulation(&config, seed);
+    simulation.run_until_quiescent();
+    bus_log(simulation.message_bus())
+}
+
+#[test]
+fn runs_until_every_message_is_handled() {
+    let clock = rust_messenger::clock::VirtualClock::new();
+    let (messenger, config) = messenger(&clock);
+    let mut simulation = messenger.simulation(&config, 1);
+    assert_eq!(
+        simulation.names().collect::<Vec<_>>(),
+        ["Front", "Pool.0", "Pool.1", "Pool.2", "Collect"]
+    );
+
+    simulation.run_until_quiescent();
+
+    let sink = &simulation.worker::<Collect>("Collect").unwrap().sink;
+    let mut done: Vec<u32> = sink.done.iter().map(|done| done % 1000).collect();
+    done.sort();
+    assert_eq!(done, (0..JOBS).collect::<Vec<_>>());
+    assert!(messenger.panic_events().is_empty());
+    assert_eq!(clock.elapsed(), std::time::Duration::ZERO, "no time passes");
+}
+
+#[test]
+fn same_seed_replays_the_same_interleaving() {
+    let first = simulate(7);
+    assert_eq!(first, simulate(7));
+    assert!(
+        (0..8).any(|seed| simulate(seed) != first),
+        "seeds vary the interleaving"
+    );
+}
+
+#[test]
+fn run_for_drives_on_loop_with_the_virtual_clock() {
+    let clock = rust_messenger::clock::VirtualClock::new();
+    let (messenger, config) = messenger(&clock);
+    let mut simulation = messenger
+        .simulation(&config, 3)
+        .with_clock(clock.clone(), std::time::Duration::from_millis(10));
+
+    simulation.run_for(std::time::Duration::from_millis(1000));
+
+    assert_eq!(clock.elapsed(), std::time::Duration::from_millis(1000));
+    let sink = &simulation.worker::<Collect>("Collect").unwrap().sink;
+    assert_eq!(sink.beats, (1..=10).collect::<Vec<_>>());
+    assert_eq!(sink.done.len(), JOBS as usize);
+}
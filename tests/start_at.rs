@@ -0,0 +1,136 @@
+//! Workers joining a `CircularBus` that has already wrapped must start at
+//! their `start:` position instead of panicking at position 0: `Tail` sees
+//! only new messages, `Oldest` the intact suffix of the ring.
+
+#[derive(Clone)]
+pub struct Config;
+
+const FEED: u16 = 50;
+const BUFFER_SIZE: usize = 1 << 14;
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Recorder = 1,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Tick = 1,
+    }
+);
+
+pub struct Tick(pub u32);
+
+impl traits::core::Message for Tick {
+    type Id = MessageId;
+    const ID: MessageId = MessageId::Tick;
+}
+
+impl Tick {
+    pub fn deserialize_from(buffer: &[u8]) -> Self {
+        Tick(u32::from_ne_bytes(buffer[..4].try_into().unwrap()))
+    }
+}
+
+impl traits::extended::ExtendedMessage for Tick {
+    fn get_size(&self) -> usize {
+        4
+    }
+    fn write_into(&self, buffer: &mut [u8]) {
+        buffer[..4].copy_from_slice(&self.0.to_ne_bytes());
+    }
+}
+
+pub struct Recorder {
+    pub ticks: Vec<u32>,
+}
+
+impl Recorder {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Recorder { ticks: Vec::new() }
+    }
+}
+
+impl traits::core::Handler for Recorder {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Recorder;
+}
+
+impl traits::core::Handle<Tick> for Recorder {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Tick, _writer: &W) {
+        self.ticks.push(message.0);
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Live:
+        start: Tail
+        handlers: [
+            recorder: Recorder,
+        ]
+        routes: [
+            messenger::Source<FEED>, Tick: [ recorder ],
+        ]
+    Catchup:
+        replicas: 2
+        start: Oldest
+        handlers: [
+            recorder: Recorder,
+        ]
+        routes: [
+            messenger::Source<FEED>, Tick: balanced [ recorder ],
+        ]
+}
+
+fn wrapped_bus() -> (
+    rust_messenger::message_bus::atomic_circular_bus::CircularBus,
+    Messenger<rust_messenger::message_bus::atomic_circular_bus::CircularBus>,
+) {
+    struct BusConfig;
+    impl rust_messenger::message_bus::atomic_circular_bus::Config for BusConfig {
+        fn get_buffer_size(&self) -> usize {
+            BUFFER_SIZE
+        }
+    }
+    let bus = rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&BusConfig);
+    let messenger = Messenger::new(bus.clone());
+    // 24 byte slots: 1000 ticks wrap the 8 KiB ring about three times.
+    let feed = messenger.injector::<FEED>();
+    for tick in 0..1000 {
+        feed.send(&Tick(tick));
+    }
+    (bus, messenger)
+}
+
+#[test]
+fn late_workers_start_at_their_start_position() {
+    assert_eq!(Live::START, messenger::StartAt::Tail);
+    assert_eq!(Catchup::START, messenger::StartAt::Oldest);
+
+    let (bus, messenger) = wrapped_bus();
+    let mut live = Live::new(&bus, &Config);
+    let mut catchup = Catchup::new(&bus, &Config);
+    // The oldest slot is the next one overwritten: drain before sending.
+    while catchup.poll_once(&bus) {}
+    messenger.injector::<FEED>().send(&Tick(1000));
+    while live.poll_once(&bus) | catchup.poll_once(&bus) {}
+
+    assert_eq!(live.recorder.ticks, [1000]);
+    let caught_up = &catchup.recorder.ticks;
+    let first = caught_up[0];
+    assert!(first > 0, "overwritten ticks are skipped");
+    assert_eq!(*caught_up, (first..=1000).collect::<Vec<_>>());
+}
+
+#[test]
+fn running_on_a_wrapped_bus_does_not_panic() {
+    let (_bus, messenger) = wrapped_bus();
+    let handles = messenger.run(&Config);
+    std::thread::sleep(std::time::Duration::from_millis(100));
+
+    messenger.stop();
+    let results = handles.join();
+    assert!(results.values().all(Result::is_ok), "no worker fell behind");
+}
@@ -0,0 +1,154 @@
+//! Workers built with `new` must be drivable by hand: `poll_once` routes at
+//! most one message per call, so a ping-pong between two workers can be
+//! stepped message by message on the test thread.
+
+mod common;
+
+use rust_messenger::traits::extended::Sender;
+
+#[derive(Clone)]
+pub struct Config {
+    rounds: u32,
+}
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Pinger = 1,
+        Ponger = 2,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Ping = 1,
+        Pong = 2,
+    }
+);
+
+impl_u32_message!(Ping, MessageId::Ping);
+impl_u32_message!(Pong, MessageId::Pong);
+
+pub struct Pinger {
+    rounds: u32,
+    pub pongs: Vec<u32>,
+    pub stopped: bool,
+}
+
+impl Pinger {
+    pub fn new<W: traits::core::Writer>(config: &Config, _writer: &W) -> Self {
+        Pinger {
+            rounds: config.rounds,
+            pongs: Vec::new(),
+            stopped: false,
+        }
+    }
+}
+
+impl traits::core::Handler for Pinger {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Pinger;
+
+    fn on_start<W: traits::core::Writer>(&mut self, writer: &W) {
+        Self::send(&Ping(0), writer);
+    }
+
+    fn on_stop(&mut self) {
+        self.stopped = true;
+    }
+}
+
+impl traits::core::Handle<Pong> for Pinger {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Pong, writer: &W) {
+        self.pongs.push(message.0);
+        if message.0 + 1 < self.rounds {
+            Self::send(&Ping(message.0 + 1), writer);
+        }
+    }
+}
+
+pub struct Ponger {
+    pub loops: u32,
+}
+
+impl Ponger {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Ponger { loops: 0 }
+    }
+}
+
+impl traits::core::Handler for Ponger {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Ponger;
+
+    fn on_loop<W: traits::core::Writer>(&mut self, _writer: &W) {
+        self.loops += 1;
+    }
+}
+
+impl traits::core::Handle<Ping> for Ponger {
+    fn handle<W: traits::core::Writer>(&mut self, message: &Ping, writer: &W) {
+        Self::send(&Pong(message.0), writer);
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Front:
+        handlers: [
+            pinger: Pinger,
+        ]
+        routes: [
+            Ponger, Pong: [ pinger ],
+        ]
+    Back:
+        handlers: [
+            ponger: Ponger,
+        ]
+        routes: [
+            Pinger, Ping: [ ponger ],
+        ]
+}
+
+fn bus() -> rust_messenger::message_bus::atomic_circular_bus::CircularBus {
+    common::ring(1 << 16)
+}
+
+#[test]
+fn workers_can_be_stepped_message_by_message() {
+    let bus = bus();
+    let config = Config { rounds: 3 };
+    let mut front = Front::new(&bus, &config);
+    let mut back = Back::new(&bus, &config);
+
+    // Nothing is sent before the first poll runs `on_start`.
+    assert!(!back.has_pending(&bus));
+    assert!(front.poll_once(&bus), "reads its own Ping(0)");
+    assert!(back.has_pending(&bus));
+
+    assert!(back.poll_once(&bus), "Ping(0) -> Pong(0)");
+    assert_eq!(back.ponger.loops, 1);
+    assert!(front.pinger.pongs.is_empty());
+    assert!(front.poll_once(&bus), "Pong(0) -> Ping(1)");
+    assert_eq!(front.pinger.pongs, [0]);
+
+    // Drive both until neither has anything left to read.
+    while front.has_pending(&bus) || back.has_pending(&bus) {
+        front.poll_once(&bus);
+        back.poll_once(&bus);
+    }
+    assert_eq!(front.pinger.pongs, [0, 1, 2]);
+    assert!(!back.poll_once(&bus));
+    assert_eq!(front.position(), back.position());
+}
+
+#[test]
+fn run_on_current_thread_returns_once_stopped() {
+    let bus = bus();
+    let mut front = Front::new(&bus, &Config { rounds: 1 });
+    front.stop();
+    front.run_on_current_thread(&bus);
+
+    assert!(front.is_stopped());
+    assert!(front.pinger.stopped, "on_stop ran");
+    assert!(front.panic_events().is_empty());
+}
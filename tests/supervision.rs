@@ -0,0 +1,221 @@
+//! A panicking handler must be rebuilt or skipped past according to its
+//! `ON_PANIC` policy, without taking its worker thread down, and every caught
+//! panic must be reported as a `PanicEvent`.
+
+use rust_messenger::traits::extended::Sender;
+
+#[derive(Clone)]
+pub struct Config;
+
+const VALUES: u32 = 10;
+
+static ON_PANIC_CALLS: std::sync::atomic::AtomicU32 = std::sync::atomic::AtomicU32::new(0);
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Feeder = 1,
+        Restarter = 2,
+        Skipper = 3,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Value = 1,
+        Seen = 2,
+    }
+);
+
+/// `[value]` for values; `[handler, value, count]` for seen reports.
+macro_rules! impl_words_message {
+    ($type:ident, $id:expr, $words:literal) => {
+        pub struct $type(pub [u32; $words]);
+
+        impl traits::core::Message for $type {
+            type Id = MessageId;
+            const ID: MessageId = $id;
+        }
+
+        impl $type {
+            pub fn deserialize_from(buffer: &[u8]) -> Self {
+                let mut words = [0; $words];
+                for (i, word) in words.iter_mut().enumerate() {
+                    *word = u32::from_ne_bytes(buffer[i * 4..i * 4 + 4].try_into().unwrap());
+                }
+                $type(words)
+            }
+        }
+
+        impl traits::extended::ExtendedMessage for $type {
+            fn get_size(&self) -> usize {
+                $words * 4
+            }
+            fn write_into(&self, buffer: &mut [u8]) {
+                for (i, word) in self.0.iter().enumerate() {
+                    buffer[i * 4..i * 4 + 4].copy_from_slice(&word.to_ne_bytes());
+                }
+            }
+        }
+    };
+}
+
+impl_words_message!(Value, MessageId::Value, 1);
+impl_words_message!(Seen, MessageId::Seen, 3);
+
+pub struct Feeder;
+
+impl Feeder {
+    pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+        Feeder
+    }
+}
+
+impl traits::core::Handler for Feeder {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Feeder;
+
+    fn on_start<W: traits::core::Writer>(&mut self, writer: &W) {
+        for value in 0..VALUES {
+            Self::send(&Value([value]), writer);
+        }
+    }
+}
+
+impl traits::core::Handle<Seen> for Feeder {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Seen, _writer: &W) {}
+}
+
+/// Counts the values it handled; panics on `$panic_on`.
+macro_rules! impl_counter {
+    ($type:ident, $panic_on:literal, $policy:ident) => {
+        pub struct $type {
+            count: u32,
+        }
+
+        impl $type {
+            pub fn new<W: traits::core::Writer>(_config: &Config, _writer: &W) -> Self {
+                $type { count: 0 }
+            }
+        }
+
+        impl traits::core::Handler for $type {
+            type Id = HandlerId;
+            const ID: HandlerId = HandlerId::$type;
+            const ON_PANIC: messenger::Supervision = messenger::Supervision::$policy;
+
+            fn on_panic(&mut self, event: &messenger::PanicEvent) {
+                assert_eq!(event.action, messenger::Supervision::$policy);
+                ON_PANIC_CALLS.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+        }
+
+        impl traits::core::Handle<Value> for $type {
+            fn handle<W: traits::core::Writer>(&mut self, message: &Value, writer: &W) {
+                let [value] = message.0;
+                if value == $panic_on {
+                    panic!("cannot handle {value}");
+                }
+                self.count += 1;
+                Self::send(&Seen([u16::from(Self::ID) as u32, value, self.count]), writer);
+            }
+        }
+    };
+}
+
+impl_counter!(Restarter, 3, Restart);
+impl_counter!(Skipper, 5, Skip);
+
+rust_messenger::Messenger! {
+    Config,
+    Front:
+        handlers: [
+            feeder: Feeder,
+        ]
+        routes: [
+            Restarter, Seen: [ feeder ],
+            Skipper, Seen: [ feeder ],
+        ]
+    Back:
+        handlers: [
+            restarter: Restarter,
+            skipper: Skipper,
+        ]
+        routes: [
+            Feeder, Value: [ restarter, skipper ],
+        ]
+}
+
+#[test]
+fn panicking_handlers_are_restarted_or_skipped() {
+    use rust_messenger::traits::core::Reader;
+
+    struct BusConfig;
+    impl rust_messenger::message_bus::atomic_circular_bus::Config for BusConfig {
+        fn get_buffer_size(&self) -> usize {
+            1 << 16
+        }
+    }
+
+    let bus = rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&BusConfig);
+    let messenger = Messenger::new(bus.clone());
+    let handles = messenger.run(&Config);
+
+    let expected = 2 * (VALUES as usize - 1);
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    let mut position = 0;
+    let mut value_positions = Vec::new();
+    let mut seen = Vec::new();
+    while std::time::Instant::now() < deadline && seen.len() < expected {
+        while let Some((header, buffer)) = bus.read(position) {
+            if header.message_id == u16::from(MessageId::Value) {
+                value_positions.push(position);
+            } else if header.message_id == u16::from(MessageId::Seen) {
+                seen.push(Seen::deserialize_from(buffer).0);
+            }
+            position += header.slot_len();
+        }
+        std::thread::yield_now();
+    }
+
+    messenger.stop();
+    handles.join();
+
+    let counts = |handler: HandlerId| -> Vec<(u32, u32)> {
+        seen.iter()
+            .filter(|s| s[0] == u16::from(handler) as u32)
+            .map(|s| (s[1], s[2]))
+            .collect()
+    };
+    // The rebuilt restarter starts counting from scratch after value 3.
+    let restarted: Vec<_> = [(0, 1), (1, 2), (2, 3)]
+        .into_iter()
+        .chain((4..VALUES).map(|v| (v, v - 3)))
+        .collect();
+    assert_eq!(counts(HandlerId::Restarter), restarted);
+    // The skipper keeps its state across the panic at value 5.
+    let skipped: Vec<_> = (0..5).chain(6..VALUES).zip(1..).collect();
+    assert_eq!(counts(HandlerId::Skipper), skipped);
+
+    let events = messenger.panic_events();
+    assert_eq!(ON_PANIC_CALLS.load(std::sync::atomic::Ordering::Relaxed), 2);
+    assert_eq!(events.len(), 2);
+    for (event, handler, value, action) in [
+        (&events[0], "restarter", 3, messenger::Supervision::Restart),
+        (&events[1], "skipper", 5, messenger::Supervision::Skip),
+    ] {
+        assert_eq!(event.worker, "Back");
+        assert_eq!(event.replica, 0);
+        assert_eq!(event.handler, handler);
+        assert_eq!(event.reason, format!("cannot handle {value}"));
+        assert_eq!(event.action, action);
+        assert_eq!(
+            event.message,
+            Some(messenger::PanickedMessage {
+                source: u16::from(HandlerId::Feeder),
+                message_id: u16::from(MessageId::Value),
+                position: value_positions[value as usize],
+            })
+        );
+    }
+    assert!(messenger.panic_events().is_empty(), "events are drained");
+}
@@ -0,0 +1,112 @@
+//! Worker threads are named after their worker (`Pool.N` for replicas), or
+//! after the `ThreadConfig` name given for the worker or replica.
+
+use rust_messenger::messenger::ThreadConfig;
+
+mod common;
+
+#[derive(Clone, Default)]
+pub struct Config {
+    names: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Namer = 1,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Never = 1,
+    }
+);
+
+impl_u32_message!(Never, MessageId::Never);
+
+/// Records the name of the thread it starts on.
+pub struct Namer {
+    names: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+}
+
+impl Namer {
+    pub fn new<W: traits::core::Writer>(config: &Config, _writer: &W) -> Self {
+        Namer {
+            names: config.names.clone(),
+        }
+    }
+}
+
+impl traits::core::Handler for Namer {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Namer;
+
+    fn on_start<W: traits::core::Writer>(&mut self, _writer: &W) {
+        let name = std::thread::current().name().unwrap_or("<unnamed>").to_string();
+        self.names.lock().unwrap().push(name);
+    }
+}
+
+impl traits::core::Handle<Never> for Namer {
+    fn handle<W: traits::core::Writer>(&mut self, _message: &Never, _writer: &W) {}
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Plain:
+        handlers: [
+            namer: Namer,
+        ]
+        routes: [
+            Namer, Never: [ namer ],
+        ]
+    Solo:
+        handlers: [
+            namer: Namer,
+        ]
+        routes: [
+            Namer, Never: [ namer ],
+        ]
+    Pool:
+        replicas: 3
+        handlers: [
+            namer: Namer,
+        ]
+        routes: [
+            Namer, Never: [ namer ],
+        ]
+}
+
+#[test]
+fn worker_threads_carry_worker_or_configured_names() {
+    let messenger = Messenger::new(common::ring(1 << 16))
+        .with_thread_config("Solo", ThreadConfig::new().name("single"))
+        .with_thread_config("Pool", ThreadConfig::new().name("crunch"))
+        .with_thread_config("Pool.2", ThreadConfig::new().name("last"));
+    let config = Config::default();
+    let handles = messenger.run(&config);
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    while config.names.lock().unwrap().len() < 5 {
+        assert!(std::time::Instant::now() < deadline, "workers did not start");
+        std::thread::yield_now();
+    }
+    messenger.stop();
+    handles.join();
+
+    let mut names = config.names.lock().unwrap().clone();
+    names.sort();
+    assert_eq!(names, ["Plain", "crunch.0", "crunch.1", "last.2", "single"]);
+}
+
+#[test]
+#[should_panic(expected = "no worker or replica named Pool.3")]
+fn thread_config_for_an_unknown_replica_panics() {
+    let _ = Messenger::new(common::ring(1 << 16)).with_thread_config("Pool.3", ThreadConfig::new());
+}
+
+#[test]
+#[should_panic(expected = "no worker or replica named Plain.0")]
+fn thread_config_for_a_replica_of_a_single_worker_panics() {
+    let _ = Messenger::new(common::ring(1 << 16)).with_thread_config("Plain.0", ThreadConfig::new());
+}
@@ -0,0 +1,142 @@
+//! Timers scheduled by a handler are published when a virtual clock reaches
+//! them under simulation, and stop once cancelled. The timers of a handler
+//! instance are published under the instance's source id.
+
+use rust_messenger::clock;
+use rust_messenger::timer;
+
+#[derive(Clone)]
+pub struct Config {
+    clock: clock::VirtualClock,
+}
+
+rust_messenger::messenger_id_enum!(
+    HandlerId {
+        Heart = 1,
+    }
+);
+
+rust_messenger::messenger_id_enum!(
+    MessageId {
+        Beat = 1,
+        Deadline = 2,
+    }
+);
+
+macro_rules! impl_empty_message {
+    ($type:ident, $id:expr) => {
+        pub struct $type;
+
+        impl traits::core::Message for $type {
+            type Id = MessageId;
+            const ID: MessageId = $id;
+        }
+
+        impl $type {
+            pub fn deserialize_from(_buffer: &[u8]) -> Self {
+                $type
+            }
+        }
+
+        impl traits::extended::ExtendedMessage for $type {
+            fn get_size(&self) -> usize {
+                0
+            }
+            fn write_into(&self, _buffer: &mut [u8]) {}
+        }
+    };
+}
+
+impl_empty_message!(Beat, MessageId::Beat);
+impl_empty_message!(Deadline, MessageId::Deadline);
+
+pub struct Heart {
+    clock: clock::VirtualClock,
+    timers: timer::Timers,
+    heartbeat: Option<timer::TimerHandle>,
+    pub beats: Vec<std::time::Duration>,
+    pub deadlines: Vec<std::time::Duration>,
+}
+
+impl Heart {
+    pub fn new<W: traits::core::Writer>(config: &Config, _writer: &W) -> Self {
+        Heart {
+            clock: config.clock.clone(),
+            timers: timer::Timers::with_clock(std::sync::Arc::new(config.clock.clone())),
+            heartbeat: None,
+            beats: Vec::new(),
+            deadlines: Vec::new(),
+        }
+    }
+}
+
+impl traits::core::Handler for Heart {
+    type Id = HandlerId;
+    const ID: HandlerId = HandlerId::Heart;
+
+    fn on_start<W: traits::core::Writer>(&mut self, _writer: &W) {
+        let every = std::time::Duration::from_millis(100);
+        self.heartbeat = Some(self.timers.every::<Self, _>(every, &Beat));
+        self.timers.after::<Self, _>(std::time::Duration::from_millis(250), &Deadline);
+    }
+
+    fn on_loop<W: traits::core::Writer>(&mut self, writer: &W) {
+        self.timers.fire(writer);
+    }
+}
+
+impl traits::core::Handle<Beat> for Heart {
+    fn handle<W: traits::core::Writer>(&mut self, _beat: &Beat, _writer: &W) {
+        self.beats.push(self.clock.elapsed());
+        if self.beats.len() == 5 {
+            assert!(self.timers.cancel(self.heartbeat.unwrap()));
+        }
+    }
+}
+
+impl traits::core::Handle<Deadline> for Heart {
+    fn handle<W: traits::core::Writer>(&mut self, _deadline: &Deadline, _writer: &W) {
+        self.deadlines.push(self.clock.elapsed());
+    }
+}
+
+rust_messenger::Messenger! {
+    Config,
+    Pulse:
+        handlers: [
+            heart: Heart,
+            spare @ 20: Heart,
+        ]
+        routes: [
+            Heart, Beat: [ heart ],
+            Heart, Deadline: [ heart ],
+            spare, Beat: [ spare ],
+            spare, Deadline: [ spare ],
+        ]
+}
+
+#[test]
+fn timers_follow_the_virtual_clock() {
+    struct BusConfig;
+    impl rust_messenger::message_bus::atomic_circular_bus::Config for BusConfig {
+        fn get_buffer_size(&self) -> usize {
+            1 << 14
+        }
+    }
+    let bus = rust_messenger::message_bus::atomic_circular_bus::CircularBus::new(&BusConfig);
+    let config = Config {
+        clock: clock::VirtualClock::new(),
+    };
+    let mut simulation = Messenger::new(bus)
+        .simulation(&config, 3)
+        .with_clock(config.clock.clone(), std::time::Duration::from_millis(10));
+    simulation.run_for(std::time::Duration::from_secs(1));
+
+    let pulse = simulation.worker::<Pulse>("Pulse").unwrap();
+    let ms = std::time::Duration::from_millis;
+    for heart in [&pulse.heart, pulse.spare.inner()] {
+        assert_eq!(heart.beats, [ms(100), ms(200), ms(300), ms(400), ms(500)]);
+        assert_eq!(heart.deadlines, [ms(250)]);
+        assert!(heart.timers.is_empty());
+    }
+}
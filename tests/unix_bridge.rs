@@ -0,0 +1,123 @@
+//! On one host, a shared `CircularBus` handed over a Unix socket is the same
+//! ring in both mappings, and Unix sockets bridge private buses like TCP.
+
+#![cfg(target_os = "linux")]
+
+use rust_messenger::bridge::{unix, Filter};
+use rust_messenger::cursor::Cursor;
+use rust_messenger::message_bus::atomic_circular_bus::{CircularBus, Config};
+use rust_messenger::traits::core::{RawWriter, Reader};
+
+const SENSOR: u16 = 7;
+const READING: u16 = 3;
+
+struct Cfg;
+
+impl Config for Cfg {
+    fn get_buffer_size(&self) -> usize {
+        1 << 16
+    }
+}
+
+fn socket_path(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("rust-messenger-{name}-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn publish(bus: &CircularBus, value: u32) {
+    bus.write_raw(SENSOR, READING, 4, |buffer| {
+        buffer[..4].copy_from_slice(&value.to_ne_bytes())
+    });
+}
+
+fn value_at(bus: &CircularBus, cursor: &mut Cursor) -> Option<u32> {
+    let (header, payload) = cursor.next(bus).unwrap()?;
+    assert_eq!((header.source, header.message_id), (SENSOR, READING));
+    Some(u32::from_ne_bytes(payload[..4].try_into().unwrap()))
+}
+
+#[test]
+fn attached_bus_shares_the_ring() {
+    let path = socket_path("share");
+    let bus = CircularBus::shared(&Cfg).unwrap();
+    let server = unix::share(&path, &bus).unwrap();
+
+    let attached = unix::attach(&path).unwrap();
+    let again = unix::attach(&path).unwrap();
+    assert_eq!(server.connections(), 2);
+
+    publish(&bus, 1);
+    publish(&attached, 2);
+    publish(&again, 3);
+    let mut cursor = Cursor::new();
+    for reader in [&bus, &attached, &again] {
+        cursor.seek(0);
+        let values: Vec<_> = std::iter::from_fn(|| value_at(reader, &mut cursor)).collect();
+        assert_eq!(values, [1, 2, 3]);
+    }
+
+    // The bus outlives the server and the creating process's handle.
+    server.stop();
+    assert!(!path.exists());
+    drop(bus);
+    assert!(attached.read(0).is_some());
+}
+
+#[test]
+fn failed_shares_are_reported_on_the_handle() {
+    let path = socket_path("gone");
+    let bus = CircularBus::shared(&Cfg).unwrap();
+    let server = unix::share(&path, &bus).unwrap();
+
+    // The client hangs up before the server hands it the bus.
+    drop(std::os::unix::net::UnixStream::connect(&path).unwrap());
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    while server.failed() == 0 {
+        assert!(std::time::Instant::now() < deadline, "the failed share was not reported");
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+    let errors = server.take_errors();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].peer.as_deref(), Some(path.to_str().unwrap()));
+    assert!(server.take_errors().is_empty(), "taken once");
+
+    unix::attach(&path).unwrap();
+    assert_eq!((server.connections(), server.failed()), (2, 1));
+}
+
+#[test]
+fn only_shared_buses_can_be_shared() {
+    let path = socket_path("private");
+    let error = unix::share(&path, &CircularBus::new(&Cfg)).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(!path.exists());
+}
+
+#[test]
+fn unix_sockets_bridge_private_buses() {
+    let path = socket_path("forward");
+    let local = CircularBus::new(&Cfg);
+    let remote = CircularBus::new(&Cfg);
+    let mut cursor = Cursor::new();
+
+    let inbound = unix::UnixReceiver::bind(&path).unwrap().spawn(remote.clone());
+    let outbound = unix::UnixForwarder::new(&path, Filter::all()).spawn(local.clone());
+    for value in 0..10 {
+        publish(&local, value);
+    }
+
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+    let mut received = Vec::new();
+    while received.len() < 10 {
+        assert!(std::time::Instant::now() < deadline, "received only {received:?}");
+        match value_at(&remote, &mut cursor) {
+            Some(value) => received.push(value),
+            None => std::thread::sleep(std::time::Duration::from_millis(1)),
+        }
+    }
+    assert_eq!(received, (0..10).collect::<Vec<_>>());
+    outbound.stop();
+    inbound.stop();
+    assert!(!path.exists());
+}